@@ -80,22 +80,22 @@ async fn initialize_market_data() -> Result<RawMarketCache, Box<dyn Error>> {
     let bond_yield_20y = match fetch_20y_bond_yield().await {
         Ok(rate) => {
             info!("Successfully fetched 20y bond yield: {}", rate);
-            rate
+            Some(rate)
         },
         Err(e) => {
             error!("Failed to fetch 20y bond yield: {}", e);
-            0.0
+            None
         }
     };
 
     let tips_yield_20y = match fetch_20y_tips_yield().await {
         Ok(rate) => {
             info!("Successfully fetched 20y TIPS yield: {}", rate);
-            rate
+            Some(rate)
         },
         Err(e) => {
             error!("Failed to fetch 20y TIPS yield: {}", e);
-            0.0
+            None
         }
     };
 
@@ -128,6 +128,7 @@ async fn initialize_market_data() -> Result<RawMarketCache, Box<dyn Error>> {
         timestamp_ycharts: now.clone(),
         timestamp_treasury: now.clone(),
         timestamp_bls: now.clone(),
+        timestamp_market_price: now.clone(),
         daily_close_sp500_price: 0.0,
         current_sp500_price: 0.0,
         current_cape: init_data["cape"]["value"].as_f64().unwrap_or(0.0),
@@ -138,6 +139,12 @@ async fn initialize_market_data() -> Result<RawMarketCache, Box<dyn Error>> {
         inflation_rate,
         latest_monthly_return,
         latest_month,
+        last_year_close_captured: 0,
+        // Left empty so the first request fetches GDP from FRED
+        gdp: 0.0,
+        gdp_period: String::new(),
+        timestamp_gdp: String::new(),
+        price_source: String::new(),
     })
 }
 
@@ -0,0 +1,204 @@
+// src/bin/seed_local.rs
+// Writes a synthetic dataset to a local JSON store so the API can run without Google credentials.
+// Run with: cargo run --bin seed_local -- [--path local_store.json] [--years 10] [--force]
+// Then start the server with LOCAL_STORE_PATH pointing at the same file.
+
+use std::env;
+use std::error::Error;
+use std::path::Path;
+use chrono::{Datelike, Utc};
+use dotenv::dotenv;
+use log::info;
+
+use macro_dashboard_acm::models::{HistoricalRecord, MonthlyData, QuarterlyData};
+use macro_dashboard_acm::services::{
+    local_store::{LocalSnapshot, LocalStore},
+    sheets::RawMarketCache,
+};
+
+const DEFAULT_PATH: &str = "local_store.json";
+const DEFAULT_YEARS: i32 = 10;
+
+// Synthetic market shape: ending near 5200, ~7% price growth, ~1.6% dividend yield, P/E around 22
+const LATEST_PRICE: f64 = 5200.0;
+const PRICE_GROWTH: f64 = 0.07;
+const DIVIDEND_YIELD: f64 = 0.016;
+const PE_RATIO: f64 = 22.0;
+
+struct SeedOptions {
+    path: String,
+    years: i32,
+    force: bool,
+}
+
+fn parse_args() -> Result<SeedOptions, Box<dyn Error>> {
+    let mut options = SeedOptions {
+        path: env::var("LOCAL_STORE_PATH").unwrap_or_else(|_| DEFAULT_PATH.to_string()),
+        years: DEFAULT_YEARS,
+        force: false,
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--path" => options.path = args.next().ok_or("--path needs a value")?,
+            "--years" => {
+                options.years = args.next().ok_or("--years needs a value")?.parse()?;
+                if options.years < 1 {
+                    return Err("--years must be at least 1".into());
+                }
+            }
+            "--force" => options.force = true,
+            other => return Err(format!("Unknown argument: {}", other).into()),
+        }
+    }
+
+    Ok(options)
+}
+
+/// Deterministic jitter in [-0.5, 0.5) so the data isn't a perfectly straight line
+fn wobble(i: i32) -> f64 {
+    (i.wrapping_mul(7919).rem_euclid(13)) as f64 / 13.0 - 0.5
+}
+
+fn historical_records(first_year: i32, last_year: i32) -> Vec<HistoricalRecord> {
+    let mut records = Vec::new();
+    let mut price = LATEST_PRICE / (1.0 + PRICE_GROWTH).powi(last_year - first_year + 1);
+    let mut wealth = 1.0;
+    let mut inflation_index = 1.0;
+
+    for (i, year) in (first_year..=last_year).enumerate() {
+        let i = i as i32;
+        let price_change = PRICE_GROWTH + 0.15 * wobble(i);
+        let previous_price = price;
+        price *= 1.0 + price_change;
+
+        let dividend = price * DIVIDEND_YIELD;
+        let total_return = price / previous_price - 1.0 + dividend / previous_price;
+        wealth *= 1.0 + total_return;
+        inflation_index *= 1.0 + 0.025 + 0.02 * wobble(i + 7);
+
+        records.push(HistoricalRecord {
+            year,
+            sp500_price: round2(price),
+            dividend: round2(dividend),
+            dividend_yield: dividend / price,
+            eps: round2(price / (PE_RATIO + 4.0 * wobble(i + 3))),
+            cape: round2(28.0 + 6.0 * wobble(i + 5)),
+            // Price index level, like the sheet's inflation column
+            inflation: inflation_index,
+            total_return,
+            // Cumulative return since the first year, like the sheet (0.5 == +50%)
+            cumulative_return: wealth - 1.0,
+            total_return_approximated: false,
+        });
+    }
+
+    records
+}
+
+/// Eight quarters of actuals ending last quarter, plus four quarters of estimates
+fn quarterly_data(current_year: i32, current_quarter: u32, latest_price: f64) -> Vec<QuarterlyData> {
+    let mut data = Vec::new();
+    let quarterly_eps = latest_price / PE_RATIO / 4.0;
+    let quarterly_dividend = latest_price * DIVIDEND_YIELD / 4.0;
+
+    // Quarters counted back from the current one (exclusive) and forward (inclusive)
+    for offset in -8..4 {
+        let index = current_year * 4 + current_quarter as i32 - 1 + offset;
+        let (year, quarter) = (index.div_euclid(4), index.rem_euclid(4) + 1);
+        let growth = 1.0 + 0.015 * offset as f64;
+
+        let is_actual = offset < 0;
+        data.push(QuarterlyData {
+            quarter: format!("{}Q{}", year, quarter),
+            dividend: is_actual.then(|| round2(quarterly_dividend * growth)),
+            eps_actual: is_actual.then(|| round2(quarterly_eps * growth * (1.0 + 0.1 * wobble(offset)))),
+            eps_estimated: Some(round2(quarterly_eps * growth)),
+        });
+    }
+
+    data
+}
+
+/// Two years of monthly total returns ending last month
+fn monthly_data(current_year: i32, current_month: u32) -> Vec<MonthlyData> {
+    let current_index = current_year * 12 + current_month as i32 - 1;
+    ((current_index - 24)..current_index)
+        .map(|index| MonthlyData {
+            month: format!("{}-{:02}", index.div_euclid(12), index.rem_euclid(12) + 1),
+            total_return: 0.008 + 0.06 * wobble(index),
+        })
+        .collect()
+}
+
+fn market_cache(latest_price: f64, latest_month: &MonthlyData) -> RawMarketCache {
+    let now = Utc::now().to_rfc3339();
+
+    RawMarketCache {
+        timestamp_yahoo: now.clone(),
+        timestamp_ycharts: now.clone(),
+        timestamp_treasury: now.clone(),
+        timestamp_bls: now.clone(),
+        timestamp_market_price: now.clone(),
+        daily_close_sp500_price: round2(latest_price),
+        current_sp500_price: round2(latest_price * 1.002),
+        current_cape: 31.5,
+        cape_period: latest_month.month.clone(),
+        tips_yield_20y: Some(0.021),
+        bond_yield_20y: Some(0.046),
+        tbill_yield: 0.043,
+        inflation_rate: 0.029,
+        latest_monthly_return: latest_month.total_return,
+        latest_month: latest_month.month.clone(),
+        last_year_close_captured: Utc::now().year() - 1,
+        gdp: 29000.0,
+        gdp_period: format!("{}-01-01", Utc::now().year()),
+        timestamp_gdp: now,
+        price_source: "yahoo".to_string(),
+    }
+}
+
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    dotenv().ok();
+    env_logger::init();
+
+    let options = parse_args()?;
+    if Path::new(&options.path).exists() && !options.force {
+        return Err(format!("{} already exists; pass --force to overwrite it", options.path).into());
+    }
+
+    let today = Utc::now().date_naive();
+    let current_year = today.year();
+    let current_quarter = (today.month() - 1) / 3 + 1;
+
+    let historical_data = historical_records(current_year - options.years, current_year - 1);
+    let latest_price = historical_data.last().map(|r| r.sp500_price).unwrap_or(LATEST_PRICE);
+    let monthly_data = monthly_data(current_year, today.month());
+    let latest_month = monthly_data.last().ok_or("No monthly data generated")?;
+
+    let snapshot = LocalSnapshot {
+        market_cache: Some(market_cache(latest_price, latest_month)),
+        quarterly_data: quarterly_data(current_year, current_quarter, latest_price),
+        historical_data,
+        monthly_data,
+    };
+
+    LocalStore::new(&options.path).save(&snapshot).await?;
+
+    info!(
+        "Seeded {} with {} historical years, {} quarters and {} months",
+        options.path,
+        snapshot.historical_data.len(),
+        snapshot.quarterly_data.len(),
+        snapshot.monthly_data.len()
+    );
+    println!("Wrote {}. Start the server with LOCAL_STORE_PATH={}", options.path, options.path);
+
+    Ok(())
+}
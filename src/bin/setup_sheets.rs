@@ -1,11 +1,10 @@
 //src/bin/setup_sheets.rs
 use dotenv::dotenv;
 use log::{info, error};
-use macro_dashboard_acm::models::HistoricalRecord;
 use serde_json::{Value, json};
 use std::{error::Error, fs::File};
 use std::env;
-use macro_dashboard_acm::services::sheets::{SheetsStore, SheetsConfig};
+use macro_dashboard_acm::services::sheets::{parse_stk_mkt_csv, SheetsStore, SheetsConfig, SHEET_SCHEMAS};
 
 
 async fn verify_spreadsheet_access(store: &SheetsStore) -> Result<(), Box<dyn Error>> {
@@ -167,100 +166,17 @@ async fn main() -> Result<(), Box<dyn Error>> {
     // First verify we can access the spreadsheet
     verify_spreadsheet_access(&store).await?;
 
-    // Setup sheets with headers
-    let sheets_to_create = [
-        ("MarketCache", vec![
-            "timestamp_yahoo",
-            "timestamp_ycharts",
-            "timestamp_treasury",
-            "timestamp_bls",
-            "daily_close_sp500_price",
-            "current_sp500_price",
-            "current_cape",
-            "cape_period",
-            "tips_yield_20y",
-            "bond_yield_20y",
-            "tbill_yield",
-            "inflation_rate",
-            "latest_monthly_return",
-            "latest_return_month"
-        ]),
-        ("QuarterlyData", vec![
-            "quarter",
-            "dividend",
-            "eps_actual",
-            "eps_estimated"
-        ]),
-        ("HistoricalData", vec![
-            "year",
-            "sp500_price",
-            "dividend",
-            "dividend_yield",
-            "eps",
-            "cape",
-            "inflation",
-            "total_return",
-            "cumulative_return"
-        ])
-    ];
-
-    for (sheet_name, headers) in sheets_to_create.iter() {
-        create_sheet_if_not_exists(&store, sheet_name, headers.clone()).await?;
+    // Setup sheets with the headers the store expects
+    for (sheet_name, headers) in SHEET_SCHEMAS {
+        create_sheet_if_not_exists(&store, sheet_name, headers.to_vec()).await?;
     }
-    create_sheet_if_not_exists(&store, "MonthlyData", vec![
-        "month",
-        "total_return"
-    ]).await?;
 
     // Load and upload historical data
     info!("Loading historical data from CSV...");
-    // Helper function to safely parse float values - fixed signature to use &str
-    fn parse_float(s: &str, field: &str) -> Result<f64, Box<dyn std::error::Error>> {
-        let trimmed = s.trim();
-        if trimmed.is_empty() {
-            return Ok(0.0); // or whatever default value makes sense
-        }
-        trimmed.parse::<f64>().map_err(|e| {
-            format!("Error parsing {} value '{}': {}", field, trimmed, e).into()
-        })
-    }
-
     let file = File::open("data/stk_mkt.csv")?;
-    let mut rdr = csv::ReaderBuilder::new()
-        .trim(csv::Trim::All)
-        .flexible(true)
-        .from_reader(file);
-
-    let mut row_number = 0;
-    let mut historical_records = Vec::new();
-
-    for result in rdr.records() {
-        row_number += 1;
-        let record = result?;
-        
-        // Skip header row
-        if &record[0] == "Year" {
-            continue;
-        }
-
-        // Log the current row being processed
-        info!("Processing row {}: {:?}", row_number, record);
-
-        let record_attempt = HistoricalRecord {
-            year: record[0].trim().parse().map_err(|e| {
-                format!("Error parsing year '{}': {}", &record[0], e)
-            })?,
-            sp500_price: parse_float(&record[1], "SP500 price")?,
-            dividend: parse_float(&record[2], "dividend")?,
-            dividend_yield: parse_float(&record[3], "dividend yield")?,
-            eps: parse_float(&record[4], "EPS")?,
-            cape: parse_float(&record[5], "CAPE")?,
-            inflation: parse_float(&record[6], "inflation")?,
-            total_return: parse_float(&record[7], "total return")?,
-            cumulative_return: parse_float(&record[8], "cumulative return")?,
-        };
-
-        historical_records.push(record_attempt);
+    let historical_records = parse_stk_mkt_csv(file)?;
+    for (row_number, record) in historical_records.iter().enumerate() {
+        info!("Parsed row {}: {:?}", row_number + 1, record);
     }
 
     info!("Successfully parsed {} records", historical_records.len());
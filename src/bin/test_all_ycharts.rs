@@ -2,7 +2,6 @@
 // Run with: cargo run --bin test_all_ycharts
 
 use dotenv::dotenv;
-use env_logger;
 use log::{info, error};
 use std::error::Error;
 use scraper::{Html, Selector};
@@ -99,7 +98,7 @@ async fn fetch_ycharts_value_original(url: &str) -> Result<(String, f64), Box<dy
     let stat = document.select(&value_selector)
         .next()
         .and_then(|el| el.text().next())
-        .ok_or_else(|| "Failed to find stat")?
+        .ok_or("Failed to find stat")?
         .trim();
     
     info!("Original function found stat text: {}", stat);
@@ -159,7 +158,7 @@ async fn fetch_ycharts_value_improved(url: &str) -> Result<(String, f64), Box<dy
     let stat = document.select(&value_selector)
         .next()
         .and_then(|el| el.text().next())
-        .ok_or_else(|| "Failed to find stat")?
+        .ok_or("Failed to find stat")?
         .trim();
     
     info!("Improved function found stat text: {}", stat);
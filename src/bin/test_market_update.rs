@@ -0,0 +1,37 @@
+// src/bin/test_market_update.rs
+// Forces a market data refresh and prints what it changed.
+// Run with: cargo run --bin test_market_update -- [price,fundamentals,daily_close]
+// Uses LOCAL_STORE_PATH when set, otherwise GOOGLE_SHEETS_ID and SERVICE_ACCOUNT_JSON.
+use std::env;
+use std::sync::Arc;
+use dotenv::dotenv;
+use macro_dashboard_acm::services::db::{market_cache_ttl_from_env, DbStore};
+use macro_dashboard_acm::services::equity::{force_refresh, RefreshPart};
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    dotenv().ok();
+    env_logger::init();
+
+    let parts = match env::args().nth(1) {
+        Some(list) => list.split(',')
+            .map(|name| RefreshPart::from_name(name.trim())
+                .ok_or_else(|| anyhow::anyhow!("Unknown refresh part '{}'", name)))
+            .collect::<anyhow::Result<Vec<_>>>()?,
+        None => RefreshPart::ALL.to_vec(),
+    };
+
+    let db = match env::var("LOCAL_STORE_PATH") {
+        Ok(path) => DbStore::new_local(&path),
+        Err(_) => DbStore::new(
+            &env::var("GOOGLE_SHEETS_ID")?,
+            &env::var("SERVICE_ACCOUNT_JSON")?,
+            market_cache_ttl_from_env(),
+        ).await?,
+    };
+
+    let (report, data) = force_refresh(&Arc::new(db), &parts).await?;
+    println!("Refresh report:\n{}", serde_json::to_string_pretty(&report)?);
+    println!("Market data:\n{}", serde_json::to_string_pretty(&data)?);
+    Ok(())
+}
@@ -0,0 +1,129 @@
+// src/handlers/admin.rs
+use warp::reply::Json;
+use warp::Rejection;
+use serde::Deserialize;
+use log::{error, info};
+use std::sync::Arc;
+use serde_json::json;
+use crate::handlers::error::ApiError;
+use crate::services::db::DbStore;
+use crate::services::equity::{
+    fetch_ycharts_value_raw, force_refresh, refresh_market_data_with_report, ycharts_indicator_url, RefreshPart, YCHARTS_INDICATORS,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct YChartsRawQuery {
+    pub indicator: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshQuery {
+    /// Comma-separated refresh portions to force, e.g. `price,fundamentals,daily_close`
+    pub include: Option<String>,
+    /// Wrap the unforced refresh response as `{report, market_data}`; forced refreshes always include the report
+    #[serde(default)]
+    pub report: bool,
+}
+
+/// Parse an `include` list into refresh parts, rejecting unknown names and dropping duplicates
+fn parse_refresh_parts(include: &str) -> Result<Vec<RefreshPart>, String> {
+    let mut parts = Vec::new();
+    for name in include.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let part = RefreshPart::from_name(name).ok_or_else(|| {
+            let known: Vec<&str> = RefreshPart::ALL.iter().map(|p| p.name()).collect();
+            format!("Unknown refresh part '{}', expected one of: {}", name, known.join(", "))
+        })?;
+        if !parts.contains(&part) {
+            parts.push(part);
+        }
+    }
+
+    if parts.is_empty() {
+        return Err("include must name at least one refresh part".to_string());
+    }
+    Ok(parts)
+}
+
+/// Scrape a single YCharts indicator and return the raw stat text alongside the parsed value.
+/// Nothing is written to the cache or sheets.
+pub async fn get_ycharts_raw(query: YChartsRawQuery) -> Result<Json, Rejection> {
+    let url = match ycharts_indicator_url(&query.indicator) {
+        Some(url) => url,
+        None => {
+            let known: Vec<&str> = YCHARTS_INDICATORS.iter().map(|(name, _)| *name).collect();
+            return Err(warp::reject::custom(ApiError::parse_error(format!(
+                "Unknown indicator '{}', expected one of: {}",
+                query.indicator,
+                known.join(", ")
+            ))));
+        }
+    };
+
+    match fetch_ycharts_value_raw(url).await {
+        Ok(raw) => {
+            info!("Fetched raw YCharts stat for {}: {}", query.indicator, raw.raw_text);
+            Ok(warp::reply::json(&raw))
+        }
+        Err(e) => {
+            error!("Failed to fetch raw YCharts stat for {}: {}", query.indicator, e);
+            Err(warp::reject::custom(ApiError::external_error(e.to_string())))
+        }
+    }
+}
+
+/// Clear the in-memory market cache and historical series, and re-read the cache from Sheets
+pub async fn invalidate_cache(db: Arc<DbStore>) -> Result<Json, Rejection> {
+    db.invalidate_market_cache().await;
+    db.invalidate_historical_data().await;
+    info!("In-memory market cache and historical data invalidated");
+
+    match db.get_market_cache().await {
+        Ok(cache) => Ok(warp::reply::json(&json!({
+            "invalidated": true,
+            "timestamps": cache.timestamps,
+        }))),
+        Err(e) => {
+            error!("Failed to re-read market cache after invalidation: {}", e);
+            Err(warp::reject::custom(ApiError::database_error(e.to_string())))
+        }
+    }
+}
+
+/// Run a full market data refresh now, including the daily close and fundamentals, or just the
+/// `include`d parts. Either way the refresh runs under the shared single flight, so a double
+/// click joins the refresh already in flight instead of scraping and writing twice.
+pub async fn refresh(query: RefreshQuery, db: Arc<DbStore>) -> Result<Json, Rejection> {
+    if let Some(include) = query.include {
+        let parts = parse_refresh_parts(&include)
+            .map_err(|e| warp::reject::custom(ApiError::parse_error(e)))?;
+        info!("Admin-triggered forced refresh of {:?}", parts);
+
+        return match force_refresh(&db, &parts).await {
+            Ok((report, data)) => Ok(warp::reply::json(&json!({
+                "requested": parts.iter().map(|p| p.name()).collect::<Vec<_>>(),
+                "updated": report.updated.iter().map(|p| p.name()).collect::<Vec<_>>(),
+                "report": report,
+                "market_data": data,
+            }))),
+            Err(e) => {
+                error!("Admin forced refresh failed: {}", e);
+                Err(warp::reject::custom(ApiError::database_error(e.to_string())))
+            }
+        };
+    }
+
+    info!("Admin-triggered market data refresh");
+
+    // The admin panel refreshes on demand, so skip the daily update time gate
+    match refresh_market_data_with_report(&db, true).await {
+        Ok((data, report)) if query.report => Ok(warp::reply::json(&json!({
+            "report": report,
+            "market_data": data,
+        }))),
+        Ok((data, _)) => Ok(warp::reply::json(&data)),
+        Err(e) => {
+            error!("Admin refresh failed: {}", e);
+            Err(warp::reject::custom(ApiError::database_error(e.to_string())))
+        }
+    }
+}
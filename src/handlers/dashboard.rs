@@ -0,0 +1,94 @@
+// src/handlers/dashboard.rs
+use warp::reply::with_status;
+use warp::Rejection;
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use crate::models::MarketCache;
+use crate::services::db::DbStore;
+use crate::services::rates::{fetch_concurrency, refresh_sources, RateSource};
+use super::envelope::{
+    self, rates_max_age, EnvelopeQuery, Meta, SOURCE_BLS, SOURCE_MARKET_CACHE, SOURCE_TREASURY, SOURCE_YAHOO, SOURCE_YCHARTS,
+};
+use super::error::ApiError;
+use log::{info, error};
+
+
+#[derive(Debug, Deserialize)]
+pub struct DashboardQuery {
+    /// Wrap each value as `{value, source, as_of}` (default) or return bare values
+    pub attribution: Option<bool>,
+}
+
+/// A cached value annotated with where it came from and when it was fetched
+#[derive(Debug, Serialize)]
+pub struct SourcedValue<T> {
+    pub value: T,
+    pub source: &'static str,
+    pub as_of: DateTime<Utc>,
+}
+
+fn sourced<T: Serialize>(value: T, source: &'static str, as_of: DateTime<Utc>) -> Value {
+    json!(SourcedValue { value, source, as_of })
+}
+
+/// Every dashboard field paired with its source and the cache timestamp for that source
+fn dashboard_fields(cache: &MarketCache) -> Vec<(&'static str, Value)> {
+    let ts = &cache.timestamps;
+    vec![
+        ("current_sp500_price", sourced(cache.current_sp500_price, SOURCE_YAHOO, ts.yahoo_price)),
+        ("daily_close_sp500_price", sourced(cache.daily_close_sp500_price, SOURCE_YAHOO, ts.yahoo_price)),
+        ("cape", sourced(cache.current_cape, SOURCE_YCHARTS, ts.ycharts_data)),
+        ("cape_period", sourced(cache.cape_period.clone(), SOURCE_YCHARTS, ts.ycharts_data)),
+        ("latest_monthly_return", sourced(cache.latest_monthly_return, SOURCE_YCHARTS, ts.ycharts_data)),
+        ("latest_month", sourced(cache.latest_month.clone(), SOURCE_YCHARTS, ts.ycharts_data)),
+        ("tbill_yield", sourced(cache.tbill_yield, SOURCE_TREASURY, ts.treasury_data)),
+        ("bond_yield_20y", sourced(cache.bond_yield_20y, SOURCE_TREASURY, ts.treasury_data)),
+        ("tips_yield_20y", sourced(cache.tips_yield_20y, SOURCE_TREASURY, ts.treasury_data)),
+        ("inflation_rate", sourced(cache.inflation_rate, SOURCE_BLS, ts.bls_data)),
+    ]
+}
+
+pub async fn get_dashboard(query: DashboardQuery, envelope: EnvelopeQuery, db: Arc<DbStore>) -> Result<impl warp::Reply, Rejection> {
+    info!("Handling request to get dashboard data");
+
+    let mut cache = match db.get_market_cache().await {
+        Ok(cache) => cache,
+        Err(e) => {
+            error!("Failed to get market cache: {:?}", e);
+            return Err(warp::reject::custom(ApiError::database_error(e.to_string())));
+        }
+    };
+
+    // On a cold cache, refresh the stale rates with a bounded number of upstream fetches in flight
+    let stale = RateSource::stale(&cache);
+    if db.handlers_may_write() && !stale.is_empty() {
+        info!("Refreshing {} stale rate(s) for dashboard", stale.len());
+        if refresh_sources(&mut cache, &stale, fetch_concurrency()).await {
+            if let Err(e) = db.update_market_cache(&cache).await {
+                error!("Failed to update market cache: {:?}", e);
+                return Err(warp::reject::custom(ApiError::database_error(e.to_string())));
+            }
+        }
+    }
+
+    let attribution = query.attribution.unwrap_or(true);
+    let body: Map<String, Value> = dashboard_fields(&cache)
+        .into_iter()
+        .map(|(name, field)| {
+            let field = if attribution { field } else { field["value"].clone() };
+            (name.to_string(), field)
+        })
+        .collect();
+
+    Ok(with_status(
+        envelope::reply(&body, &envelope, || {
+            // The oldest rate timestamp; stale when any rate is past its refresh interval
+            let ts = &cache.timestamps;
+            let as_of = ts.treasury_data.min(ts.bls_data);
+            Meta { stale: !RateSource::stale(&cache).is_empty(), ..Meta::cached(SOURCE_MARKET_CACHE, as_of, rates_max_age()) }
+        }),
+        warp::http::StatusCode::OK
+    ))
+}
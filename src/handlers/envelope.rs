@@ -0,0 +1,115 @@
+// src/handlers/envelope.rs
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use warp::reply::Json;
+
+pub const SOURCE_YAHOO: &str = "Yahoo Finance";
+pub const SOURCE_YCHARTS: &str = "YCharts";
+pub const SOURCE_TREASURY: &str = "U.S. Treasury";
+pub const SOURCE_BLS: &str = "BLS";
+pub const SOURCE_FRED: &str = "FRED";
+pub const SOURCE_HISTORICAL: &str = "Historical data";
+pub const SOURCE_TREASURY_BLS: &str = "U.S. Treasury, BLS";
+pub const SOURCE_MARKET_CACHE: &str = "Market cache";
+
+/// How long each cached source is considered fresh, matching the handlers' refresh intervals
+pub fn yahoo_max_age() -> Duration {
+    Duration::minutes(15)
+}
+
+pub fn rates_max_age() -> Duration {
+    Duration::hours(1)
+}
+
+pub fn ycharts_max_age() -> Duration {
+    Duration::days(1)
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct EnvelopeQuery {
+    /// Wrap the payload as `{data, meta}` instead of returning it bare
+    #[serde(default, deserialize_with = "flag")]
+    pub envelope: bool,
+}
+
+/// A query flag given as `true`/`false` or `1`/`0`
+fn flag<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    let raw = String::deserialize(deserializer)?;
+    match raw.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" => Ok(true),
+        "0" | "false" => Ok(false),
+        _ => Err(serde::de::Error::custom(format!("expected true, false, 1 or 0, got '{}'", raw))),
+    }
+}
+
+/// Where an enveloped payload came from and how fresh it is
+#[derive(Debug, Serialize)]
+pub struct Meta {
+    pub as_of: Option<DateTime<Utc>>,
+    pub source: &'static str,
+    pub stale: bool,
+}
+
+impl Meta {
+    /// Cached data fetched at `as_of`, stale once it's older than the source's refresh interval
+    pub fn cached(source: &'static str, as_of: DateTime<Utc>, max_age: Duration) -> Self {
+        Meta {
+            as_of: Some(as_of),
+            source,
+            stale: as_of < Utc::now() - max_age,
+        }
+    }
+
+    /// Data fetched from upstream for this request
+    pub fn live(source: &'static str) -> Self {
+        Meta { as_of: Some(Utc::now()), source, stale: false }
+    }
+
+    /// Data read from a stored sheet with no fetch timestamp of its own
+    pub fn stored(source: &'static str) -> Self {
+        Meta { as_of: None, source, stale: false }
+    }
+}
+
+#[derive(Serialize)]
+struct Envelope<'a, T> {
+    data: &'a T,
+    meta: Meta,
+}
+
+/// Reply with `payload` as-is, or wrapped in `{data, meta}` when the client asked for `?envelope=true`.
+/// `meta` is only built for enveloped responses.
+pub fn reply<T: Serialize>(payload: &T, query: &EnvelopeQuery, meta: impl FnOnce() -> Meta) -> Json {
+    if query.envelope {
+        warp::reply::json(&Envelope { data: payload, meta: meta() })
+    } else {
+        warp::reply::json(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn parse(query: &str) -> Result<EnvelopeQuery, warp::Rejection> {
+        warp::test::request()
+            .path(&format!("/?{}", query))
+            .filter(&warp::query::<EnvelopeQuery>())
+            .await
+    }
+
+    #[tokio::test]
+    async fn envelope_flag_accepts_words_and_digits() {
+        for (query, expected) in [
+            ("envelope=true", true),
+            ("envelope=1", true),
+            ("envelope=TRUE", true),
+            ("envelope=false", false),
+            ("envelope=0", false),
+            ("", false),
+        ] {
+            assert_eq!(parse(query).await.unwrap().envelope, expected, "{}", query);
+        }
+        assert!(parse("envelope=yes").await.is_err());
+    }
+}
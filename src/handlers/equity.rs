@@ -2,58 +2,464 @@
 use warp::reply::Json;
 use warp::Rejection;
 use crate::{handlers::error::ApiError, services::equity};
-use log::{error, info};
+use crate::handlers::envelope::{
+    self, ycharts_max_age, yahoo_max_age, EnvelopeQuery, Meta, SOURCE_FRED, SOURCE_HISTORICAL, SOURCE_YAHOO,
+    SOURCE_YCHARTS,
+};
+use crate::services::export::{historical_records_to_csv, CsvFormat};
+use crate::services::calculations::{calculate_pe_ratio, DEFAULT_CAGR_WINDOW_YEARS};
+use crate::services::equity::{MarketData, QuarterlyValue};
+use crate::models::QuarterlyData;
+use crate::services::{gdp, price_stream, signals};
+use futures_util::StreamExt;
+use std::convert::Infallible;
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use log::{error, info, warn};
 use std::sync::Arc;
 use crate::services::db::DbStore;
+use serde_json::json;
 
-pub async fn get_equity_data(db: Arc<DbStore>) -> Result<Json, Rejection> {
-    match equity::get_market_data(&db).await {
-        Ok(data) => {
-            info!("Successfully fetched market data");
-            Ok(warp::reply::json(&data))
-        }
-        Err(e) => {
-            error!("Failed to fetch market data: {}", e);
-            Err(warp::reject::not_found())
+#[derive(Debug, Deserialize)]
+pub struct EquityQuery {
+    /// Include forward-looking estimate fields (defaults to true)
+    pub include_estimates: Option<bool>,
+    /// Serve the cached values without triggering any fetch or write (defaults to false)
+    pub cached: Option<bool>,
+    /// Only count estimates for the current calendar quarter onward in `estimated_eps_sum`
+    /// (defaults to false)
+    pub drop_stale_estimates: Option<bool>,
+}
+
+/// Serialized shape of `/api/v1/equity`; estimate-derived fields can be left out entirely
+#[derive(Debug, Serialize)]
+struct EquityResponse<'a> {
+    daily_close_sp500_price: f64,
+    current_sp500_price: f64,
+    ttm_dividend: &'a Option<QuarterlyValue>,
+    ttm_dividend_yield: Option<f64>,
+    ttm_eps: &'a Option<QuarterlyValue>,
+    trailing_pe: Option<f64>,
+    earnings_yield: Option<f64>,
+    latest_eps_actual: &'a Option<QuarterlyValue>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    estimates: Option<EstimateFields<'a>>,
+    cape: f64,
+    cape_period: &'a str,
+    price_source: &'a str,
+    last_update: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+struct EstimateFields<'a> {
+    estimated_eps_sum: &'a Option<QuarterlyValue>,
+    forward_pe: Option<f64>,
+}
+
+impl<'a> EquityResponse<'a> {
+    /// `estimated_eps_sum` is normally `data.estimated_eps_sum`, but can be swapped for a filtered sum
+    fn new(data: &'a MarketData, estimated_eps_sum: Option<&'a Option<QuarterlyValue>>) -> Self {
+        let estimates = estimated_eps_sum.map(|sum| EstimateFields {
+            estimated_eps_sum: sum,
+            forward_pe: sum.as_ref()
+                .and_then(|eps| calculate_pe_ratio(data.current_sp500_price, eps.value)),
+        });
+
+        EquityResponse {
+            daily_close_sp500_price: data.daily_close_sp500_price,
+            current_sp500_price: data.current_sp500_price,
+            ttm_dividend: &data.ttm_dividend,
+            ttm_dividend_yield: data.ttm_dividend_yield,
+            ttm_eps: &data.ttm_eps,
+            trailing_pe: data.trailing_pe,
+            earnings_yield: data.earnings_yield,
+            latest_eps_actual: &data.latest_eps_actual,
+            estimates,
+            cape: data.cape,
+            cape_period: &data.cape_period,
+            price_source: &data.price_source,
+            last_update: data.last_update,
         }
     }
 }
 
-pub async fn get_equity_history(db: Arc<DbStore>) -> Result<Json, Rejection> {
-    match equity::get_historical_data(&db).await {
-        Ok(data) => {
-            info!("Successfully fetched historical data");
-            Ok(warp::reply::json(&data))
-        }
-        Err(e) => {
-            error!("Failed to fetch historical data: {}", e);
-            Err(warp::reject::not_found())
-        }
+/// Calendar quarter containing `now`, as `(year, quarter)`
+fn current_quarter(now: DateTime<Utc>) -> (i32, u32) {
+    (now.year(), (now.month() - 1) / 3 + 1)
+}
+
+/// Parse a `YYYYQn` quarter label into `(year, quarter)`
+pub(crate) fn parse_quarter_label(label: &str) -> Option<(i32, u32)> {
+    let (year, quarter) = label.split_once('Q')?;
+    let quarter: u32 = quarter.parse().ok()?;
+    (1..=4).contains(&quarter).then_some((year.parse().ok()?, quarter))
+}
+
+/// Sum of the next four estimated quarters, counting only quarters at or after `current`.
+/// Quarters that already have an actual are skipped, and the four must be consecutive;
+/// `None` if the sheet doesn't hold that many current estimates.
+fn current_estimated_eps_sum(quarters: &[QuarterlyData], current: (i32, u32)) -> Option<QuarterlyValue> {
+    let mut forward: Vec<((i32, u32), &QuarterlyData)> = quarters.iter()
+        .filter(|q| q.eps_actual.is_none() && q.eps_estimated.is_some())
+        .filter_map(|q| parse_quarter_label(&q.quarter).map(|key| (key, q)))
+        .filter(|(key, _)| *key >= current)
+        .collect();
+    forward.sort_by_key(|(key, _)| *key);
+
+    let window = forward.get(..4)?;
+    let consecutive = window.windows(2).all(|pair| {
+        let ((year, quarter), (next_year, next_quarter)) = (pair[0].0, pair[1].0);
+        next_year * 4 + next_quarter as i32 == year * 4 + quarter as i32 + 1
+    });
+    if !consecutive {
+        return None;
     }
+
+    Some(QuarterlyValue {
+        final_quarter: window[3].1.quarter.clone(),
+        value: window.iter().filter_map(|(_, q)| q.eps_estimated).sum(),
+        estimated: false,
+    })
 }
 
-pub async fn get_equity_history_range(start_year: i32, end_year: i32, db: Arc<DbStore>) -> Result<Json, Rejection> {
-    match equity::get_historical_data_range(&db, start_year, end_year).await {
-        Ok(data) => {
-            info!("Successfully fetched historical data range");
-            Ok(warp::reply::json(&data))
-        }
-        Err(e) => {
-            error!("Failed to fetch historical data range: {}", e);
-            Err(warp::reject::not_found())
+pub async fn get_equity_data(query: EquityQuery, envelope: EnvelopeQuery, db: Arc<DbStore>) -> Result<Json, Rejection> {
+    let cached_only = query.cached.unwrap_or(false) || !db.handlers_may_write();
+    let result = if cached_only {
+        equity::build_market_data_from_cache(&db).await
+    } else {
+        equity::refresh_market_data(&db, false).await
+    };
+
+    let data = result.map_err(ApiError::from)?;
+    info!("Successfully fetched market data");
+
+    let include_estimates = query.include_estimates.unwrap_or(true);
+    let current_only = if include_estimates && query.drop_stale_estimates.unwrap_or(false) {
+        let quarters = db.store.get_quarterly_data().await.map_err(ApiError::from)?;
+        Some(current_estimated_eps_sum(&quarters, current_quarter(Utc::now())))
+    } else {
+        None
+    };
+    let estimated_eps_sum = include_estimates
+        .then(|| current_only.as_ref().unwrap_or(&data.estimated_eps_sum));
+    Ok(envelope::reply(&EquityResponse::new(&data, estimated_eps_sum), &envelope, || {
+        Meta::cached(SOURCE_YCHARTS, data.last_update, ycharts_max_age())
+    }))
+}
+
+/// Every historical year, each with a `real_sp500_price` in the latest year's dollars
+pub async fn get_equity_history(envelope: EnvelopeQuery, db: Arc<DbStore>) -> Result<Json, Rejection> {
+    let data = equity::get_historical_data_with_real_price(&db).await.map_err(ApiError::from)?;
+    info!("Successfully fetched historical data");
+    Ok(envelope::reply(&data, &envelope, || Meta::stored(SOURCE_HISTORICAL)))
+}
+
+pub async fn get_equity_history_range(start_year: i32, end_year: i32, envelope: EnvelopeQuery, db: Arc<DbStore>) -> Result<Json, Rejection> {
+    let data = equity::get_historical_data_range(&db, start_year, end_year).await.map_err(ApiError::from)?;
+    info!("Successfully fetched historical data range");
+    Ok(envelope::reply(&data, &envelope, || Meta::stored(SOURCE_HISTORICAL)))
+}
+
+/// Earliest year a historical lookup accepts; the sheet's data starts well after this
+const MIN_HISTORICAL_YEAR: i32 = 1800;
+
+/// A single year's historical record, or 404 if the sheet has no row for it
+pub async fn get_equity_history_year(year: String, envelope: EnvelopeQuery, db: Arc<DbStore>) -> Result<Json, Rejection> {
+    let max_year = Utc::now().year() + 1;
+    let year = year.parse::<i32>()
+        .ok()
+        .filter(|y| (MIN_HISTORICAL_YEAR..=max_year).contains(y))
+        .ok_or_else(|| {
+            ApiError::parse_error(format!("Invalid year '{}', expected {}-{}", year, MIN_HISTORICAL_YEAR, max_year))
+        })?;
+
+    let record = db.get_historical_year(year).await.map_err(ApiError::from)?
+        .ok_or_else(|| ApiError::not_found(format!("No historical data for {}", year)))?;
+    info!("Successfully fetched historical data for {}", year);
+    Ok(envelope::reply(&record, &envelope, || Meta::stored(SOURCE_HISTORICAL)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MetricsQuery {
+    /// Years covered by the "current" CAGRs (default 10)
+    pub window: Option<i32>,
+}
+
+impl MetricsQuery {
+    fn window_years(&self) -> Result<i32, ApiError> {
+        match self.window {
+            None => Ok(DEFAULT_CAGR_WINDOW_YEARS),
+            Some(window) if window > 0 => Ok(window),
+            Some(window) => Err(ApiError::parse_error(format!("Invalid window '{}', expected a positive number of years", window))),
         }
     }
 }
 
-pub async fn get_market_metrics(db: Arc<DbStore>) -> Result<Json, Rejection> {
-    match equity::get_market_metrics(&db).await {
-        Ok(metrics) => {
-            info!("Successfully calculated market metrics");
-            Ok(warp::reply::json(&metrics))
+pub async fn get_market_metrics(query: MetricsQuery, envelope: EnvelopeQuery, db: Arc<DbStore>) -> Result<Json, Rejection> {
+    let window_years = query.window_years()?;
+    let metrics = equity::get_market_metrics(&db, window_years).await.map_err(ApiError::from)?;
+    info!("Successfully calculated market metrics");
+    Ok(envelope::reply(&metrics, &envelope, || Meta::stored(SOURCE_HISTORICAL)))
+}
+
+/// Market metrics computed over only the historical years `start_year` through `end_year`
+pub async fn get_market_metrics_range(
+    start_year: i32,
+    end_year: i32,
+    query: MetricsQuery,
+    envelope: EnvelopeQuery,
+    db: Arc<DbStore>,
+) -> Result<Json, Rejection> {
+    if start_year >= end_year {
+        return Err(ApiError::parse_error(format!("Start year {} must be before end year {}", start_year, end_year)).into());
+    }
+    let window_years = query.window_years()?;
+
+    let metrics = equity::get_market_metrics_range(&db, start_year, end_year, window_years)
+        .await
+        .map_err(ApiError::from)?;
+    info!("Successfully calculated market metrics for {}-{}", start_year, end_year);
+    Ok(envelope::reply(&metrics, &envelope, || Meta::stored(SOURCE_HISTORICAL)))
+}
+
+pub async fn get_avg_dividend_yield(envelope: EnvelopeQuery, db: Arc<DbStore>) -> Result<Json, Rejection> {
+    let average = equity::get_average_dividend_yield(&db).await.map_err(ApiError::from)?;
+    if average.avg_dividend_yield.is_none() {
+        info!("No historical years with a positive dividend yield to average");
+    }
+    Ok(envelope::reply(&average, &envelope, || Meta::stored(SOURCE_HISTORICAL)))
+}
+
+pub async fn get_real_returns(envelope: EnvelopeQuery, db: Arc<DbStore>) -> Result<Json, Rejection> {
+    let series = equity::get_real_returns(&db).await.map_err(ApiError::from)?;
+    info!("Successfully calculated real returns for {} years", series.len());
+    Ok(envelope::reply(&series, &envelope, || Meta::stored(SOURCE_HISTORICAL)))
+}
+
+pub async fn get_sharpe_like_ratio(envelope: EnvelopeQuery, db: Arc<DbStore>) -> Result<Json, Rejection> {
+    let ratio = equity::get_sharpe_like_ratio(&db).await.map_err(ApiError::from)?;
+    if ratio.sharpe_ratio.is_none() {
+        info!("Sharpe-like ratio unavailable ({} return years, risk-free rate {:?})", ratio.years, ratio.risk_free_rate);
+    }
+    Ok(envelope::reply(&ratio, &envelope, || Meta::stored(SOURCE_HISTORICAL)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MonthlyQuery {
+    /// Only return months in this year
+    pub year: Option<i32>,
+}
+
+/// Stored monthly total returns, oldest first; an empty array when nothing matches
+pub async fn get_monthly_returns(query: MonthlyQuery, envelope: EnvelopeQuery, db: Arc<DbStore>) -> Result<Json, Rejection> {
+    let months = equity::get_monthly_returns(&db, query.year).await.map_err(ApiError::from)?;
+    info!("Returning {} monthly returns", months.len());
+    Ok(envelope::reply(&months, &envelope, || Meta::stored(SOURCE_YCHARTS)))
+}
+
+/// Whether `month` is a `YYYY-MM` month, the format the MonthlyData sheet sorts by
+fn is_valid_month(month: &str) -> bool {
+    month.len() == 7 && NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d").is_ok()
+}
+
+/// Monthly total returns between two `YYYY-MM` months, inclusive
+pub async fn get_monthly_returns_range(start: String, end: String, envelope: EnvelopeQuery, db: Arc<DbStore>) -> Result<Json, Rejection> {
+    for month in [&start, &end] {
+        if !is_valid_month(month) {
+            return Err(ApiError::parse_error(format!("Invalid month '{}', expected YYYY-MM", month)).into());
         }
+    }
+
+    let months = equity::get_monthly_returns_range(&db, &start, &end).await.map_err(ApiError::from)?;
+    info!("Returning {} monthly returns from {} to {}", months.len(), start, end);
+    Ok(envelope::reply(&months, &envelope, || Meta::stored(SOURCE_YCHARTS)))
+}
+
+pub async fn get_latest_monthly_return(envelope: EnvelopeQuery, db: Arc<DbStore>) -> Result<Json, Rejection> {
+    let cache = db.get_market_cache().await.map_err(ApiError::from)?;
+
+    if cache.latest_month.is_empty() {
+        error!("No latest monthly return in cache");
+        return Err(ApiError::cache_error("No monthly return data available").into());
+    }
+
+    info!("Returning latest monthly return for {}", cache.latest_month);
+    let body = json!({
+        "month": cache.latest_month,
+        "return": cache.latest_monthly_return
+    });
+    Ok(envelope::reply(&body, &envelope, || {
+        Meta::cached(SOURCE_YCHARTS, cache.timestamps.ycharts_data, ycharts_max_age())
+    }))
+}
+
+/// Price-only view straight from the market cache, without reading the quarterly sheet
+pub async fn get_equity_price(envelope: EnvelopeQuery, db: Arc<DbStore>) -> Result<Json, Rejection> {
+    let cache = db.get_market_cache().await.map_err(ApiError::from)?;
+
+    let current = cache.current_sp500_price;
+    let close = cache.daily_close_sp500_price;
+    let (change, change_percent) = if current > 0.0 && close > 0.0 {
+        (Some(current - close), Some((current - close) / close))
+    } else {
+        (None, None)
+    };
+
+    let body = json!({
+        "current_sp500_price": current,
+        "daily_close_sp500_price": close,
+        "change": change,
+        "change_percent": change_percent,
+        "as_of": cache.timestamps.yahoo_price,
+    });
+    Ok(envelope::reply(&body, &envelope, || {
+        Meta::cached(SOURCE_YAHOO, cache.timestamps.yahoo_price, yahoo_max_age())
+    }))
+}
+
+/// Server-Sent Events stream of price updates, starting with the cached price
+pub async fn get_price_stream(db: Arc<DbStore>) -> Result<impl warp::Reply, Rejection> {
+    let updates = match price_stream::price_update_stream(db).await {
+        Ok(updates) => updates,
         Err(e) => {
-            error!("Failed to calculate market metrics: {}", e);
-            Err(warp::reject::custom(ApiError::database_error(e.to_string())))
+            warn!("Rejected price stream subscriber: {}", e);
+            return Err(ApiError::unavailable(e.to_string()).into());
+        }
+    };
+
+    let events = updates.map(|update| {
+        warp::sse::Event::default()
+            .event("price")
+            .json_data(&update)
+            .or_else(|e| Ok::<_, Infallible>(warp::sse::Event::default().event("error").data(e.to_string())))
+    });
+
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(events)))
+}
+
+pub async fn get_implied_return(envelope: EnvelopeQuery, db: Arc<DbStore>) -> Result<Json, Rejection> {
+    let implied = signals::get_implied_return(&db).await.map_err(ApiError::from)?;
+    info!("Calculated implied return: {:?}", implied.implied_return);
+    Ok(envelope::reply(&implied, &envelope, || Meta::stored(SOURCE_HISTORICAL)))
+}
+
+pub async fn get_market_cap_to_gdp(envelope: EnvelopeQuery, db: Arc<DbStore>) -> Result<Json, Rejection> {
+    let indicator = gdp::get_market_cap_to_gdp(&db).await.map_err(ApiError::from)?;
+    info!("Calculated market cap to GDP: {:?}", indicator.ratio);
+    Ok(envelope::reply(&indicator, &envelope, || {
+        Meta::cached(SOURCE_FRED, indicator.gdp_as_of, gdp::gdp_max_age())
+    }))
+}
+
+pub async fn get_drawdown_series(envelope: EnvelopeQuery, db: Arc<DbStore>) -> Result<Json, Rejection> {
+    let series = equity::get_drawdown_series(&db).await.map_err(ApiError::from)?;
+    info!("Successfully calculated drawdown series ({} years)", series.len());
+    Ok(envelope::reply(&series, &envelope, || Meta::stored(SOURCE_HISTORICAL)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CsvExportQuery {
+    /// `comma` for European-style `,` decimals with `;` delimiters; defaults to US formatting
+    pub decimal: Option<String>,
+}
+
+pub async fn get_equity_history_csv(query: CsvExportQuery, db: Arc<DbStore>) -> Result<impl warp::Reply, Rejection> {
+    let format = CsvFormat::from_decimal_param(query.decimal.as_deref()).ok_or_else(|| {
+        ApiError::parse_error(format!(
+            "Unsupported decimal format '{}', expected 'point' or 'comma'",
+            query.decimal.clone().unwrap_or_default()
+        ))
+    })?;
+
+    let data = equity::get_historical_data(&db).await.map_err(ApiError::from)?;
+
+    let csv = historical_records_to_csv(&data, format).map_err(ApiError::from)?;
+    info!("Exported {} historical records as CSV", data.len());
+    Ok(warp::reply::with_header(
+        warp::reply::with_header(csv, "Content-Type", "text/csv; charset=utf-8"),
+        "Content-Disposition",
+        "attachment; filename=\"historical_data.csv\"",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn quarterly_value(quarter: &str, value: f64) -> QuarterlyValue {
+        QuarterlyValue { final_quarter: quarter.to_string(), value, estimated: false }
+    }
+
+    fn market_data() -> MarketData {
+        MarketData {
+            daily_close_sp500_price: 5881.63,
+            current_sp500_price: 5900.0,
+            ttm_dividend: None,
+            ttm_dividend_yield: None,
+            ttm_eps: None,
+            trailing_pe: None,
+            earnings_yield: None,
+            latest_eps_actual: None,
+            estimated_eps_sum: Some(quarterly_value("2025Q4", 236.0)),
+            cape: 37.9,
+            cape_period: "Dec 2024".to_string(),
+            price_source: "yahoo".to_string(),
+            last_update: DateTime::UNIX_EPOCH,
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn estimate_fields_are_absent_when_estimates_are_excluded() {
+        let data = market_data();
+
+        let without = serde_json::to_value(EquityResponse::new(&data, None)).unwrap();
+        assert!(without.get("estimated_eps_sum").is_none());
+        assert!(without.get("forward_pe").is_none());
+        assert_eq!(without["current_sp500_price"], json!(5900.0));
+
+        let with = serde_json::to_value(EquityResponse::new(&data, Some(&data.estimated_eps_sum))).unwrap();
+        assert_eq!(with["estimated_eps_sum"]["value"], json!(236.0));
+        assert_eq!(with["forward_pe"], json!(5900.0 / 236.0));
+    }
+
+    #[test]
+    fn negative_estimated_eps_keeps_the_raw_sum_but_no_forward_pe() {
+        let data = MarketData { estimated_eps_sum: Some(quarterly_value("2025Q4", -12.5)), ..market_data() };
+
+        let body = serde_json::to_value(EquityResponse::new(&data, Some(&data.estimated_eps_sum))).unwrap();
+
+        assert_eq!(body["estimated_eps_sum"]["value"], json!(-12.5));
+        assert_eq!(body["forward_pe"], json!(null));
+    }
+
+    fn estimate(label: &str, eps: f64) -> QuarterlyData {
+        QuarterlyData { quarter: label.to_string(), dividend: None, eps_actual: None, eps_estimated: Some(eps) }
+    }
+
+    #[test]
+    fn stale_estimate_quarters_are_dropped_from_the_current_sum() {
+        let quarters = vec![
+            estimate("2024Q3", 55.0),
+            estimate("2024Q4", 56.0),
+            estimate("2025Q1", 57.0),
+            estimate("2025Q2", 58.0),
+            estimate("2025Q3", 59.0),
+            estimate("2025Q4", 60.0),
+        ];
+
+        let sum = current_estimated_eps_sum(&quarters, (2025, 1)).unwrap();
+        assert_eq!(sum.final_quarter, "2025Q4");
+        assert_eq!(sum.value, 57.0 + 58.0 + 59.0 + 60.0);
+
+        // From Q2 2025 only three future estimates are left
+        assert!(current_estimated_eps_sum(&quarters, (2025, 2)).is_none());
+    }
+
+    #[test]
+    fn current_quarter_follows_the_calendar() {
+        assert_eq!(current_quarter(Utc.with_ymd_and_hms(2025, 3, 31, 23, 0, 0).unwrap()), (2025, 1));
+        assert_eq!(current_quarter(Utc.with_ymd_and_hms(2025, 4, 1, 0, 0, 0).unwrap()), (2025, 2));
+        assert_eq!(current_quarter(Utc.with_ymd_and_hms(2025, 12, 31, 0, 0, 0).unwrap()), (2025, 4));
+    }
+}
@@ -2,6 +2,11 @@
 use std::fmt;
 use std::error::Error;
 use warp::reject::Reject;
+use crate::services::calculations::NoHistoricalData;
+use crate::services::equity::ScrapeError;
+use crate::services::error::ServiceError;
+use crate::services::google_oauth::TokenError;
+use crate::services::treasury_common::TreasuryCsvError;
 
 #[derive(Debug, Clone)]
 pub enum ApiError {
@@ -9,6 +14,9 @@ pub enum ApiError {
     ExternalServiceError(String),
     CacheError(String),
     ParseError(String),
+    Unauthorized(String),
+    Unavailable(String),
+    NotFound(String),
 }
 
 // Implement the necessary traits
@@ -28,6 +36,18 @@ impl ApiError {
     pub fn parse_error(msg: impl Into<String>) -> Self {
         ApiError::ParseError(msg.into())
     }
+
+    pub fn unauthorized(msg: impl Into<String>) -> Self {
+        ApiError::Unauthorized(msg.into())
+    }
+
+    pub fn unavailable(msg: impl Into<String>) -> Self {
+        ApiError::Unavailable(msg.into())
+    }
+
+    pub fn not_found(msg: impl Into<String>) -> Self {
+        ApiError::NotFound(msg.into())
+    }
 }
 
 impl fmt::Display for ApiError {
@@ -37,16 +57,69 @@ impl fmt::Display for ApiError {
             ApiError::ExternalServiceError(msg) => write!(f, "External service error: {}", msg),
             ApiError::CacheError(msg) => write!(f, "Cache error: {}", msg),
             ApiError::ParseError(msg) => write!(f, "Parse error: {}", msg),
+            ApiError::Unauthorized(msg) => write!(f, "Unauthorized: {}", msg),
+            ApiError::Unavailable(msg) => write!(f, "Service unavailable: {}", msg),
+            ApiError::NotFound(msg) => write!(f, "Not found: {}", msg),
+        }
+    }
+}
+
+/// Service functions return `anyhow::Result`; pick the variant from the underlying error so
+/// handlers can use `?` and still get the right status. Anything unrecognized (including a
+/// misconfigured Google service account) came from the store, matching what handlers
+/// reported before.
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        if let Some(service_error) = e.downcast_ref::<ServiceError>() {
+            ApiError::from(service_error)
+        } else if e.downcast_ref::<NoHistoricalData>().is_some() {
+            ApiError::cache_error(e.to_string())
+        } else if e.downcast_ref::<ScrapeError>().is_some()
+            || e.downcast_ref::<TreasuryCsvError>().is_some()
+            || e.downcast_ref::<reqwest::Error>().is_some()
+            || matches!(e.downcast_ref::<TokenError>(), Some(TokenError::Transient(_)))
+        {
+            ApiError::external_error(e.to_string())
+        } else {
+            ApiError::database_error(e.to_string())
         }
     }
 }
 
+/// Upstream failures are 502s; a broken Sheets setup or rejected write is the store's fault
+impl From<&ServiceError> for ApiError {
+    fn from(e: &ServiceError) -> Self {
+        match e {
+            ServiceError::Http(_) | ServiceError::Parse(_) | ServiceError::Scrape(_) => ApiError::external_error(e.to_string()),
+            ServiceError::Auth(TokenError::Transient(_)) => ApiError::external_error(e.to_string()),
+            ServiceError::Auth(TokenError::Misconfigured(_)) | ServiceError::Sheets(_) => ApiError::database_error(e.to_string()),
+            ServiceError::NotFound(msg) => ApiError::not_found(msg.clone()),
+        }
+    }
+}
+
+impl From<ServiceError> for ApiError {
+    fn from(e: ServiceError) -> Self {
+        ApiError::from(&e)
+    }
+}
+
+/// The BLS and FRED clients box their errors; all of them are upstream failures
+impl From<Box<dyn Error + Send + Sync>> for ApiError {
+    fn from(e: Box<dyn Error + Send + Sync>) -> Self {
+        ApiError::external_error(e.to_string())
+    }
+}
+
 // This is required to make ApiError usable with Error trait objects
 impl Error for ApiError {}
 
-// This is required for warp's rejection handling
+// This is required for warp's rejection handling, and lets `?` turn an ApiError into a Rejection
 impl Reject for ApiError {}
 
-// Explicitly implement Send and Sync
-unsafe impl Send for ApiError {}
-unsafe impl Sync for ApiError {}
\ No newline at end of file
+// `Reject` needs Send + Sync; ApiError only holds Strings so both are auto traits here,
+// and this fails to compile if a future variant breaks that
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<ApiError>();
+};
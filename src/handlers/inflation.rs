@@ -1,15 +1,17 @@
 // src/handlers/inflation.rs
 use warp::reply::with_status;
 use warp::Rejection;
-use crate::services::bls::fetch_inflation_data;
+use crate::services::bls::{fetch_cpi_history, fetch_inflation_data};
+use serde::Deserialize;
 use log::{info, error, debug};
 use std::sync::Arc;
 use chrono::{Duration, Utc};
 use crate::services::db::DbStore;
+use super::envelope::{self, rates_max_age, EnvelopeQuery, Meta, SOURCE_BLS};
 use super::error::ApiError;
 use serde_json::json;
 
-pub async fn get_inflation(db: Arc<DbStore>) -> Result<impl warp::Reply, Rejection> {
+pub async fn get_inflation(envelope: EnvelopeQuery, db: Arc<DbStore>) -> Result<impl warp::Reply, Rejection> {
     info!("Handling request to get inflation data");
 
     // Add debug logging for cache access
@@ -26,7 +28,7 @@ pub async fn get_inflation(db: Arc<DbStore>) -> Result<impl warp::Reply, Rejecti
     };
 
     debug!("Current inflation cache timestamp: {:?}", cache.timestamps.bls_data);
-    if cache.timestamps.bls_data < Utc::now() - Duration::hours(1) {
+    if db.handlers_may_write() && cache.timestamps.bls_data < Utc::now() - Duration::hours(1) {
         info!("Cache expired, fetching new inflation data");
         match fetch_inflation_data().await {
             Ok(rate) => {
@@ -53,9 +55,42 @@ pub async fn get_inflation(db: Arc<DbStore>) -> Result<impl warp::Reply, Rejecti
 
     debug!("Returning inflation rate: {}", cache.inflation_rate);
     Ok(with_status(
-        warp::reply::json(&json!({
-            "rate": cache.inflation_rate
-        })),
+        envelope::reply(&json!({ "rate": cache.inflation_rate }), &envelope, || {
+            Meta::cached(SOURCE_BLS, cache.timestamps.bls_data, rates_max_age())
+        }),
         warp::http::StatusCode::OK
     ))
-}
\ No newline at end of file
+}
+
+/// Number of months of CPI history returned when `?months=` isn't given
+const DEFAULT_CPI_HISTORY_MONTHS: usize = 24;
+
+#[derive(Debug, Deserialize)]
+pub struct CpiHistoryQuery {
+    pub months: Option<usize>,
+}
+
+pub async fn get_cpi_history(query: CpiHistoryQuery, envelope: EnvelopeQuery) -> Result<impl warp::Reply, Rejection> {
+    let months = query.months.unwrap_or(DEFAULT_CPI_HISTORY_MONTHS);
+    info!("Handling request to get {} months of CPI history", months);
+
+    if months == 0 {
+        return Err(warp::reject::custom(ApiError::parse_error("months must be at least 1")));
+    }
+
+    match fetch_cpi_history(months).await {
+        Ok(history) => {
+            debug!("Returning {} CPI observations", history.len());
+            Ok(with_status(
+                envelope::reply(&history, &envelope, || Meta::live(SOURCE_BLS)),
+                warp::http::StatusCode::OK
+            ))
+        }
+        Err(e) => {
+            error!("Failed to fetch CPI history: {}", e);
+            Err(warp::reject::custom(ApiError::external_error(
+                format!("Failed to fetch CPI history: {}", e)
+            )))
+        }
+    }
+}
@@ -2,42 +2,64 @@
 use warp::reply::with_status;
 use warp::Rejection;
 use std::sync::Arc;
+use crate::handlers::envelope::{self, rates_max_age, EnvelopeQuery, Meta, SOURCE_TREASURY};
 use crate::handlers::error::ApiError;
 use crate::services::db::DbStore;
 use crate::services::treasury_long::{fetch_20y_bond_yield, fetch_20y_tips_yield};
 use log::{error, info, debug};
 use chrono::{Duration, Utc};
-use serde_json::json;
+use serde_json::{json, Value};
+use crate::models::MarketCache;
 
-pub async fn get_long_term_rates(db: Arc<DbStore>) -> Result<impl warp::Reply, Rejection> {
-    info!("Handling request to get long-term rates");
+/// Response body for the long-term rates; a yield that has never been fetched is null,
+/// while a fetched 0.0 is reported as is
+fn long_term_rates_body(cache: &MarketCache, partial: bool) -> Value {
+    // Real T-bill rate in percent; inflation is cached as a decimal
+    let real_tbill = if cache.tbill_yield != 0.0 && cache.inflation_rate != 0.0 {
+        Some(cache.tbill_yield - cache.inflation_rate * 100.0)
+    } else {
+        None
+    };
 
-    debug!("Attempting to get market cache");
-    let mut cache = match db.get_market_cache().await {
-        Ok(cache) => {
-            debug!("Successfully retrieved market cache");
-            cache
+    json!({
+        "rates": {
+            "bond_yield_20y": cache.bond_yield_20y,
+            "tips_yield_20y": cache.tips_yield_20y,
+            "real_tbill": real_tbill
         },
-        Err(e) => {
-            error!("Failed to get market cache: {:?}", e);
-            return Err(warp::reject::custom(ApiError::database_error(e.to_string())));
+        "partial": partial,
+        "timestamps": {
+            "treasury": cache.timestamps.treasury_data,
+            "bls": cache.timestamps.bls_data
         }
-    };
+    })
+}
+
+pub async fn get_long_term_rates(envelope: EnvelopeQuery, db: Arc<DbStore>) -> Result<impl warp::Reply, Rejection> {
+    info!("Handling request to get long-term rates");
+
+    debug!("Attempting to get market cache");
+    let mut cache = db.get_market_cache().await.map_err(ApiError::from)?;
+    debug!("Successfully retrieved market cache");
 
     debug!("Current treasury cache timestamp: {:?}", cache.timestamps.treasury_data);
-    if cache.timestamps.treasury_data < Utc::now() - Duration::hours(1) {
+    let mut partial = false;
+    if db.handlers_may_write() && cache.timestamps.treasury_data < Utc::now() - Duration::hours(1) {
         info!("Cache expired, fetching new treasury data");
         
         let mut update_failed = false;
+        let mut bond_refreshed = false;
+        let mut tips_refreshed = false;
         
         match fetch_20y_bond_yield().await {
             Ok(rate) => {
                 debug!("Successfully fetched new 20y bond yield: {}", rate);
-                cache.bond_yield_20y = rate;
+                cache.bond_yield_20y = Some(rate);
+                bond_refreshed = true;
             }
             Err(e) => {
                 error!("Failed to fetch 20y bond yield: {}", e);
-                if cache.bond_yield_20y == 0.0 {
+                if cache.bond_yield_20y.is_none() {
                     update_failed = true;
                 }
             }
@@ -46,16 +68,19 @@ pub async fn get_long_term_rates(db: Arc<DbStore>) -> Result<impl warp::Reply, R
         match fetch_20y_tips_yield().await {
             Ok(rate) => {
                 debug!("Successfully fetched new 20y TIPS yield: {}", rate);
-                cache.tips_yield_20y = rate;
+                cache.tips_yield_20y = Some(rate);
+                tips_refreshed = true;
             }
             Err(e) => {
                 error!("Failed to fetch 20y TIPS yield: {}", e);
-                if cache.tips_yield_20y == 0.0 {
+                if cache.tips_yield_20y.is_none() {
                     update_failed = true;
                 }
             }
         }
 
+        partial = bond_refreshed != tips_refreshed;
+
         if !update_failed {
             cache.timestamps.treasury_data = Utc::now();
             if let Err(e) = db.update_market_cache(&cache).await {
@@ -64,36 +89,45 @@ pub async fn get_long_term_rates(db: Arc<DbStore>) -> Result<impl warp::Reply, R
             }
         } else {
             // Only reject if we have no data at all
-            if cache.bond_yield_20y == 0.0 && cache.tips_yield_20y == 0.0 {
-                return Err(warp::reject::custom(ApiError::external_error(
-                    "Failed to fetch treasury yield data".to_string()
-                )));
+            if cache.bond_yield_20y.is_none() && cache.tips_yield_20y.is_none() {
+                return Err(ApiError::external_error("Failed to fetch treasury yield data").into());
             }
         }
     }
 
-    // Calculate real T-bill rate
-    let real_tbill = if cache.tbill_yield != 0.0 && cache.inflation_rate != 0.0 {
-        cache.tbill_yield - cache.inflation_rate
-    } else {
-        0.0 // Or another suitable default/fallback value
-    };
+    debug!("Returning long-term rates: bond={:?}, tips={:?}, partial={}",
+           cache.bond_yield_20y, cache.tips_yield_20y, partial);
 
-    debug!("Returning long-term rates: bond={}, tips={}, real_tbill={}", 
-           cache.bond_yield_20y, cache.tips_yield_20y, real_tbill);
-           
     Ok(with_status(
-        warp::reply::json(&json!({
-            "rates": {
-                "bond_yield_20y": cache.bond_yield_20y,
-                "tips_yield_20y": cache.tips_yield_20y,
-                "real_tbill": real_tbill
-            },
-            "timestamps": {
-                "treasury": cache.timestamps.treasury_data,
-                "bls": cache.timestamps.bls_data
-            }
-        })),
+        envelope::reply(&long_term_rates_body(&cache, partial), &envelope, || {
+            Meta::cached(SOURCE_TREASURY, cache.timestamps.treasury_data, rates_max_age())
+        }),
         warp::http::StatusCode::OK
     ))
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::test_support::market_cache;
+
+    #[test]
+    fn fetched_zero_yield_is_reported_and_unfetched_yield_is_null() {
+        let cache = MarketCache { bond_yield_20y: Some(0.0), tips_yield_20y: None, ..market_cache() };
+
+        let body = long_term_rates_body(&cache, true);
+
+        assert_eq!(body["rates"]["bond_yield_20y"], json!(0.0));
+        assert_eq!(body["rates"]["tips_yield_20y"], Value::Null);
+        assert_eq!(body["partial"], json!(true));
+    }
+
+    #[test]
+    fn real_tbill_is_in_percent_with_inflation_cached_as_a_decimal() {
+        let cache = MarketCache { tbill_yield: 4.3, inflation_rate: 0.029, ..market_cache() };
+
+        let body = long_term_rates_body(&cache, false);
+
+        let real_tbill = body["rates"]["real_tbill"].as_f64().unwrap();
+        assert!((real_tbill - 1.4).abs() < 1e-9, "{}", real_tbill);
+    }
+}
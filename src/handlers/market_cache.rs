@@ -0,0 +1,19 @@
+// src/handlers/market_cache.rs
+use warp::reply::Json;
+use warp::Rejection;
+use std::sync::Arc;
+use crate::services::db::DbStore;
+use super::envelope::{self, EnvelopeQuery, Meta, SOURCE_MARKET_CACHE};
+use super::error::ApiError;
+use log::{debug, error};
+
+/// The whole market cache as stored, for debugging without opening the sheet
+pub async fn get_market_cache(envelope: EnvelopeQuery, db: Arc<DbStore>) -> Result<Json, Rejection> {
+    let cache = db.get_market_cache().await.map_err(|e| {
+        error!("Failed to get market cache: {}", e);
+        ApiError::cache_error(e.to_string())
+    })?;
+
+    debug!("Returning market cache with timestamps {:?}", cache.timestamps);
+    Ok(envelope::reply(&cache, &envelope, || Meta::stored(SOURCE_MARKET_CACHE)))
+}
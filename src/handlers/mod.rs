@@ -4,4 +4,11 @@ pub mod tbill;
 pub mod real_yield;
 pub mod long_term;
 pub mod equity;
-pub mod error;
\ No newline at end of file
+pub mod error;
+pub mod admin;
+pub mod status;
+pub mod dashboard;
+pub mod version;
+pub mod envelope;
+pub mod market_cache;
+pub mod quarterly;
@@ -0,0 +1,34 @@
+// src/handlers/quarterly.rs
+use warp::reply::Json;
+use warp::Rejection;
+use std::sync::Arc;
+use serde::Deserialize;
+use crate::services::db::DbStore;
+use crate::services::equity;
+use super::envelope::{self, EnvelopeQuery, Meta, SOURCE_YCHARTS};
+use super::equity::parse_quarter_label;
+use super::error::ApiError;
+use log::info;
+
+#[derive(Debug, Deserialize)]
+pub struct QuarterlyQuery {
+    /// Only return quarters at or after this `YYYYQn` quarter
+    pub from: Option<String>,
+}
+
+/// The quarters behind the TTM dividend and forward EPS figures, most recent first
+pub async fn get_quarterly_data(query: QuarterlyQuery, envelope: EnvelopeQuery, db: Arc<DbStore>) -> Result<Json, Rejection> {
+    let from = match query.from.as_deref() {
+        Some(label) => {
+            let (year, quarter) = parse_quarter_label(label).ok_or_else(|| {
+                ApiError::parse_error(format!("Invalid quarter '{}', expected YYYYQn", label))
+            })?;
+            Some((year, quarter as i32))
+        }
+        None => None,
+    };
+
+    let quarters = equity::get_quarterly_data(&db, from).await.map_err(ApiError::from)?;
+    info!("Returning {} quarters", quarters.len());
+    Ok(envelope::reply(&quarters, &envelope, || Meta::stored(SOURCE_YCHARTS)))
+}
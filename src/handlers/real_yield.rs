@@ -3,11 +3,12 @@ use warp::reply::with_status;
 use warp::Rejection;
 use std::sync::Arc;
 use crate::services::db::DbStore;
+use super::envelope::{self, rates_max_age, EnvelopeQuery, Meta, SOURCE_TREASURY_BLS};
 use super::error::ApiError;
 use log::{info, error, debug};
 use serde_json::json;
 
-pub async fn get_real_yield(db: Arc<DbStore>) -> Result<impl warp::Reply, Rejection> {
+pub async fn get_real_yield(envelope: EnvelopeQuery, db: Arc<DbStore>) -> Result<impl warp::Reply, Rejection> {
     info!("Handling request to calculate real yield");
 
     debug!("Attempting to get market cache");
@@ -30,17 +31,22 @@ pub async fn get_real_yield(db: Arc<DbStore>) -> Result<impl warp::Reply, Reject
         )));
     }
 
-    let real_yield = cache.tbill_yield - cache.inflation_rate;
+    // In percent, like the T-bill yield; inflation is cached as a decimal
+    let real_yield = cache.tbill_yield - cache.inflation_rate * 100.0;
     debug!("Calculated real yield: {}", real_yield);
 
     Ok(with_status(
-        warp::reply::json(&json!({
+        envelope::reply(&json!({
             "real_yield": real_yield,
             "components": {
                 "tbill_yield": cache.tbill_yield,
                 "inflation_rate": cache.inflation_rate
             }
-        })),
+        }), &envelope, || {
+            // As old as the older of its two inputs
+            let as_of = cache.timestamps.treasury_data.min(cache.timestamps.bls_data);
+            Meta::cached(SOURCE_TREASURY_BLS, as_of, rates_max_age())
+        }),
         warp::http::StatusCode::OK
     ))
 }
\ No newline at end of file
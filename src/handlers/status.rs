@@ -0,0 +1,113 @@
+// src/handlers/status.rs
+use warp::reply::with_status;
+use warp::Rejection;
+use std::env;
+use std::sync::Arc;
+use chrono::{NaiveDate, Utc};
+use chrono_tz::US::Central;
+use crate::services::db::DbStore;
+use crate::services::equity::is_trading_day;
+use super::error::ApiError;
+use log::{info, error, debug};
+use serde_json::json;
+
+/// Default number of trading days the YCharts fundamentals may lag before being flagged stale
+const DEFAULT_FUNDAMENTALS_STALE_TRADING_DAYS: i64 = 2;
+
+fn fundamentals_stale_threshold() -> i64 {
+    env::var("FUNDAMENTALS_STALE_TRADING_DAYS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_FUNDAMENTALS_STALE_TRADING_DAYS)
+}
+
+/// Count NYSE trading days after `from` up to and including `to`
+fn trading_days_between(from: NaiveDate, to: NaiveDate) -> i64 {
+    from.iter_days()
+        .skip(1)
+        .take_while(|d| *d <= to)
+        .filter(|d| is_trading_day(*d))
+        .count() as i64
+}
+
+/// How long the readiness probe waits on the store before reporting it unreachable
+const READINESS_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Readiness probe: read the market cache row straight from the store, bypassing the
+/// in-memory copy, so bad credentials or an unreachable spreadsheet report 503
+pub async fn get_readiness(db: Arc<DbStore>) -> Result<impl warp::Reply, Rejection> {
+    match tokio::time::timeout(READINESS_TIMEOUT, db.store.get_market_cache()).await {
+        Ok(Ok(_)) => {
+            debug!("Readiness check passed");
+            Ok(warp::reply::json(&json!({"status": "ready"})))
+        }
+        // handle_rejection logs these as server errors
+        Ok(Err(e)) => Err(ApiError::unavailable(format!("Store unreachable: {}", e)).into()),
+        Err(_) => Err(ApiError::unavailable(format!("Store did not respond within {:?}", READINESS_TIMEOUT)).into()),
+    }
+}
+
+pub async fn get_status(db: Arc<DbStore>) -> Result<impl warp::Reply, Rejection> {
+    info!("Handling request to get data status");
+
+    let cache = match db.get_market_cache().await {
+        Ok(cache) => cache,
+        Err(e) => {
+            error!("Failed to get market cache: {:?}", e);
+            return Err(warp::reject::custom(ApiError::database_error(e.to_string())));
+        }
+    };
+
+    let now = Utc::now();
+    let ycharts_update = cache.timestamps.ycharts_data;
+    let age_days = (now - ycharts_update).num_days();
+    let trading_days = trading_days_between(
+        ycharts_update.with_timezone(&Central).date_naive(),
+        now.with_timezone(&Central).date_naive(),
+    );
+    let threshold = fundamentals_stale_threshold();
+    let fundamentals_stale = trading_days > threshold;
+
+    let market_price_update = cache.timestamps.last_market_price_update;
+    let market_price_trading_days = trading_days_between(
+        market_price_update.with_timezone(&Central).date_naive(),
+        now.with_timezone(&Central).date_naive(),
+    );
+
+    debug!("Fundamentals age: {} days ({} trading days), stale: {}", age_days, trading_days, fundamentals_stale);
+
+    Ok(with_status(
+        warp::reply::json(&json!({
+            "timestamps": cache.timestamps,
+            "fundamentals": {
+                "last_update": ycharts_update,
+                "age_days": age_days,
+                "trading_days_since_update": trading_days,
+                "stale_threshold_trading_days": threshold,
+                "fundamentals_stale": fundamentals_stale
+            },
+            "market_price": {
+                "last_update": market_price_update,
+                "age_minutes": (now - market_price_update).num_minutes(),
+                "trading_days_since_update": market_price_trading_days
+            }
+        })),
+        warp::http::StatusCode::OK
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holidays_are_not_counted_as_trading_days() {
+        let day = |m: u32, d: u32| NaiveDate::from_ymd_opt(2024, m, d).unwrap();
+
+        // Thursday before Independence Day to the Monday after: only Friday the 5th and Monday trade
+        assert_eq!(trading_days_between(day(7, 3), day(7, 8)), 2);
+        // Christmas Eve to the 27th: Christmas Day is closed
+        assert_eq!(trading_days_between(day(12, 24), day(12, 27)), 2);
+        assert_eq!(trading_days_between(day(12, 27), day(12, 27)), 0);
+    }
+}
@@ -6,10 +6,11 @@ use log::{info, error, debug};
 use std::sync::Arc;
 use chrono::{Duration, Utc};
 use crate::services::db::DbStore;
+use super::envelope::{self, rates_max_age, EnvelopeQuery, Meta, SOURCE_TREASURY};
 use super::error::ApiError;
 use serde_json::json;
 
-pub async fn get_tbill(db: Arc<DbStore>) -> Result<impl warp::Reply, Rejection> {
+pub async fn get_tbill(envelope: EnvelopeQuery, db: Arc<DbStore>) -> Result<impl warp::Reply, Rejection> {
     info!("Handling request to get T-bill rate");
 
     debug!("Attempting to get market cache");
@@ -25,7 +26,7 @@ pub async fn get_tbill(db: Arc<DbStore>) -> Result<impl warp::Reply, Rejection>
     };
 
     debug!("Current tbill cache timestamp: {:?}", cache.timestamps.treasury_data);
-    if cache.timestamps.treasury_data < Utc::now() - Duration::hours(1) {
+    if db.handlers_may_write() && cache.timestamps.treasury_data < Utc::now() - Duration::hours(1) {
         info!("Cache expired, fetching new T-bill data");
         match fetch_tbill_data().await {
             Ok(rate) => {
@@ -52,9 +53,9 @@ pub async fn get_tbill(db: Arc<DbStore>) -> Result<impl warp::Reply, Rejection>
 
     debug!("Returning T-bill yield: {}", cache.tbill_yield);
     Ok(with_status(
-        warp::reply::json(&json!({
-            "rate": cache.tbill_yield
-        })),
+        envelope::reply(&json!({ "rate": cache.tbill_yield }), &envelope, || {
+            Meta::cached(SOURCE_TREASURY, cache.timestamps.treasury_data, rates_max_age())
+        }),
         warp::http::StatusCode::OK
     ))
 }
\ No newline at end of file
@@ -0,0 +1,19 @@
+// src/handlers/version.rs
+use warp::reply::Json;
+use warp::Rejection;
+use chrono::DateTime;
+use serde_json::json;
+
+/// Build metadata baked in at compile time by `build.rs`
+pub async fn get_version() -> Result<Json, Rejection> {
+    let build_timestamp = env!("BUILD_TIMESTAMP")
+        .parse::<i64>()
+        .ok()
+        .and_then(|secs| DateTime::from_timestamp(secs, 0));
+
+    Ok(warp::reply::json(&json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_commit": env!("BUILD_GIT_COMMIT"),
+        "build_timestamp": build_timestamp,
+    })))
+}
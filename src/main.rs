@@ -1,59 +1,206 @@
 // src/main.rs
+// Proving the full route tree's service future is Send recurses past the default limit
+#![recursion_limit = "256"]
 
 use chrono::offset::LocalResult;
 use dotenv::dotenv;
-use env_logger;
 use log::{info, warn, error};
+use std::convert::Infallible;
 use std::env;
 use std::fs;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Instant;
 use warp::Filter;
+use warp::hyper::{self, service::Service};
 use tokio_cron_scheduler::{JobScheduler, Job};
-use chrono_tz::US::Central;
-use chrono::{Utc, TimeZone, Datelike};
+use chrono_tz::{Tz, US::Central};
+use chrono::{DateTime, Utc, TimeZone, Datelike, NaiveTime, Timelike};
 
 use macro_dashboard_acm::services;
 use macro_dashboard_acm::routes;
 
+/// Port used when `PORT` is unset or invalid
+const DEFAULT_PORT: u16 = 3030;
+
+/// Parse the `PORT` value, trimming whitespace and falling back to 3030 instead of
+/// refusing to boot when it's missing or malformed
+fn parse_port(value: Option<&str>) -> u16 {
+    match value.map(str::trim) {
+        None => {
+            warn!("$PORT not set, defaulting to {}", DEFAULT_PORT);
+            DEFAULT_PORT
+        }
+        Some(raw) => raw.parse().unwrap_or_else(|e| {
+            error!("Invalid $PORT '{}' ({}), defaulting to {}", raw, e, DEFAULT_PORT);
+            DEFAULT_PORT
+        }),
+    }
+}
+
+/// How many missed trading days the startup catch-up looks back over, from `CATCH_UP_DAYS` (default 5)
+fn catch_up_days() -> usize {
+    env::var("CATCH_UP_DAYS")
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(5)
+}
+
+/// Central time of the daily update, from `DAILY_UPDATE_TIME` (`HH:MM`, default 15:30).
+/// A malformed value stops the server rather than silently updating at the wrong time.
+fn daily_update_time() -> NaiveTime {
+    let raw = env::var("DAILY_UPDATE_TIME").unwrap_or_else(|_| services::equity::DEFAULT_DAILY_UPDATE_TIME.to_string());
+    match services::equity::parse_daily_update_time(&raw) {
+        Some(time) => time,
+        None => {
+            error!("Invalid DAILY_UPDATE_TIME '{}', expected HH:MM (e.g. 15:30)", raw);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Today's daily update time in Central. A DST transition can make a local time ambiguous
+/// (the earlier instant is used) or nonexistent (the catch-up is skipped for the day).
+fn daily_update_target(central_now: DateTime<Tz>, update_time: NaiveTime) -> Option<DateTime<Tz>> {
+    match Central.with_ymd_and_hms(
+        central_now.year(), central_now.month(), central_now.day(),
+        update_time.hour(), update_time.minute(), 0,
+    ) {
+        LocalResult::Single(dt) => Some(dt),
+        LocalResult::Ambiguous(earlier, later) => {
+            warn!("Ambiguous update time ({} or {}), using the earlier one", earlier, later);
+            Some(earlier)
+        }
+        LocalResult::None => {
+            warn!("{} does not exist on {} in Central time, skipping startup catch-up", update_time.format("%H:%M"), central_now.date_naive());
+            None
+        }
+    }
+}
+
+/// Log any sheet whose header row differs from what the code expects.
+/// With `STRICT_SCHEMA=1` the server refuses to start instead of risking writes to the wrong columns.
+async fn check_schema(db: &services::db::DbStore) {
+    let strict = env::var("STRICT_SCHEMA").is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"));
+
+    match db.store.check_schema().await {
+        Ok(drift) if drift.is_empty() => info!("Sheet headers match the expected schema"),
+        Ok(drift) => {
+            for diff in &drift {
+                warn!("Schema drift in {}", diff);
+            }
+            if strict {
+                error!("STRICT_SCHEMA is set and {} sheet(s) have drifted; refusing to start", drift.len());
+                std::process::exit(1);
+            }
+        }
+        Err(e) => {
+            error!("Failed to check sheet headers: {}", e);
+            if strict {
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Load the market cache into memory (and fetch the Sheets OAuth token) before serving,
+/// so the first request doesn't pay for the round-trip. A failure is logged and left for
+/// the first request to retry.
+async fn warm_market_cache(db: &services::db::DbStore) {
+    let start = Instant::now();
+    match db.get_market_cache().await {
+        Ok(_) => info!("Warmed market cache in {} ms", start.elapsed().as_millis()),
+        Err(e) => warn!("Failed to warm market cache after {} ms, first request will load it: {}", start.elapsed().as_millis(), e),
+    }
+}
+
+/// How long shutdown waits for in-flight store writes; Heroku kills the dyno 30s after SIGTERM
+const SHUTDOWN_WRITE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(20);
+
+/// Resolve on Ctrl-C or SIGTERM (what Heroku sends before restarting a dyno)
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    info!("Shutdown signal received, shutting down gracefully");
+}
+
 #[tokio::main]
 async fn main() {
     dotenv().ok();
     env_logger::init();
     info!("Logger initialized. Starting the application...");
-    //remove following block if testing locally
-    if let Ok(json_str) = std::env::var("GOOGLE_SERVICE_ACCOUNT_JSON") {
-        // Choose a path that won't conflict with anything else:
-        let path = "/tmp/service_account.json";
-        fs::write(path, json_str).expect("Failed to write service account JSON");
-
-        // Then tell your code that the service account file is at "/tmp/service_account.json"
-        // e.g. re-export it as an env var:
-        std::env::set_var("SERVICE_ACCOUNT_JSON", path);
-    }
-    // Initialize Google Sheets connection
-    let spreadsheet_id = env::var("GOOGLE_SHEETS_ID")
-        .expect("GOOGLE_SHEETS_ID must be set");
-    // Instead of an API key, we use the service account JSON path
-    let service_account_json_path = env::var("SERVICE_ACCOUNT_JSON")
-        .expect("SERVICE_ACCOUNT_JSON must be set");
-
-    let db = services::db::DbStore::new(&spreadsheet_id, &service_account_json_path)
-        .await
-        .expect("Failed to initialize Google Sheets connection");
+    let update_time = daily_update_time();
+    services::equity::set_daily_update_time(update_time);
+    services::bls::log_bls_tier();
+    let db = if let Ok(local_path) = env::var("LOCAL_STORE_PATH") {
+        // Local development against a JSON file written by `cargo run --bin seed_local`
+        info!("Using local JSON store at {}", local_path);
+        services::db::DbStore::new_local(&local_path)
+    } else {
+        //remove following block if testing locally
+        if let Ok(json_str) = std::env::var("GOOGLE_SERVICE_ACCOUNT_JSON") {
+            // Choose a path that won't conflict with anything else:
+            let path = "/tmp/service_account.json";
+            fs::write(path, json_str).expect("Failed to write service account JSON");
+
+            // Then tell your code that the service account file is at "/tmp/service_account.json"
+            // e.g. re-export it as an env var:
+            std::env::set_var("SERVICE_ACCOUNT_JSON", path);
+        }
+        // Initialize Google Sheets connection
+        let spreadsheet_id = env::var("GOOGLE_SHEETS_ID")
+            .expect("GOOGLE_SHEETS_ID must be set");
+        // Instead of an API key, we use the service account JSON path
+        let service_account_json_path = env::var("SERVICE_ACCOUNT_JSON")
+            .expect("SERVICE_ACCOUNT_JSON must be set");
+
+        let market_cache_ttl = services::db::market_cache_ttl_from_env();
+        services::db::DbStore::new(&spreadsheet_id, &service_account_json_path, market_cache_ttl)
+            .await
+            .expect("Failed to initialize Google Sheets connection")
+    };
+    check_schema(&db).await;
+    warm_market_cache(&db).await;
     let db = Arc::new(db);
     let db_clone = db.clone();
     let scheduler_db = db.clone();
 
     // Initialize the scheduler
-    let scheduler = JobScheduler::new().await.expect("Failed to create scheduler");
+    let mut scheduler = JobScheduler::new().await.expect("Failed to create scheduler");
 
-    // Schedule market data updates for 3:30 PM Central every day
-    let daily_job = Job::new_async("0 30 15 * * *", move |_, _| {
+    // Schedule market data updates for the daily update time every day
+    let daily_cron = format!("0 {} {} * * *", update_time.minute(), update_time.hour());
+    info!("Scheduling daily market data update at {} Central", update_time.format("%H:%M"));
+    let daily_job = Job::new_async(daily_cron.as_str(), move |_, _| {
         let db = scheduler_db.clone();
         Box::pin(async move {
-            info!("Running scheduled market data update at 3:30 PM Central");
-            match services::equity::get_market_data(&db).await {
+            info!("Running scheduled market data update at {} Central", update_time.format("%H:%M"));
+            match services::equity::run_scheduled_daily_update(&db).await {
                 Ok(_) => info!("Successfully completed scheduled market data update"),
                 Err(e) => error!("Failed to update market data: {}", e),
             }
@@ -63,6 +210,21 @@ async fn main() {
     // Add job to scheduler
     scheduler.add(daily_job).await.expect("Failed to add job to scheduler");
 
+    // When handlers are read-only, the scheduler also owns the hourly Treasury/BLS refresh
+    if !db.handlers_may_write() {
+        info!("Cache write mode is scheduler-only; handlers will not refresh the cache");
+        let rates_db = db.clone();
+        let rates_job = Job::new_async("0 0 * * * *", move |_, _| {
+            let db = rates_db.clone();
+            Box::pin(async move {
+                if let Err(e) = services::rates::refresh_rates(&db).await {
+                    error!("Failed to refresh rates: {}", e);
+                }
+            })
+        }).expect("Failed to create rates job");
+        scheduler.add(rates_job).await.expect("Failed to add rates job to scheduler");
+    }
+
     // Start the scheduler
     scheduler.start().await.expect("Failed to start scheduler");
 
@@ -70,46 +232,51 @@ async fn main() {
     tokio::spawn(async move {
         let now = Utc::now();
         let central_now = now.with_timezone(&Central);
-        let target = match Central.with_ymd_and_hms(
-            central_now.year(),
-            central_now.month(),
-            central_now.day(),
-            15,
-            30,
-            0,
-        ) {
-            LocalResult::None => {
-                panic!("Invalid date/time");
-            }
-            LocalResult::Ambiguous(dt1, dt2) => {
-                panic!("Ambiguous local time: {} or {}", dt1, dt2);
+        let Some(target) = daily_update_target(central_now, update_time) else {
+            return;
+        };
+
+        let cache = match db_clone.get_market_cache().await {
+            Ok(cache) => cache,
+            Err(e) => {
+                error!("Skipping startup catch-up, failed to get market cache: {}", e);
+                return;
             }
-            LocalResult::Single(dt) => dt,
         };
 
+        // Trading days whose daily update was missed while the process was down
+        let today = central_now.date_naive();
+        let after_close = central_now.time() > target.time();
+        let last_update = cache.timestamps.yahoo_price.with_timezone(&Central).date_naive();
+        let missed = services::equity::missed_trading_days(last_update, today, after_close, catch_up_days());
+        if missed.is_empty() {
+            return;
+        }
+        let days: Vec<String> = missed.iter().map(|d| d.to_string()).collect();
+        warn!("Missed daily updates for {} trading day(s): {}", missed.len(), days.join(", "));
 
-        // If we're starting after 3:30 PM Central and haven't updated today
-        if central_now.time() > target.time() {
-            let cache = db_clone.get_market_cache().await
-                .expect("Failed to get market cache");
+        // Only today's close can still be read; scraped fundamentals merge idempotently,
+        // so one refresh backfills any quarters and months published during the outage
+        let mut parts = vec![services::equity::RefreshPart::Fundamentals];
+        let earlier: Vec<&String> = if missed.last() == Some(&today) {
+            parts.insert(0, services::equity::RefreshPart::DailyClose);
+            days.iter().take(missed.len() - 1).collect()
+        } else {
+            days.iter().collect()
+        };
+        if !earlier.is_empty() {
+            warn!("Daily closes for {:?} can't be recovered and will stay missing", earlier);
+        }
 
-            let last_update = cache.timestamps.yahoo_price.with_timezone(&Central);
-            if last_update.date_naive() < central_now.date_naive() {
-                info!("Catching up on missed market update");
-                if let Err(e) = services::equity::get_market_data(&db_clone).await {
-                    error!("Failed to catch up on market data: {}", e);
-                }
-            }
+        info!("Catching up on missed market update");
+        match services::equity::force_refresh(&db_clone, &parts).await {
+            Ok((report, _)) => info!("Startup catch-up refreshed {:?}", report.updated),
+            Err(e) => error!("Failed to catch up on market data: {}", e),
         }
     });
 
     // Get port from Heroku environment
-    let port_str = env::var("PORT").unwrap_or_else(|_| {
-        warn!("$PORT not set, defaulting to 3030");
-        "3030".to_string()
-    });
-
-    let port: u16 = port_str.parse().expect("PORT must be a number");
+    let port = parse_port(env::var("PORT").ok().as_deref());
     let addr: SocketAddr = ([0, 0, 0, 0], port).into();
     info!("Will bind to: {}", addr);
 
@@ -120,9 +287,73 @@ async fn main() {
         .allow_methods(vec!["GET", "POST", "PUT", "DELETE"]);
 
     // Set up routes with db connection
+    let shutdown_db = db.clone();
     let api = routes::routes(db).with(cors);
     info!("Routes configured successfully with CORS.");
 
     info!("Starting server on {}", addr);
-    warp::serve(api).run(addr).await;
+    // Serve through hyper directly so each request runs under `with_store_timing`, which
+    // lets the request log split Sheets time from compute time
+    let service = warp::service(api);
+    let make_service = hyper::service::make_service_fn(move |_| {
+        let service = service.clone();
+        async move {
+            Ok::<_, Infallible>(hyper::service::service_fn(move |request| {
+                let mut service = service.clone();
+                // Boxed: the route future is large enough to overflow a worker stack if moved around by value
+                services::db::with_store_timing(Box::pin(service.call(request)))
+            }))
+        }
+    });
+    let server = hyper::Server::bind(&addr)
+        .serve(make_service)
+        .with_graceful_shutdown(shutdown_signal());
+    if let Err(e) = server.await {
+        error!("Server error: {}", e);
+    }
+    info!("Server stopped accepting requests");
+
+    // Stop firing jobs, then let any store write already in progress land before exiting
+    if let Err(e) = scheduler.shutdown().await {
+        warn!("Failed to shut down scheduler: {}", e);
+    }
+    let writes = tokio::time::timeout(SHUTDOWN_WRITE_TIMEOUT, shutdown_db.block_writes()).await;
+    match &writes {
+        Ok(_) => info!("No store writes in flight, exiting"),
+        Err(_) => warn!("Store writes still in flight after {:?}, exiting anyway", SHUTDOWN_WRITE_TIMEOUT),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn central(year: i32, month: u32, day: u32, hour: u32) -> DateTime<Tz> {
+        Utc.with_ymd_and_hms(year, month, day, hour, 0, 0).unwrap().with_timezone(&Central)
+    }
+
+    #[test]
+    fn update_target_survives_dst_transitions() {
+        let at = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+
+        // An ordinary 3:30 PM is a single instant
+        let target = daily_update_target(central(2024, 6, 17, 18), at(15, 30)).unwrap();
+        assert_eq!(target.with_timezone(&Utc), Utc.with_ymd_and_hms(2024, 6, 17, 20, 30, 0).unwrap());
+
+        // 2:30 AM is skipped when clocks spring forward on 2024-03-10
+        assert_eq!(daily_update_target(central(2024, 3, 10, 18), at(2, 30)), None);
+
+        // 1:30 AM happens twice when clocks fall back on 2024-11-03; the earlier (CDT) one wins
+        let target = daily_update_target(central(2024, 11, 3, 18), at(1, 30)).unwrap();
+        assert_eq!(target.with_timezone(&Utc), Utc.with_ymd_and_hms(2024, 11, 3, 6, 30, 0).unwrap());
+    }
+
+    #[test]
+    fn port_is_trimmed_and_falls_back_to_the_default() {
+        assert_eq!(parse_port(Some("8080")), 8080);
+        assert_eq!(parse_port(Some(" 8080\n")), 8080);
+        assert_eq!(parse_port(Some("eighty")), DEFAULT_PORT);
+        assert_eq!(parse_port(Some("70000")), DEFAULT_PORT);
+        assert_eq!(parse_port(None), DEFAULT_PORT);
+    }
 }
@@ -1,35 +1,54 @@
 // src/models.rs
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Timestamps {
     pub yahoo_price: DateTime<Utc>,
     pub ycharts_data: DateTime<Utc>,
     pub treasury_data: DateTime<Utc>,  
     pub bls_data: DateTime<Utc>,       
+    /// Last successful price fetch during market hours; unlike `yahoo_price`, this isn't
+    /// bumped by weekend or overnight polls
+    pub last_market_price_update: DateTime<Utc>,
+    /// Last GDP fetch from FRED (the Unix epoch if GDP has never been fetched)
+    pub gdp_data: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct MarketCache {
     pub timestamps: Timestamps,
+    /// Prices of the tracked index (`INDEX_SYMBOL`, the S&P 500 by default). The `sp500`
+    /// names are kept because they are the sheet columns and API fields clients read.
     pub daily_close_sp500_price: f64,
     pub current_sp500_price: f64,
-    pub quarterly_dividends: HashMap<String, f64>,
-    pub eps_actual: HashMap<String, f64>,
-    pub eps_estimated: HashMap<String, f64>,
+    /// Keyed by quarter (`2024Q1`); ordered maps keep iteration and logging stable between runs
+    pub quarterly_dividends: BTreeMap<String, f64>,
+    pub eps_actual: BTreeMap<String, f64>,
+    pub eps_estimated: BTreeMap<String, f64>,
     pub current_cape: f64,
     pub cape_period: String,
-    pub tips_yield_20y: f64,        
-    pub bond_yield_20y: f64,        
-    pub tbill_yield: f64,          
-    pub inflation_rate: f64,  
+    /// `None` until the yield has been fetched once, so a real 0.0 isn't mistaken for missing data
+    pub tips_yield_20y: Option<f64>,
+    pub bond_yield_20y: Option<f64>,
+    /// 4-week T-bill yield in percent (5.28 == 5.28%)
+    pub tbill_yield: f64,
+    /// Year-over-year CPI-U inflation as a decimal (0.029 == 2.9%)
+    pub inflation_rate: f64,
     pub latest_monthly_return: f64,
     pub latest_month: String,      
+    /// Year whose final close has been written to the historical sheet (0 if never recorded)
+    pub last_year_close_captured: i32,
+    /// Latest US nominal GDP from FRED, in billions of dollars (SAAR); 0.0 if never fetched
+    pub gdp: f64,
+    /// Observation date of `gdp`, e.g. `2024-07-01` for Q3 2024
+    pub gdp_period: String,
+    /// Source of `current_sp500_price` (`yahoo` or `stooq`); empty if not yet recorded
+    pub price_source: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct HistoricalRecord {
     pub year: i32,
     pub sp500_price: f64,
@@ -37,9 +56,16 @@ pub struct HistoricalRecord {
     pub dividend_yield: f64,
     pub eps: f64,
     pub cape: f64,
+    /// CPI index level for the year (e.g. 304.7), not a rate; a year's inflation rate is the
+    /// change versus the prior year's level. 0.0 when missing
     pub inflation: f64,
     pub total_return: f64,
+    /// Cumulative total return since the start of the series (0.5 == +50%), not an index level
     pub cumulative_return: f64,
+    /// True when `total_return` was approximated from price change plus dividend yield
+    /// rather than compounded from monthly returns
+    #[serde(default)]
+    pub total_return_approximated: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -50,8 +76,27 @@ pub struct QuarterlyData {
     pub eps_estimated: Option<f64>,
 }
 
+/// One month of S&P 500 total return, as stored in the MonthlyData sheet
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MonthlyData {
+    /// Month in `YYYY-MM` format, so lexicographic order is chronological
     pub month: String,
+    /// Total return for the month as a decimal (0.0123 == 1.23%)
     pub total_return: f64,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn monthly_data_round_trips_through_json() {
+        let original = MonthlyData { month: "2024-01".to_string(), total_return: 0.0123 };
+
+        let json = serde_json::to_string(&original).unwrap();
+        assert_eq!(json, r#"{"month":"2024-01","total_return":0.0123}"#);
+
+        let parsed: MonthlyData = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.month, "2024-01");
+        assert_eq!(parsed.total_return, 0.0123);
+    }
+}
@@ -6,9 +6,51 @@ use serde_json::json;
 use log::{info, error, debug};
 
 use crate::handlers::{
-    equity::{get_equity_data, get_equity_history, get_equity_history_range, get_market_metrics}, error::ApiError, inflation::get_inflation, long_term::get_long_term_rates, real_yield::get_real_yield, tbill::get_tbill
+    admin::{get_ycharts_raw, invalidate_cache, refresh, RefreshQuery, YChartsRawQuery},
+    dashboard::{get_dashboard, DashboardQuery},
+    equity::{
+        get_avg_dividend_yield, get_drawdown_series, get_equity_data, get_equity_price, EquityQuery, get_equity_history,
+        get_equity_history_csv, CsvExportQuery, get_equity_history_range, get_equity_history_year, get_implied_return, get_latest_monthly_return, get_price_stream,
+        get_monthly_returns, get_monthly_returns_range, MonthlyQuery,
+        get_market_cap_to_gdp, get_market_metrics, get_market_metrics_range, MetricsQuery, get_real_returns, get_sharpe_like_ratio,
+    },
+    envelope::EnvelopeQuery,
+    error::ApiError,
+    inflation::{get_cpi_history, get_inflation, CpiHistoryQuery},
+    long_term::get_long_term_rates,
+    market_cache::get_market_cache,
+    quarterly::{get_quarterly_data, QuarterlyQuery},
+    real_yield::get_real_yield,
+    status::{get_readiness, get_status},
+    tbill::get_tbill,
+    version::get_version,
 };
-use crate::services::db::DbStore;
+use crate::services::db::{request_store_time, DbStore};
+
+/// Log method, path, status and total duration of every request, split into time spent
+/// waiting on the store and everything else when the server runs requests under
+/// `with_store_timing`. Health checks are logged at debug so load balancer probes don't
+/// drown out real traffic.
+fn request_timing() -> warp::log::Log<impl Fn(warp::log::Info<'_>) + Copy> {
+    warp::log::custom(|info| {
+        let elapsed = info.elapsed();
+        let breakdown = request_store_time()
+            .map(|store| {
+                let other = elapsed.saturating_sub(store);
+                format!(" (store {:.1} ms, compute {:.1} ms)", store.as_secs_f64() * 1000.0, other.as_secs_f64() * 1000.0)
+            })
+            .unwrap_or_default();
+        let line = format!(
+            "{} {} -> {} in {:.1} ms{}",
+            info.method(), info.path(), info.status().as_u16(), elapsed.as_secs_f64() * 1000.0, breakdown
+        );
+        if info.path().starts_with("/health") {
+            debug!(target: "macro_dashboard_acm::api", "{}", line);
+        } else {
+            info!(target: "macro_dashboard_acm::api", "{}", line);
+        }
+    })
+}
 
 /// Helper function to clone the db reference for each route
 fn with_db(
@@ -17,6 +59,43 @@ fn with_db(
     warp::any().map(move || db.clone())
 }
 
+/// Whether either the bearer token or the `X-API-Key` header matches the admin token
+fn is_admin_authorized(authorization: Option<&str>, api_key: Option<&str>, expected: &str) -> bool {
+    let bearer = authorization.and_then(|value| value.strip_prefix("Bearer "));
+    bearer == Some(expected) || api_key == Some(expected)
+}
+
+/// Reject requests that don't carry the `ADMIN_TOKEN` as a bearer token or `X-API-Key` header.
+/// Admin routes are disabled entirely when `ADMIN_TOKEN` isn't set.
+fn with_admin_auth() -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and(warp::header::optional::<String>("x-api-key"))
+        .and_then(|auth: Option<String>, api_key: Option<String>| async move {
+            let expected = match std::env::var("ADMIN_TOKEN") {
+                Ok(token) if !token.is_empty() => token,
+                _ => return Err(warp::reject::custom(ApiError::unauthorized("admin endpoints are disabled"))),
+            };
+
+            if is_admin_authorized(auth.as_deref(), api_key.as_deref(), &expected) {
+                Ok(())
+            } else {
+                Err(warp::reject::custom(ApiError::unauthorized("missing or invalid admin token")))
+            }
+        })
+        .untuple_one()
+}
+
+/// Default cap on request bodies for mutating routes; admin payloads are tiny or empty.
+/// Requests to those routes must send a `Content-Length` (0 for an empty body).
+const DEFAULT_MAX_BODY_BYTES: u64 = 1024;
+
+fn max_body_bytes() -> u64 {
+    std::env::var("MAX_BODY_BYTES")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_MAX_BODY_BYTES)
+}
+
 /// Handle all types of rejections that our API might encounter
 async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible> {
     let (code, message) = if err.is_not_found() {
@@ -27,8 +106,21 @@ async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible> {
             ApiError::ExternalServiceError(_) => warp::http::StatusCode::BAD_GATEWAY,
             ApiError::CacheError(_) => warp::http::StatusCode::SERVICE_UNAVAILABLE,
             ApiError::ParseError(_) => warp::http::StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => warp::http::StatusCode::UNAUTHORIZED,
+            ApiError::Unavailable(_) => warp::http::StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::NotFound(_) => warp::http::StatusCode::NOT_FOUND,
         };
+        // Handlers that propagate with `?` don't log the failure themselves
+        if code.is_server_error() {
+            error!("Request failed with {}: {}", code, api_error);
+        }
         (code, api_error.to_string())
+    } else if let Some(too_large) = err.find::<warp::reject::PayloadTooLarge>() {
+        (warp::http::StatusCode::PAYLOAD_TOO_LARGE, too_large.to_string())
+    } else if let Some(length_required) = err.find::<warp::reject::LengthRequired>() {
+        (warp::http::StatusCode::LENGTH_REQUIRED, length_required.to_string())
+    } else if let Some(invalid_query) = err.find::<warp::reject::InvalidQuery>() {
+        (warp::http::StatusCode::BAD_REQUEST, invalid_query.to_string())
     } else {
         error!("Unhandled rejection: {:?}", err);
         (
@@ -51,16 +143,34 @@ fn inflation_route(
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     warp::path!("api" / "v1" / "inflation")
         .and(warp::get())
+        .and(warp::query::<EnvelopeQuery>())
         .and(with_db(db))
         .and_then(get_inflation)
 }
 
+/// Set up CPI index history route
+fn cpi_history_route() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / "inflation" / "cpi_history")
+        .and(warp::get())
+        .and(warp::query::<CpiHistoryQuery>())
+        .and(warp::query::<EnvelopeQuery>())
+        .and_then(get_cpi_history)
+}
+
+/// Set up build version route
+fn version_route() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / "version")
+        .and(warp::get())
+        .and_then(get_version)
+}
+
 /// Set up T-bill route
 fn tbill_route(
     db: Arc<DbStore>,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     warp::path!("api" / "v1" / "tbill")
         .and(warp::get())
+        .and(warp::query::<EnvelopeQuery>())
         .and(with_db(db))
         .and_then(get_tbill)
 }
@@ -71,6 +181,7 @@ fn real_yield_route(
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     warp::path!("api" / "v1" / "real_yield")
         .and(warp::get())
+        .and(warp::query::<EnvelopeQuery>())
         .and(with_db(db))
         .and_then(get_real_yield)
 }
@@ -81,6 +192,7 @@ fn long_term_route(
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     warp::path!("api" / "v1" / "long_term_rates")
         .and(warp::get())
+        .and(warp::query::<EnvelopeQuery>())
         .and(with_db(db))
         .and_then(get_long_term_rates)
 }
@@ -91,39 +203,298 @@ fn equity_route(
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     warp::path!("api" / "v1" / "equity")
         .and(warp::get())
+        .and(warp::query::<EquityQuery>())
+        .and(warp::query::<EnvelopeQuery>())
         .and(with_db(db))
         .and_then(get_equity_data)
 }
 
+/// Set up lightweight price-only equity route
+fn equity_price_route(
+    db: Arc<DbStore>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / "equity" / "price")
+        .and(warp::get())
+        .and(warp::query::<EnvelopeQuery>())
+        .and(with_db(db))
+        .and_then(get_equity_price)
+}
+
+/// Set up market cap to GDP route
+fn market_cap_to_gdp_route(
+    db: Arc<DbStore>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / "equity" / "market_cap_to_gdp")
+        .and(warp::get())
+        .and(warp::query::<EnvelopeQuery>())
+        .and(with_db(db))
+        .and_then(get_market_cap_to_gdp)
+}
+
+/// Set up Server-Sent Events price stream route
+fn equity_stream_route(
+    db: Arc<DbStore>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / "equity" / "stream")
+        .and(warp::get())
+        .and(with_db(db))
+        .and_then(get_price_stream)
+}
+
 /// Set up equity history route
 fn equity_history_route(
     db: Arc<DbStore>,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     warp::path!("api" / "v1" / "equity" / "history" / "all")
         .and(warp::get())
+        .and(warp::query::<EnvelopeQuery>())
         .and(with_db(db))
         .and_then(get_equity_history)
 }
 
+/// Set up equity history CSV export route
+fn equity_history_csv_route(
+    db: Arc<DbStore>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / "equity" / "history" / "all.csv")
+        .and(warp::get())
+        .and(warp::query::<CsvExportQuery>())
+        .and(with_db(db))
+        .and_then(get_equity_history_csv)
+}
+
 /// Set up equity history range route
 fn equity_history_range_route(
     db: Arc<DbStore>,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     warp::path!("api" / "v1" / "equity" / "history" / i32 / i32)
         .and(warp::get())
+        .and(warp::query::<EnvelopeQuery>())
         .and(with_db(db))
         .and_then(get_equity_history_range)
 }
 
+/// Set up single-year equity history route
+fn equity_history_year_route(
+    db: Arc<DbStore>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / "equity" / "history" / "year" / String)
+        .and(warp::get())
+        .and(warp::query::<EnvelopeQuery>())
+        .and(with_db(db))
+        .and_then(get_equity_history_year)
+}
+
 fn market_metrics_route(
     db: Arc<DbStore>,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     warp::path!("api" / "v1" / "equity" / "metrics")
         .and(warp::get())
+        .and(warp::query::<MetricsQuery>())
+        .and(warp::query::<EnvelopeQuery>())
         .and(with_db(db))
         .and_then(get_market_metrics)
 }
 
+/// Set up market metrics year-range route
+fn market_metrics_range_route(
+    db: Arc<DbStore>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / "equity" / "metrics" / i32 / i32)
+        .and(warp::get())
+        .and(warp::query::<MetricsQuery>())
+        .and(warp::query::<EnvelopeQuery>())
+        .and(with_db(db))
+        .and_then(get_market_metrics_range)
+}
+
+/// Set up average dividend yield route
+fn avg_dividend_yield_route(
+    db: Arc<DbStore>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / "equity" / "avg_dividend_yield")
+        .and(warp::get())
+        .and(warp::query::<EnvelopeQuery>())
+        .and(with_db(db))
+        .and_then(get_avg_dividend_yield)
+}
+
+/// Set up real (inflation-adjusted) returns route
+fn real_returns_route(
+    db: Arc<DbStore>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / "equity" / "real_returns")
+        .and(warp::get())
+        .and(warp::query::<EnvelopeQuery>())
+        .and(with_db(db))
+        .and_then(get_real_returns)
+}
+
+/// Routes derived from the historical and cached equity data, grouped so the
+/// top-level filter chain stays shallow enough for the compiler
+fn equity_analytics_routes(
+    db: Arc<DbStore>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    market_metrics_route(db.clone())
+        .or(market_metrics_range_route(db.clone()))
+        .or(latest_monthly_route(db.clone()))
+        .or(monthly_route(db.clone()))
+        .or(monthly_range_route(db.clone()))
+        .or(quarterly_route(db.clone()))
+        .or(avg_dividend_yield_route(db.clone()))
+        .or(real_returns_route(db.clone()))
+        .or(sharpe_ratio_route(db.clone()))
+        .or(implied_return_route(db.clone()))
+        .or(market_cap_to_gdp_route(db.clone()))
+        .or(drawdown_series_route(db))
+}
+
+/// Set up Sharpe-like ratio route
+fn sharpe_ratio_route(
+    db: Arc<DbStore>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / "equity" / "sharpe_ratio")
+        .and(warp::get())
+        .and(warp::query::<EnvelopeQuery>())
+        .and(with_db(db))
+        .and_then(get_sharpe_like_ratio)
+}
+
+/// Set up Gordon-growth implied return route
+fn implied_return_route(
+    db: Arc<DbStore>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / "equity" / "implied_return")
+        .and(warp::get())
+        .and(warp::query::<EnvelopeQuery>())
+        .and(with_db(db))
+        .and_then(get_implied_return)
+}
+
+/// Set up drawdown series route
+fn drawdown_series_route(
+    db: Arc<DbStore>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / "equity" / "drawdown_series")
+        .and(warp::get())
+        .and(warp::query::<EnvelopeQuery>())
+        .and(with_db(db))
+        .and_then(get_drawdown_series)
+}
+
+/// Set up monthly returns route
+fn monthly_route(
+    db: Arc<DbStore>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / "equity" / "monthly")
+        .and(warp::get())
+        .and(warp::query::<MonthlyQuery>())
+        .and(warp::query::<EnvelopeQuery>())
+        .and(with_db(db))
+        .and_then(get_monthly_returns)
+}
+
+/// Set up quarterly data route
+fn quarterly_route(
+    db: Arc<DbStore>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / "equity" / "quarterly")
+        .and(warp::get())
+        .and(warp::query::<QuarterlyQuery>())
+        .and(warp::query::<EnvelopeQuery>())
+        .and(with_db(db))
+        .and_then(get_quarterly_data)
+}
+
+/// Set up monthly returns range route
+fn monthly_range_route(
+    db: Arc<DbStore>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / "equity" / "monthly" / String / String)
+        .and(warp::get())
+        .and(warp::query::<EnvelopeQuery>())
+        .and(with_db(db))
+        .and_then(get_monthly_returns_range)
+}
+
+/// Set up latest monthly return route
+fn latest_monthly_route(
+    db: Arc<DbStore>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / "equity" / "latest_monthly")
+        .and(warp::get())
+        .and(warp::query::<EnvelopeQuery>())
+        .and(with_db(db))
+        .and_then(get_latest_monthly_return)
+}
+
+/// Set up aggregate dashboard route
+fn dashboard_route(
+    db: Arc<DbStore>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / "dashboard")
+        .and(warp::get())
+        .and(warp::query::<DashboardQuery>())
+        .and(warp::query::<EnvelopeQuery>())
+        .and(with_db(db))
+        .and_then(get_dashboard)
+}
+
+/// Set up data status route
+fn status_route(
+    db: Arc<DbStore>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / "status")
+        .and(warp::get())
+        .and(with_db(db))
+        .and_then(get_status)
+}
+
+/// Set up raw market cache route
+fn market_cache_route(
+    db: Arc<DbStore>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / "market_cache")
+        .and(warp::get())
+        .and(warp::query::<EnvelopeQuery>())
+        .and(with_db(db))
+        .and_then(get_market_cache)
+}
+
+/// Set up admin YCharts diagnostic route
+fn ycharts_raw_route() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / "admin" / "ycharts_raw")
+        .and(warp::get())
+        .and(with_admin_auth())
+        .and(warp::query::<YChartsRawQuery>())
+        .and_then(get_ycharts_raw)
+}
+
+/// Set up admin cache invalidation route
+fn invalidate_cache_route(
+    db: Arc<DbStore>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / "admin" / "cache" / "invalidate")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(max_body_bytes()))
+        .and(with_admin_auth())
+        .and(with_db(db))
+        .and_then(invalidate_cache)
+}
+
+/// Set up admin market data refresh route
+fn refresh_route(
+    db: Arc<DbStore>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("api" / "v1" / "admin" / "refresh")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(max_body_bytes()))
+        .and(with_admin_auth())
+        .and(warp::query::<RefreshQuery>())
+        .and(with_db(db))
+        .and_then(refresh)
+}
+
 /// Combine all routes into a single API
 pub fn routes(db: Arc<DbStore>) -> impl Filter<Extract = impl Reply, Error = Infallible> + Clone {
     info!("Configuring routes...");
@@ -131,11 +502,11 @@ pub fn routes(db: Arc<DbStore>) -> impl Filter<Extract = impl Reply, Error = Inf
     // Set up CORS with more permissive settings
     let cors = warp::cors()
         .allow_any_origin()
-        .allow_headers(vec!["Content-Type", "Authorization", "Accept"])
+        .allow_headers(vec!["Content-Type", "Authorization", "Accept", "X-API-Key"])
         .allow_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"])
         .max_age(3600);
 
-    // Health check route
+    // Health check route; a cheap liveness probe that doesn't touch the store
     let health_route = warp::path!("health")
         .and(warp::get())
         .map(|| {
@@ -143,23 +514,249 @@ pub fn routes(db: Arc<DbStore>) -> impl Filter<Extract = impl Reply, Error = Inf
             warp::reply::json(&json!({"status": "ok"}))
         });
 
+    // Readiness check route
+    let ready_route = warp::path!("health" / "ready")
+        .and(warp::get())
+        .and(with_db(db.clone()))
+        .and_then(get_readiness);
+
     // Combine all routes
     let api = health_route
+        .or(ready_route)
+        .or(version_route())
         .or(inflation_route(db.clone()))
+        .or(cpi_history_route())
         .or(tbill_route(db.clone()))
         .or(real_yield_route(db.clone()))
         .or(long_term_route(db.clone()))
         .or(equity_route(db.clone()))
+        .or(equity_price_route(db.clone()))
+        .or(equity_stream_route(db.clone()))
         .or(equity_history_route(db.clone()))
+        .or(equity_history_csv_route(db.clone()))
         .or(equity_history_range_route(db.clone()))
-        .or(market_metrics_route(db.clone())); 
+        .or(equity_history_year_route(db.clone()))
+        .or(equity_analytics_routes(db.clone()))
+        .or(dashboard_route(db.clone()))
+        .or(status_route(db.clone()))
+        .or(market_cache_route(db.clone()))
+        .or(ycharts_raw_route())
+        .or(invalidate_cache_route(db.clone()))
+        .or(refresh_route(db.clone()));
 
-    // Add logging, CORS and error handling
+    // Add CORS, error handling and timing. Timing wraps the recovered filter so
+    // rejected requests are logged with the status the client actually receives.
     let api = api
-        .with(warp::log("macro_dashboard_acm::api"))
         .with(cors)
-        .recover(handle_rejection);
+        .recover(handle_rejection)
+        .with(request_timing());
 
     info!("All routes configured successfully.");
     api
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::HistoricalRecord;
+    use crate::services::local_store::LocalSnapshot;
+    use crate::services::db::CacheWriteMode;
+    use crate::services::sheets::RawMarketCache;
+    use crate::services::test_support::{local_db, local_db_with_mode, raw_market_cache, ENV_LOCK};
+
+    #[tokio::test]
+    async fn market_metrics_on_an_empty_sheet_is_503_not_zeroes() {
+        let store = local_db(&LocalSnapshot::default());
+
+        let response = warp::test::request()
+            .path("/api/v1/equity/metrics")
+            .reply(&routes(store.db.clone()))
+            .await;
+
+        assert_eq!(response.status(), 503);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert!(body["error"].as_str().unwrap().contains("No historical data"), "{}", body);
+    }
+
+    async fn metrics_body(db: &Arc<DbStore>, path: &str) -> (u16, serde_json::Value) {
+        let response = warp::test::request().path(path).reply(&routes(db.clone())).await;
+        (response.status().as_u16(), serde_json::from_slice(response.body()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn a_metrics_window_differs_from_the_full_range() {
+        // EPS grows 2% a year through the 2000s and 10% a year either side of them
+        let mut eps = 100.0;
+        let historical_data = (1990..=2020)
+            .map(|year| {
+                let record = HistoricalRecord { year, eps, ..Default::default() };
+                eps *= if (2000..2010).contains(&year) { 1.02 } else { 1.10 };
+                record
+            })
+            .collect();
+        let store = local_db(&LocalSnapshot { historical_data, ..Default::default() });
+
+        let (status, full) = metrics_body(&store.db, "/api/v1/equity/metrics").await;
+        assert_eq!(status, 200);
+        let (status, window) = metrics_body(&store.db, "/api/v1/equity/metrics/2000/2010").await;
+        assert_eq!(status, 200);
+
+        let window_cagr = window["past_earnings_cagr"].as_f64().unwrap();
+        assert!((window_cagr - 0.02).abs() < 1e-9, "{}", window);
+        assert!(full["past_earnings_cagr"].as_f64().unwrap() > window_cagr + 0.03, "{}", full);
+    }
+
+    #[tokio::test]
+    async fn a_metrics_range_must_start_before_it_ends() {
+        let store = local_db(&LocalSnapshot::default());
+
+        for path in ["/api/v1/equity/metrics/2010/2000", "/api/v1/equity/metrics/2010/2010"] {
+            let (status, body) = metrics_body(&store.db, path).await;
+            assert_eq!(status, 400, "{}: {}", path, body);
+            assert!(body["error"].as_str().unwrap().contains("must be before"), "{}", body);
+        }
+    }
+
+    async fn invalidate_with_headers(headers: &[(&str, &str)]) -> u16 {
+        let store = local_db(&LocalSnapshot { market_cache: Some(raw_market_cache()), ..Default::default() });
+        let mut request = warp::test::request()
+            .method("POST")
+            .path("/api/v1/admin/cache/invalidate")
+            .body("");
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let api = invalidate_cache_route(store.db.clone()).recover(handle_rejection);
+        request.reply(&api).await.status().as_u16()
+    }
+
+    #[tokio::test]
+    async fn admin_routes_accept_bearer_token_or_api_key() {
+        let _env = ENV_LOCK.lock().await;
+        std::env::set_var("ADMIN_TOKEN", "s3cret");
+
+        assert_eq!(invalidate_with_headers(&[("authorization", "Bearer s3cret")]).await, 200);
+        assert_eq!(invalidate_with_headers(&[("x-api-key", "s3cret")]).await, 200);
+        assert_eq!(invalidate_with_headers(&[]).await, 401);
+        assert_eq!(invalidate_with_headers(&[("authorization", "Bearer wrong")]).await, 401);
+        assert_eq!(invalidate_with_headers(&[("x-api-key", "wrong")]).await, 401);
+
+        std::env::remove_var("ADMIN_TOKEN");
+    }
+
+    #[tokio::test]
+    async fn handlers_never_write_in_scheduler_only_mode() {
+        // Every timestamp is at the epoch, so each handler would normally refresh and write
+        let snapshot = LocalSnapshot { market_cache: Some(raw_market_cache()), ..Default::default() };
+        let store = local_db_with_mode(&snapshot, CacheWriteMode::SchedulerOnly);
+        let before = std::fs::read(&store.path).unwrap();
+
+        let paths = ["/api/v1/inflation", "/api/v1/tbill", "/api/v1/long_term_rates", "/api/v1/dashboard"];
+        let api = inflation_route(store.db.clone())
+            .or(tbill_route(store.db.clone()))
+            .or(long_term_route(store.db.clone()))
+            .or(dashboard_route(store.db.clone()))
+            .recover(handle_rejection);
+        for path in paths {
+            let response = warp::test::request().path(path).reply(&api).await;
+            assert_eq!(response.status(), 200, "{}", path);
+        }
+
+        assert_eq!(std::fs::read(&store.path).unwrap(), before);
+    }
+
+    #[tokio::test]
+    async fn cached_equity_reads_never_fetch_or_write() {
+        // Every timestamp is at the epoch, so a normal read would refresh prices and write back
+        let market_cache = RawMarketCache { current_sp500_price: 5900.0, ..raw_market_cache() };
+        let store = local_db(&LocalSnapshot { market_cache: Some(market_cache), ..Default::default() });
+        let before = std::fs::read(&store.path).unwrap();
+
+        let response = warp::test::request()
+            .path("/api/v1/equity?cached=true")
+            .reply(&equity_route(store.db.clone()).recover(handle_rejection))
+            .await;
+
+        assert_eq!(response.status(), 200);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["current_sp500_price"], serde_json::json!(5900.0));
+        assert_eq!(std::fs::read(&store.path).unwrap(), before);
+    }
+
+    #[tokio::test]
+    async fn oversized_admin_bodies_are_rejected_with_413() {
+        let _env = ENV_LOCK.lock().await;
+        std::env::set_var("ADMIN_TOKEN", "s3cret");
+        std::env::set_var("MAX_BODY_BYTES", "16");
+        let store = local_db(&LocalSnapshot { market_cache: Some(raw_market_cache()), ..Default::default() });
+        let api = invalidate_cache_route(store.db.clone()).recover(handle_rejection);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/api/v1/admin/cache/invalidate")
+            .header("authorization", "Bearer s3cret")
+            .body("x".repeat(17))
+            .reply(&api)
+            .await;
+
+        std::env::remove_var("MAX_BODY_BYTES");
+        std::env::remove_var("ADMIN_TOKEN");
+        assert_eq!(response.status(), 413);
+    }
+
+    #[tokio::test]
+    async fn equity_price_is_served_without_reading_the_quarterly_sheet() {
+        let market_cache = RawMarketCache {
+            current_sp500_price: 5050.0,
+            daily_close_sp500_price: 5000.0,
+            ..raw_market_cache()
+        };
+        let store = local_db(&LocalSnapshot { market_cache: Some(market_cache), ..Default::default() });
+        let api = equity_price_route(store.db.clone())
+            .or(equity_route(store.db.clone()))
+            .recover(handle_rejection);
+
+        // Warm the in-memory market cache, then take the store away: any further read fails
+        store.db.get_market_cache().await.unwrap();
+        std::fs::remove_file(&store.path).unwrap();
+
+        let response = warp::test::request().path("/api/v1/equity/price").reply(&api).await;
+        assert_eq!(response.status(), 200);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["change"], serde_json::json!(50.0));
+        assert_eq!(body["change_percent"], serde_json::json!(0.01));
+
+        // The full equity view reads the quarterly sheet, so it can't answer
+        let response = warp::test::request().path("/api/v1/equity?cached=true").reply(&api).await;
+        assert_eq!(response.status(), 500);
+    }
+
+    #[tokio::test]
+    async fn service_errors_map_to_the_status_of_their_kind() {
+        use crate::services::calculations::NoHistoricalData;
+        use crate::services::equity::ScrapeError;
+        use crate::services::error::ServiceError;
+        use crate::services::google_oauth::TokenError;
+        use warp::http::StatusCode;
+
+        let cases: Vec<(anyhow::Error, StatusCode)> = vec![
+            (ServiceError::Http("connection reset".into()).into(), StatusCode::BAD_GATEWAY),
+            (ServiceError::Parse("bad json".into()).into(), StatusCode::BAD_GATEWAY),
+            (ServiceError::Sheets("quota exceeded".into()).into(), StatusCode::INTERNAL_SERVER_ERROR),
+            (ServiceError::NotFound("no row for 1999".into()).into(), StatusCode::NOT_FOUND),
+            (ServiceError::Auth(TokenError::Transient("timeout".into())).into(), StatusCode::BAD_GATEWAY),
+            (ServiceError::Auth(TokenError::Misconfigured("bad key".into())).into(), StatusCode::INTERNAL_SERVER_ERROR),
+            (TokenError::Transient("timeout".into()).into(), StatusCode::BAD_GATEWAY),
+            (TokenError::Misconfigured("bad key".into()).into(), StatusCode::INTERNAL_SERVER_ERROR),
+            (ScrapeError::StatNotFound.into(), StatusCode::BAD_GATEWAY),
+            (NoHistoricalData.into(), StatusCode::SERVICE_UNAVAILABLE),
+            (anyhow::anyhow!("something else"), StatusCode::INTERNAL_SERVER_ERROR),
+        ];
+
+        for (error, expected) in cases {
+            let message = error.to_string();
+            let rejection = warp::reject::custom(ApiError::from(error));
+            let response = handle_rejection(rejection).await.unwrap().into_response();
+            assert_eq!(response.status(), expected, "{}", message);
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use reqwest;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::env;
 use dotenv::dotenv;
 use std::error::Error as StdError;
@@ -7,11 +7,15 @@ pub type Result<T> = std::result::Result<T, Box<dyn StdError + Send + Sync>>;
 use std::fmt;
 use log::{info, error};  // Import the logging macros
 
+/// `status` BLS reports when it refused a request, e.g. after the daily query limit
+const STATUS_NOT_PROCESSED: &str = "REQUEST_NOT_PROCESSED";
+
 #[derive(Deserialize, Debug)]
 #[allow(non_snake_case, dead_code)]
 struct BlsResponse {
     status: String,
-    Results: Results,
+    /// Absent when the request wasn't processed
+    Results: Option<Results>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -34,6 +38,14 @@ struct DataPoint {
     value: String,
 }
 
+/// One monthly CPI-U index level
+#[derive(Debug, Clone, Serialize)]
+pub struct CpiObservation {
+    /// Month in `YYYY-MM` format
+    pub month: String,
+    pub value: f64,
+}
+
 // Define a custom error type
 #[derive(Debug)]
 struct DataFetchError {
@@ -56,21 +68,46 @@ impl fmt::Display for DataFetchError {
 
 impl StdError for DataFetchError {}
 
-pub async fn fetch_inflation_data() -> Result<f64> {
+/// Unregistered endpoint: 25 queries a day and 10 years of data
+const BLS_V1_URL: &str = "https://api.bls.gov/publicAPI/v1/timeseries/data/";
+/// Registered endpoint, used when `BLS_API_KEY` is set
+const BLS_V2_URL: &str = "https://api.bls.gov/publicAPI/v2/timeseries/data/";
+
+/// Registration key from `BLS_API_KEY`, if set and non-empty
+fn bls_api_key() -> Option<String> {
+    env::var("BLS_API_KEY").ok().filter(|key| !key.trim().is_empty())
+}
+
+/// Log which BLS API tier requests will use
+pub fn log_bls_tier() {
+    if bls_api_key().is_some() {
+        info!("BLS_API_KEY set, using the registered BLS v2 API");
+    } else {
+        info!("BLS_API_KEY not set, using the unregistered BLS v1 API (25 queries/day)");
+    }
+}
+
+/// Endpoint and JSON body for the CPI-U request; the key only goes in when there is one
+fn cpi_request(api_key: Option<&str>) -> (&'static str, serde_json::Value) {
+    match api_key {
+        Some(key) => (BLS_V2_URL, serde_json::json!({
+            "seriesid": ["CUUR0000SA0"],
+            "registrationkey": key,
+        })),
+        None => (BLS_V1_URL, serde_json::json!({
+            "seriesid": ["CUUR0000SA0"],
+        })),
+    }
+}
+
+/// POST the CPI-U series request to BLS and return the raw response body
+async fn fetch_cpi_response_text() -> Result<String> {
     dotenv().ok();  // Load environment variables from .env file
-    
-    let api_key = env::var("BLS_API_KEY").expect("BLS_API_KEY must be set");
-    info!("API Key loaded successfully");
 
-    let url = "https://api.bls.gov/publicAPI/v1/timeseries/data/";
+    let api_key = bls_api_key();
+    let (url, request_body) = cpi_request(api_key.as_deref());
     info!("Request URL: {}", url);
 
-    let request_body = serde_json::json!({
-        "seriesid": ["CUUR0000SA0"],
-        "registrationkey": api_key
-    });
-    info!("Request Body: {:?}", request_body);
-
     let client = reqwest::Client::new();
     let response = client.post(url)
         .json(&request_body)
@@ -80,41 +117,140 @@ pub async fn fetch_inflation_data() -> Result<f64> {
     let response_text = response.text().await?;
     info!("Response Text: {}", response_text);
 
-    let resp: BlsResponse = serde_json::from_str(&response_text)?;
-    info!("Parsed Response: {:?}", resp);
-
-    if let Some(series) = resp.Results.series.first() {
-        info!("Series Data: {:?}", series.data);
-
-        // Get the most recent data point
-        if let Some(current_data) = series.data.first() {
-            let current_year = &current_data.year;
-            let current_period = &current_data.period;
-            let current_value: f64 = current_data.value.parse().unwrap_or(0.0);
-
-            info!("Current Year: {}, Current Period: {}, Current Value: {}", current_year, current_period, current_value);
-
-            // Find the data point from the same month last year
-            if let Some(last_year_data) = series.data.iter().find(|d| {
-                &d.year == &(current_year.parse::<i32>().unwrap() - 1).to_string() && &d.period == current_period
-            }) {
-                let last_year_value: f64 = last_year_data.value.parse().unwrap_or(0.0);
-
-                info!("Last Year Value: {}", last_year_value);
-
-                // Calculate the yearly percentage change
-                let percentage_change = ((current_value - last_year_value) / last_year_value) * 100.0;
-                info!("Yearly Percentage Change: {}", percentage_change);
-                return Ok(percentage_change);
-            } else {
-                error!("No data found for the same month last year.");
-            }
-        } else {
-            error!("No current data found.");
-        }
-    } else {
-        error!("No series data found.");
+    Ok(response_text)
+}
+
+/// Parse a BLS CPI response into monthly index levels, newest first.
+/// Annual averages (period `M13`) are skipped.
+pub fn parse_cpi_history(response_text: &str) -> Result<Vec<CpiObservation>> {
+    // Check the status before the full shape: a refused request may carry an empty `Results`
+    let resp: serde_json::Value = serde_json::from_str(response_text)?;
+    if resp["status"] == STATUS_NOT_PROCESSED {
+        let messages: Vec<&str> = resp["message"].as_array()
+            .map(|messages| messages.iter().filter_map(|m| m.as_str()).collect())
+            .unwrap_or_default();
+        let details = format!("BLS did not process the request: {}", messages.join("; "));
+        error!("{}", details);
+        return Err(Box::new(DataFetchError::new(&details)));
     }
+    let resp: BlsResponse = serde_json::from_value(resp)?;
+
+    let series = resp.Results.as_ref()
+        .and_then(|results| results.series.first())
+        .ok_or_else(|| DataFetchError::new("No series data found"))?;
+
+    let mut history: Vec<CpiObservation> = series.data.iter()
+        .filter(|d| d.period.starts_with('M') && d.period != "M13")
+        .filter_map(|d| {
+            let value = d.value.parse::<f64>().ok()?;
+            Some(CpiObservation {
+                month: format!("{}-{}", d.year, &d.period[1..]),
+                value,
+            })
+        })
+        .collect();
+
+    history.sort_by(|a, b| b.month.cmp(&a.month));
+    Ok(history)
+}
+
+/// Fetch up to `months` of the most recent CPI-U index levels, newest first
+pub async fn fetch_cpi_history(months: usize) -> Result<Vec<CpiObservation>> {
+    let response_text = fetch_cpi_response_text().await?;
+    let mut history = parse_cpi_history(&response_text)?;
+    history.truncate(months);
+    Ok(history)
+}
 
-    Err(Box::new(DataFetchError::new("No data found")))
+/// Year-over-year CPI-U inflation as a decimal (0.029 == 2.9%) from a BLS response: the newest
+/// month against the same month a year earlier. Returns the newest month (`YYYY-MM`) with the rate.
+pub fn parse_inflation_rate(response_text: &str) -> Result<(String, f64)> {
+    let history = parse_cpi_history(response_text)?;
+    let current = history.first()
+        .ok_or_else(|| DataFetchError::new("No monthly CPI data found"))?;
+
+    let (year, month) = current.month.split_once('-')
+        .and_then(|(year, month)| Some((year.parse::<i32>().ok()?, month)))
+        .ok_or_else(|| DataFetchError::new(&format!("Unexpected CPI month '{}'", current.month)))?;
+    let prior_month = format!("{}-{}", year - 1, month);
+    let prior = history.iter()
+        .find(|obs| obs.month == prior_month)
+        .filter(|obs| obs.value > 0.0)
+        .ok_or_else(|| DataFetchError::new(&format!("No CPI data for {} to compare {} against", prior_month, current.month)))?;
+
+    let rate = (current.value - prior.value) / prior.value;
+    Ok((current.month.clone(), rate))
+}
+
+/// Latest year-over-year CPI-U inflation, as a decimal
+pub async fn fetch_inflation_data() -> Result<f64> {
+    let response_text = fetch_cpi_response_text().await?;
+    let (month, rate) = parse_inflation_rate(&response_text)?;
+    info!("Yearly CPI-U change for {}: {}", month, rate);
+    Ok(rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::test_support::ENV_LOCK;
+
+    const CPI_RESPONSE: &str = include_str!("../../tests/fixtures/bls_cpi_response.json");
+
+    #[test]
+    fn cpi_history_is_monthly_newest_first_without_annual_averages() {
+        let history = parse_cpi_history(CPI_RESPONSE).unwrap();
+
+        assert_eq!(history.len(), 24);
+        assert_eq!(history[0].month, "2024-12");
+        assert_eq!(history[0].value, 315.605);
+        assert_eq!(history[11].month, "2024-01");
+        assert_eq!(history[23].month, "2023-01");
+        assert_eq!(history[23].value, 299.170);
+        assert!(history.windows(2).all(|pair| pair[0].month > pair[1].month));
+    }
+
+    #[test]
+    fn inflation_is_a_decimal_from_the_newest_month_and_a_year_before() {
+        let (month, rate) = parse_inflation_rate(CPI_RESPONSE).unwrap();
+
+        assert_eq!(month, "2024-12");
+        // 315.605 against 306.746 for December 2023
+        assert!((rate - 0.028880).abs() < 1e-6, "{}", rate);
+    }
+
+    #[test]
+    fn a_request_bls_did_not_process_is_an_error_with_its_message() {
+        let response = include_str!("../../tests/fixtures/bls_request_not_processed.json");
+
+        let err = parse_inflation_rate(response).unwrap_err();
+
+        assert!(err.to_string().starts_with("BLS did not process the request"), "{}", err);
+        assert!(err.to_string().contains("daily threshold"), "{}", err);
+    }
+
+    #[test]
+    fn the_registration_key_is_sent_to_v2_only_when_there_is_one() {
+        let (url, body) = cpi_request(Some("abc123"));
+        assert_eq!(url, BLS_V2_URL);
+        assert_eq!(body["registrationkey"], "abc123");
+        assert_eq!(body["seriesid"], serde_json::json!(["CUUR0000SA0"]));
+
+        let (url, body) = cpi_request(None);
+        assert_eq!(url, BLS_V1_URL);
+        assert!(body.get("registrationkey").is_none(), "{}", body);
+        assert_eq!(body["seriesid"], serde_json::json!(["CUUR0000SA0"]));
+    }
+
+    #[test]
+    fn a_blank_bls_api_key_counts_as_unset() {
+        let _env = ENV_LOCK.blocking_lock();
+
+        std::env::set_var("BLS_API_KEY", "abc123");
+        assert_eq!(bls_api_key().as_deref(), Some("abc123"));
+        std::env::set_var("BLS_API_KEY", "  ");
+        assert_eq!(bls_api_key(), None);
+        std::env::remove_var("BLS_API_KEY");
+        assert_eq!(bls_api_key(), None);
+    }
 }
@@ -3,6 +3,20 @@ use serde::Serialize;
 use log::warn;
 use crate::models::HistoricalRecord;
 use anyhow::Result;
+use std::fmt;
+
+/// Returned when there are no historical records to compute metrics from,
+/// so callers can tell an empty sheet apart from metrics that are genuinely zero
+#[derive(Debug)]
+pub struct NoHistoricalData;
+
+impl fmt::Display for NoHistoricalData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "No historical data available")
+    }
+}
+
+impl std::error::Error for NoHistoricalData {}
 
 #[derive(Serialize)]
 pub struct MarketMetrics {
@@ -11,12 +25,241 @@ pub struct MarketMetrics {
     pub current_inflation_cagr: f64,
     pub past_earnings_cagr: f64,
     pub current_earnings_cagr: f64,
+    pub past_dividend_cagr: f64,
+    pub current_dividend_cagr: f64,
     pub past_cape_cagr: f64,
     pub current_cape_cagr: f64,
     pub past_returns_cagr: f64,
     pub current_returns_cagr: f64,
 }
 
+/// Average of the positive historical dividend yields and how many years went into it
+#[derive(Debug, Serialize)]
+pub struct AverageDividendYield {
+    /// `None` when no year has a positive dividend yield
+    pub avg_dividend_yield: Option<f64>,
+    pub years: usize,
+}
+
+/// Average dividend yield over years with a positive yield
+pub fn calculate_average_dividend_yield(historical_data: &[HistoricalRecord]) -> AverageDividendYield {
+    let dividend_yields: Vec<f64> = historical_data.iter()
+        .filter(|r| r.dividend_yield > 0.0)
+        .map(|r| r.dividend_yield)
+        .collect();
+
+    AverageDividendYield {
+        avg_dividend_yield: (!dividend_yields.is_empty()).then(|| calculate_average(&dividend_yields)),
+        years: dividend_yields.len(),
+    }
+}
+
+/// Drawdown of the cumulative-return index from its running peak for one year
+#[derive(Debug, Serialize)]
+pub struct DrawdownPoint {
+    pub year: i32,
+    pub drawdown: f64,
+}
+
+/// Build the underwater series: for each year, `wealth / running_peak - 1` where wealth is
+/// `1 + cumulative_return` (0.0 at a new peak, negative below it).
+/// Years with an empty cumulative return are skipped.
+pub fn calculate_drawdown_series(historical_data: &[HistoricalRecord]) -> Vec<DrawdownPoint> {
+    let mut sorted_data: Vec<&HistoricalRecord> = historical_data.iter()
+        .filter(|r| r.cumulative_return != 0.0)
+        .collect();
+    sorted_data.sort_by_key(|r| r.year);
+
+    let mut peak = 0.0_f64;
+    sorted_data.into_iter()
+        .filter_map(|r| {
+            let wealth = 1.0 + r.cumulative_return;
+            peak = peak.max(wealth);
+            (peak > 0.0).then(|| DrawdownPoint {
+                year: r.year,
+                drawdown: wealth / peak - 1.0,
+            })
+        })
+        .collect()
+}
+
+/// Price-to-earnings ratio, or `None` when EPS is zero or negative (the ratio would be
+/// infinite or negative and meaningless on a chart) or the price is missing
+pub fn calculate_pe_ratio(price: f64, eps: f64) -> Option<f64> {
+    (eps > 0.0 && price > 0.0).then(|| price / eps)
+}
+
+/// Earnings yield (EPS / price), with the same `None` cases as `calculate_pe_ratio`
+pub fn calculate_earnings_yield(price: f64, eps: f64) -> Option<f64> {
+    (eps > 0.0 && price > 0.0).then(|| eps / price)
+}
+
+/// Approximate a year's total return as price change plus that year's dividend yield, for
+/// years without monthly data. Ignores dividend reinvestment, so it runs slightly below
+/// the compounded monthly figure.
+pub fn approximate_total_return(start_price: f64, end_price: f64, dividend_yield: f64) -> Option<f64> {
+    (start_price > 0.0 && end_price > 0.0).then(|| end_price / start_price - 1.0 + dividend_yield)
+}
+
+/// Nominal and inflation-adjusted total return for one year
+#[derive(Debug, Serialize)]
+pub struct RealReturnPoint {
+    pub year: i32,
+    pub nominal_return: Option<f64>,
+    /// Year-over-year change in the inflation index
+    pub inflation: Option<f64>,
+    pub real_return: Option<f64>,
+}
+
+/// Real return from a nominal return and the inflation rate over the same period
+pub fn calculate_real_return(nominal_return: f64, inflation: f64) -> f64 {
+    (1.0 + nominal_return) / (1.0 + inflation) - 1.0
+}
+
+/// Real total return per year. The `inflation` column is a price index, so each year's
+/// inflation rate comes from the change versus the prior year; years without a prior
+/// index level or without a total return get `None`.
+pub fn calculate_real_return_series(historical_data: &[HistoricalRecord]) -> Vec<RealReturnPoint> {
+    let mut sorted_data: Vec<&HistoricalRecord> = historical_data.iter().collect();
+    sorted_data.sort_by_key(|r| r.year);
+
+    let mut previous: Option<&HistoricalRecord> = None;
+    sorted_data.into_iter()
+        .map(|r| {
+            let inflation = previous
+                .filter(|p| p.year == r.year - 1 && p.inflation > 0.0 && r.inflation > 0.0)
+                .map(|p| r.inflation / p.inflation - 1.0);
+            let nominal_return = (r.total_return != 0.0).then_some(r.total_return);
+            previous = Some(r);
+
+            RealReturnPoint {
+                year: r.year,
+                nominal_return,
+                inflation,
+                real_return: nominal_return.zip(inflation).map(|(n, i)| calculate_real_return(n, i)),
+            }
+        })
+        .collect()
+}
+
+/// A historical record plus its price in the latest year's dollars
+#[derive(Debug, Serialize)]
+pub struct HistoricalRecordWithRealPrice {
+    #[serde(flatten)]
+    pub record: HistoricalRecord,
+    /// `sp500_price` in the latest indexed year's dollars; `None` when the price is missing
+    /// or no index level has been seen yet
+    pub real_sp500_price: Option<f64>,
+}
+
+/// Attach a CPI-adjusted price to each record, sorted by year, in the latest indexed year's
+/// dollars. The `inflation` column is a price index, so each year's inflation rate is the
+/// change in the index since the last indexed year; the rates are chained into a cumulative
+/// deflator starting at 1.0 on the first indexed year. A year whose index is missing (0.0)
+/// carries the deflator forward rather than dropping out.
+pub fn calculate_real_price_series(historical_data: &[HistoricalRecord]) -> Vec<HistoricalRecordWithRealPrice> {
+    let mut sorted_data = historical_data.to_vec();
+    sorted_data.sort_by_key(|r| r.year);
+
+    let mut last_index: Option<f64> = None;
+    let mut deflator: Option<f64> = None;
+    let deflators: Vec<Option<f64>> = sorted_data.iter()
+        .map(|record| {
+            if record.inflation > 0.0 {
+                let inflation = last_index.map_or(0.0, |prior| record.inflation / prior - 1.0);
+                deflator = Some(deflator.unwrap_or(1.0) * (1.0 + inflation));
+                last_index = Some(record.inflation);
+            }
+            deflator
+        })
+        .collect();
+
+    let latest_deflator = deflators.iter().rev().find_map(|d| *d);
+
+    sorted_data.into_iter()
+        .zip(deflators)
+        .map(|(record, deflator)| {
+            let real_sp500_price = latest_deflator
+                .zip(deflator)
+                .filter(|_| record.sp500_price > 0.0)
+                .map(|(latest, year)| record.sp500_price * latest / year);
+
+            HistoricalRecordWithRealPrice { record, real_sp500_price }
+        })
+        .collect()
+}
+
+/// Sharpe-like ratio of annual total returns over the real T-bill yield, with its inputs
+#[derive(Debug, Serialize)]
+pub struct SharpeLikeRatio {
+    /// `(mean_return - risk_free_rate) / return_std_dev`; `None` when any input is missing
+    /// or the returns don't vary
+    pub sharpe_ratio: Option<f64>,
+    pub mean_return: Option<f64>,
+    /// Sample standard deviation of the annual total returns
+    pub return_std_dev: Option<f64>,
+    /// Real T-bill yield as a decimal
+    pub risk_free_rate: Option<f64>,
+    pub years: usize,
+}
+
+/// Mean and sample standard deviation, or `None` with fewer than two values
+pub fn calculate_mean_std_dev(values: &[f64]) -> Option<(f64, f64)> {
+    if values.len() < 2 {
+        return None;
+    }
+    let mean = calculate_average(values);
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64;
+    Some((mean, variance.sqrt()))
+}
+
+/// Sharpe-like ratio over the years with a total return. The sheet has no T-bill history,
+/// so `risk_free_rate` is a single (current) real yield applied to every year.
+pub fn calculate_sharpe_like_ratio(historical_data: &[HistoricalRecord], risk_free_rate: Option<f64>) -> SharpeLikeRatio {
+    let returns: Vec<f64> = historical_data.iter()
+        .filter(|r| r.total_return != 0.0)
+        .map(|r| r.total_return)
+        .collect();
+
+    let stats = calculate_mean_std_dev(&returns);
+    if stats.is_none() {
+        warn!("Insufficient total return data points ({}) for a Sharpe-like ratio", returns.len());
+    }
+
+    let sharpe_ratio = stats.zip(risk_free_rate)
+        .filter(|((_, std_dev), _)| *std_dev > 0.0)
+        .map(|((mean, std_dev), risk_free)| (mean - risk_free) / std_dev);
+
+    SharpeLikeRatio {
+        sharpe_ratio,
+        mean_return: stats.map(|(mean, _)| mean),
+        return_std_dev: stats.map(|(_, std_dev)| std_dev),
+        risk_free_rate,
+        years: returns.len(),
+    }
+}
+
+/// Decimal places used when rounding fetched values if no override is set
+const DEFAULT_INGESTION_DECIMALS: u32 = 2;
+
+/// Decimal places to keep for a fetched value, overridable with `env_var` (0-10)
+pub fn ingestion_decimals(env_var: &str) -> u32 {
+    std::env::var(env_var)
+        .ok()
+        .and_then(|v| v.trim().parse::<u32>().ok())
+        .filter(|d| *d <= 10)
+        .unwrap_or(DEFAULT_INGESTION_DECIMALS)
+}
+
+/// Round to a fixed number of decimal places, so re-fetching a value that only
+/// differs in upstream noise (e.g. 34.12 vs 34.1199) stores the same number
+pub fn round_to(value: f64, decimals: u32) -> f64 {
+    let factor = 10f64.powi(decimals as i32);
+    (value * factor).round() / factor
+}
+
+/// CAGR of a level series (prices, earnings, CAPE, a price index). A level of zero or below
+/// has no meaningful growth rate, so those inputs yield 0.0.
 fn calculate_cagr(start_value: f64, end_value: f64, years: f64) -> f64 {
     if start_value <= 0.0 || end_value <= 0.0 || years <= 0.0 {
         0.0
@@ -25,6 +268,38 @@ fn calculate_cagr(start_value: f64, end_value: f64, years: f64) -> f64 {
     }
 }
 
+/// CAGR between two points of a cumulative return series (0.5 == +50% since inception).
+/// Unlike a level, a cumulative return is legitimately negative or crosses zero, so the
+/// growth is taken on the `1 + r` wealth factors. Only a total loss (`1 + r <= 0`) has no rate.
+fn calculate_return_cagr(start_return: f64, end_return: f64, years: f64) -> f64 {
+    calculate_cagr(1.0 + start_return, 1.0 + end_return, years)
+}
+
+/// How `compute_cagrs` reads a metric's values
+#[derive(Clone, Copy)]
+enum Growth {
+    /// Positive levels; zero or below means missing
+    Level,
+    /// Cumulative returns; zero is an empty sheet cell, negatives are real
+    Return,
+}
+
+impl Growth {
+    fn is_valid(self, value: f64) -> bool {
+        match self {
+            Growth::Level => value > 0.0,
+            Growth::Return => value != 0.0,
+        }
+    }
+
+    fn cagr(self, start_value: f64, end_value: f64, years: f64) -> f64 {
+        match self {
+            Growth::Level => calculate_cagr(start_value, end_value, years),
+            Growth::Return => calculate_return_cagr(start_value, end_value, years),
+        }
+    }
+}
+
 fn calculate_average(values: &[f64]) -> f64 {
     if values.is_empty() {
         0.0
@@ -33,25 +308,33 @@ fn calculate_average(values: &[f64]) -> f64 {
     }
 }
 
-pub fn calculate_market_metrics(historical_data: &[HistoricalRecord]) -> Result<MarketMetrics> {
+/// Years covered by the "current" CAGRs unless a caller asks for another window
+pub const DEFAULT_CAGR_WINDOW_YEARS: i32 = 10;
+
+/// Full-period ("past") and trailing `window_years` ("current") CAGRs over `historical_data`
+pub fn calculate_market_metrics(historical_data: &[HistoricalRecord], window_years: i32) -> Result<MarketMetrics> {
+    if historical_data.is_empty() {
+        return Err(NoHistoricalData.into());
+    }
+
     let mut sorted_data = historical_data.to_vec();
     sorted_data.sort_by_key(|r| r.year);
 
     // Calculate average dividend yield
-    let dividend_yields: Vec<f64> = sorted_data.iter()
-        .filter(|r| r.dividend_yield > 0.0)
-        .map(|r| r.dividend_yield)
-        .collect();
-    let avg_dividend_yield = calculate_average(&dividend_yields);
+    let avg_dividend_yield = calculate_average_dividend_yield(&sorted_data)
+        .avg_dividend_yield
+        .unwrap_or(0.0);
 
     // Helper to compute CAGRs for a metric with validation and logging
     fn compute_cagrs(
         data: &[HistoricalRecord],
         metric_extractor: fn(&HistoricalRecord) -> f64,
         metric_name: &'static str,
+        growth: Growth,
+        window_years: i32,
     ) -> (f64, f64) {
         let valid_entries: Vec<&HistoricalRecord> = data.iter()
-            .filter(|r| metric_extractor(r) > 0.0)
+            .filter(|r| growth.is_valid(metric_extractor(r)))
             .collect();
     
         let (past_cagr, current_cagr) = if valid_entries.len() < 2 {
@@ -62,21 +345,34 @@ pub fn calculate_market_metrics(historical_data: &[HistoricalRecord]) -> Result<
             let first = valid_entries.first().unwrap();
             let last = valid_entries.last().unwrap();
             let past_years = (last.year - first.year) as f64;
-            let past_cagr = calculate_cagr(metric_extractor(first), metric_extractor(last), past_years);
+            let past_cagr = if past_years > 0.0 {
+                growth.cagr(metric_extractor(first), metric_extractor(last), past_years)
+            } else {
+                warn!("All valid {} data points fall in {}, no span for CAGR calculation", metric_name, last.year);
+                0.0
+            };
     
-            // Calculate current CAGR (10-year window)
-            let target_start_year = last.year - 10; // Use the last valid entry's year -10
+            // Calculate current CAGR (trailing window ending at the last valid entry's year)
+            let target_start_year = last.year - window_years;
             let start = valid_entries.iter()
                 .take_while(|r| r.year <= target_start_year)
                 .last();
     
             let current_cagr = match start {
-                Some(start_entry) => {
+                Some(start_entry) if last.year > start_entry.year => {
                     let years = (last.year - start_entry.year) as f64;
-                    calculate_cagr(metric_extractor(start_entry), metric_extractor(last), years)
+                    growth.cagr(metric_extractor(start_entry), metric_extractor(last), years)
+                }
+                Some(start_entry) => {
+                    // Same-year start and end points would divide by a zero span
+                    warn!(
+                        "{} {}-year CAGR start point shares year {} with the end point, using the full-period CAGR",
+                        metric_name, window_years, start_entry.year
+                    );
+                    past_cagr
                 }
                 None => {
-                    warn!("No valid {} start point found for 10-year CAGR calculation", metric_name);
+                    warn!("No valid {} start point found for {}-year CAGR calculation", metric_name, window_years);
                     0.0
                 }
             };
@@ -89,13 +385,15 @@ pub fn calculate_market_metrics(historical_data: &[HistoricalRecord]) -> Result<
 
     // Calculate metrics for each category
     let (past_inflation_cagr, current_inflation_cagr) = 
-        compute_cagrs(&sorted_data, |r| r.inflation, "inflation");
+        compute_cagrs(&sorted_data, |r| r.inflation, "inflation", Growth::Level, window_years);
     let (past_earnings_cagr, current_earnings_cagr) = 
-        compute_cagrs(&sorted_data, |r| r.eps, "earnings");
+        compute_cagrs(&sorted_data, |r| r.eps, "earnings", Growth::Level, window_years);
+    let (past_dividend_cagr, current_dividend_cagr) = 
+        compute_cagrs(&sorted_data, |r| r.dividend, "dividends", Growth::Level, window_years);
     let (past_cape_cagr, current_cape_cagr) = 
-        compute_cagrs(&sorted_data, |r| r.cape, "CAPE");
+        compute_cagrs(&sorted_data, |r| r.cape, "CAPE", Growth::Level, window_years);
     let (past_returns_cagr, current_returns_cagr) = 
-        compute_cagrs(&sorted_data, |r| r.cumulative_return, "returns");
+        compute_cagrs(&sorted_data, |r| r.cumulative_return, "returns", Growth::Return, window_years);
 
     Ok(MarketMetrics {
         avg_dividend_yield,
@@ -103,9 +401,208 @@ pub fn calculate_market_metrics(historical_data: &[HistoricalRecord]) -> Result<
         current_inflation_cagr,
         past_earnings_cagr,
         current_earnings_cagr,
+        past_dividend_cagr,
+        current_dividend_cagr,
         past_cape_cagr,
         current_cape_cagr,
         past_returns_cagr,
         current_returns_cagr,
     })
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(year: i32) -> HistoricalRecord {
+        HistoricalRecord { year, ..Default::default() }
+    }
+
+    #[test]
+    fn drawdown_returns_to_zero_at_a_new_peak() {
+        let data: Vec<HistoricalRecord> = [(2019, 1.0), (2020, 0.5), (2021, 1.2)]
+            .into_iter()
+            .map(|(year, cumulative_return)| HistoricalRecord { cumulative_return, ..record(year) })
+            .collect();
+
+        let series = calculate_drawdown_series(&data);
+
+        let drawdowns: Vec<(i32, f64)> = series.iter().map(|p| (p.year, p.drawdown)).collect();
+        assert_eq!(drawdowns[0], (2019, 0.0));
+        assert_eq!(drawdowns[1].0, 2020);
+        assert!((drawdowns[1].1 - (1.5 / 2.0 - 1.0)).abs() < 1e-12);
+        assert_eq!(drawdowns[2], (2021, 0.0));
+    }
+
+    #[test]
+    fn cagrs_are_zero_when_every_point_falls_in_one_year() {
+        let data = vec![
+            HistoricalRecord { eps: 100.0, cape: 30.0, ..record(2020) },
+            HistoricalRecord { eps: 120.0, cape: 33.0, ..record(2020) },
+        ];
+
+        let metrics = calculate_market_metrics(&data, DEFAULT_CAGR_WINDOW_YEARS).unwrap();
+
+        assert_eq!(metrics.past_earnings_cagr, 0.0);
+        assert_eq!(metrics.current_earnings_cagr, 0.0);
+        assert_eq!(metrics.past_cape_cagr, 0.0);
+        assert_eq!(metrics.current_cape_cagr, 0.0);
+    }
+
+    #[test]
+    fn real_returns_deflate_by_the_change_in_the_cpi_level() {
+        let data = vec![
+            HistoricalRecord { inflation: 100.0, total_return: 0.05, ..record(2020) },
+            HistoricalRecord { inflation: 103.0, total_return: 0.10, ..record(2021) },
+            HistoricalRecord { inflation: 0.0, total_return: 0.08, ..record(2022) },
+        ];
+
+        let series = calculate_real_return_series(&data);
+
+        assert_eq!(series[0].inflation, None);
+        assert_eq!(series[0].real_return, None);
+
+        let inflation = series[1].inflation.unwrap();
+        assert!((inflation - 0.03).abs() < 1e-12);
+        assert!((series[1].real_return.unwrap() - (1.10 / 1.03 - 1.0)).abs() < 1e-12);
+
+        assert_eq!(series[2].nominal_return, Some(0.08));
+        assert_eq!(series[2].inflation, None);
+        assert_eq!(series[2].real_return, None);
+    }
+
+    #[test]
+    fn pe_and_earnings_yield_are_none_for_non_positive_eps() {
+        assert_eq!(calculate_pe_ratio(5000.0, 200.0), Some(25.0));
+        assert_eq!(calculate_earnings_yield(5000.0, 200.0), Some(0.04));
+
+        for eps in [-50.0, 0.0] {
+            assert_eq!(calculate_pe_ratio(5000.0, eps), None, "eps {}", eps);
+            assert_eq!(calculate_earnings_yield(5000.0, eps), None, "eps {}", eps);
+        }
+    }
+
+    #[test]
+    fn sharpe_like_ratio_uses_the_sample_std_dev_of_returns() {
+        // Mean 0.2, sample standard deviation 0.1; years without a return are skipped
+        let data = vec![
+            HistoricalRecord { total_return: 0.1, ..record(2020) },
+            HistoricalRecord { total_return: 0.2, ..record(2021) },
+            HistoricalRecord { total_return: 0.3, ..record(2022) },
+            record(2023),
+        ];
+
+        let ratio = calculate_sharpe_like_ratio(&data, Some(0.05));
+
+        assert_eq!(ratio.years, 3);
+        assert!((ratio.mean_return.unwrap() - 0.2).abs() < 1e-12);
+        assert!((ratio.return_std_dev.unwrap() - 0.1).abs() < 1e-12);
+        assert!((ratio.sharpe_ratio.unwrap() - 1.5).abs() < 1e-9);
+
+        assert_eq!(calculate_sharpe_like_ratio(&data, None).sharpe_ratio, None);
+    }
+
+    #[test]
+    fn sharpe_like_ratio_is_none_with_fewer_than_two_returns() {
+        let data = vec![HistoricalRecord { total_return: 0.1, ..record(2020) }];
+
+        let ratio = calculate_sharpe_like_ratio(&data, Some(0.05));
+
+        assert_eq!(ratio.years, 1);
+        assert_eq!(ratio.mean_return, None);
+        assert_eq!(ratio.return_std_dev, None);
+        assert_eq!(ratio.sharpe_ratio, None);
+    }
+
+    #[test]
+    fn dividend_cagrs_cover_the_full_period_and_the_trailing_window() {
+        let data = vec![
+            HistoricalRecord { dividend: 10.0, ..record(2000) },
+            HistoricalRecord { dividend: 20.0, ..record(2010) },
+            // An empty cell is skipped rather than read as a dividend cut
+            HistoricalRecord { dividend: 0.0, ..record(2015) },
+            HistoricalRecord { dividend: 80.0, ..record(2020) },
+        ];
+
+        let metrics = calculate_market_metrics(&data, DEFAULT_CAGR_WINDOW_YEARS).unwrap();
+
+        assert!((metrics.past_dividend_cagr - (8.0_f64.powf(1.0 / 20.0) - 1.0)).abs() < 1e-12);
+        assert!((metrics.current_dividend_cagr - (4.0_f64.powf(1.0 / 10.0) - 1.0)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn dividend_cagrs_need_two_valid_points() {
+        let data = vec![
+            HistoricalRecord { dividend: 10.0, ..record(2000) },
+            record(2010),
+        ];
+
+        let metrics = calculate_market_metrics(&data, DEFAULT_CAGR_WINDOW_YEARS).unwrap();
+
+        assert_eq!(metrics.past_dividend_cagr, 0.0);
+        assert_eq!(metrics.current_dividend_cagr, 0.0);
+    }
+
+    #[test]
+    fn return_cagr_grows_the_wealth_factors_through_a_negative_start() {
+        let expected = (1.5_f64 / 0.9).powf(1.0 / 5.0) - 1.0;
+        assert!((calculate_return_cagr(-0.1, 0.5, 5.0) - expected).abs() < 1e-12);
+        assert_eq!(calculate_return_cagr(-1.0, 0.5, 5.0), 0.0);
+
+        let data = vec![
+            HistoricalRecord { cumulative_return: -0.1, ..record(2015) },
+            HistoricalRecord { cumulative_return: 0.5, ..record(2020) },
+        ];
+        let metrics = calculate_market_metrics(&data, DEFAULT_CAGR_WINDOW_YEARS).unwrap();
+        assert!((metrics.past_returns_cagr - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn drawdown_measures_wealth_when_cumulative_returns_are_negative() {
+        // Wealth 0.8 is the first peak even though the cumulative return is a loss
+        let data: Vec<HistoricalRecord> = [(2019, -0.2), (2020, -0.5), (2021, -0.1)]
+            .into_iter()
+            .map(|(year, cumulative_return)| HistoricalRecord { cumulative_return, ..record(year) })
+            .collect();
+
+        let series = calculate_drawdown_series(&data);
+
+        assert_eq!(series.len(), 3);
+        assert_eq!(series[0].drawdown, 0.0);
+        assert!((series[1].drawdown - (0.5 / 0.8 - 1.0)).abs() < 1e-12);
+        assert_eq!(series[2].drawdown, 0.0);
+    }
+
+    #[test]
+    fn real_prices_chain_yearly_inflation_to_the_latest_year() {
+        // 2% then 3% inflation; 2023 has no index level yet and carries 2022's deflator
+        let data = vec![
+            HistoricalRecord { sp500_price: 3000.0, inflation: 100.0, ..record(2020) },
+            HistoricalRecord { sp500_price: 3500.0, inflation: 102.0, ..record(2021) },
+            HistoricalRecord { sp500_price: 4000.0, inflation: 105.06, ..record(2022) },
+            HistoricalRecord { sp500_price: 4200.0, ..record(2023) },
+        ];
+
+        let series = calculate_real_price_series(&data);
+
+        let real: Vec<f64> = series.iter().map(|p| p.real_sp500_price.unwrap()).collect();
+        assert!((real[0] - 3000.0 * 1.02 * 1.03).abs() < 1e-9, "{:?}", real);
+        assert!((real[1] - 3500.0 * 1.03).abs() < 1e-9, "{:?}", real);
+        assert!((real[2] - 4000.0).abs() < 1e-9, "{:?}", real);
+        assert!((real[3] - 4200.0).abs() < 1e-9, "{:?}", real);
+    }
+
+    #[test]
+    fn real_prices_are_none_before_the_first_index_level_or_without_a_price() {
+        let data = vec![
+            HistoricalRecord { sp500_price: 2900.0, ..record(2019) },
+            HistoricalRecord { inflation: 100.0, ..record(2020) },
+            HistoricalRecord { sp500_price: 3500.0, inflation: 102.0, ..record(2021) },
+        ];
+
+        let series = calculate_real_price_series(&data);
+
+        assert_eq!(series[0].real_sp500_price, None);
+        assert_eq!(series[1].real_sp500_price, None);
+        assert_eq!(series[2].real_sp500_price, Some(3500.0));
+    }
+}
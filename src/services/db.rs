@@ -1,47 +1,262 @@
 // src/services/db.rs
 
-use std::collections::HashMap;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use chrono::{DateTime, Utc};
-use crate::services::sheets::{SheetsStore, SheetsConfig, RawMarketCache};
-use crate::models::{MarketCache, Timestamps, HistoricalRecord};
+use log::{debug, warn};
+use tokio::sync::{RwLock, RwLockWriteGuard};
+use crate::services::local_store::LocalStore;
+use crate::services::sheets::{dedupe_historical_years, rows_fingerprint, HeaderDrift, SheetsStore, SheetsConfig, RawMarketCache};
+use crate::models::{MarketCache, Timestamps, HistoricalRecord, MonthlyData, QuarterlyData};
 use anyhow::Result;
 
+/// How many times a read-modify-write is retried when the sheet changes between read and write
+pub const MAX_WRITE_ATTEMPTS: usize = 3;
+
+/// How long an in-memory copy of the market cache is served before re-reading Sheets,
+/// unless overridden
+pub const DEFAULT_MARKET_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Market cache TTL from `MARKET_CACHE_TTL_SECS` (default 60; 0 disables the in-memory copy)
+pub fn market_cache_ttl_from_env() -> Duration {
+    std::env::var("MARKET_CACHE_TTL_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_MARKET_CACHE_TTL)
+}
+
+/// How long the in-memory historical series is served before re-reading Sheets. Writes made
+/// through `DbStore` drop it straight away; the TTL only bounds how long manual sheet edits
+/// take to show up.
+const HISTORICAL_CACHE_TTL: Duration = Duration::from_secs(600);
+
+/// Who is allowed to write refreshed values back to the market cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheWriteMode {
+    /// Handlers refresh stale values on demand and write them through (default)
+    WriteThrough,
+    /// Only the scheduler refreshes and writes; handlers serve whatever is cached
+    SchedulerOnly,
+}
+
+impl CacheWriteMode {
+    /// Read from `CACHE_WRITE_MODE` (`scheduler` or `write_through`)
+    pub fn from_env() -> Self {
+        match std::env::var("CACHE_WRITE_MODE").unwrap_or_default().trim().to_lowercase().as_str() {
+            "scheduler" | "scheduler_only" => CacheWriteMode::SchedulerOnly,
+            _ => CacheWriteMode::WriteThrough,
+        }
+    }
+}
+
+/// Backing storage for the dashboard: Google Sheets in production, a JSON file for local development
+pub enum Store {
+    Sheets(SheetsStore),
+    Local(LocalStore),
+}
+
+tokio::task_local! {
+    /// Time the current request has spent waiting on the store
+    static STORE_TIME: Cell<Duration>;
+}
+
+/// Run `fut` while adding up the time its store calls take, for `request_store_time`
+pub async fn with_store_timing<F: Future>(fut: F) -> F::Output {
+    STORE_TIME.scope(Cell::new(Duration::ZERO), fut).await
+}
+
+/// Store time accumulated so far by the current request, or `None` outside
+/// `with_store_timing`. Calls made concurrently (e.g. via `try_join!`) each count in full.
+pub fn request_store_time() -> Option<Duration> {
+    STORE_TIME.try_with(Cell::get).ok()
+}
+
+/// Forward a call to whichever backend is active, logging how long the round-trip took
+/// and adding it to the current request's store time
+macro_rules! dispatch {
+    ($self:ident, $method:ident($($arg:expr),*)) => {{
+        let start = Instant::now();
+        let result = match $self {
+            Store::Sheets(s) => s.$method($($arg),*).await.map_err(anyhow::Error::from),
+            Store::Local(s) => s.$method($($arg),*).await,
+        };
+        let elapsed = start.elapsed();
+        let _ = STORE_TIME.try_with(|total| total.set(total.get() + elapsed));
+        debug!("Store {} took {} ms", stringify!($method), elapsed.as_millis());
+        result
+    }};
+}
+
+impl Store {
+    pub async fn get_market_cache(&self) -> Result<RawMarketCache> {
+        dispatch!(self, get_market_cache())
+    }
+
+    pub async fn update_market_cache(&self, cache: &RawMarketCache) -> Result<()> {
+        dispatch!(self, update_market_cache(cache))
+    }
+
+    pub async fn get_quarterly_data(&self) -> Result<Vec<QuarterlyData>> {
+        dispatch!(self, get_quarterly_data())
+    }
+
+    pub async fn update_quarterly_data(&self, data: &[QuarterlyData]) -> Result<()> {
+        dispatch!(self, update_quarterly_data(data))
+    }
+
+    pub async fn get_monthly_data(&self) -> Result<Vec<MonthlyData>> {
+        dispatch!(self, get_monthly_data())
+    }
+
+    pub async fn update_monthly_data(&self, data: &[MonthlyData]) -> Result<()> {
+        dispatch!(self, update_monthly_data(data))
+    }
+
+    pub async fn get_historical_data(&self) -> Result<Vec<HistoricalRecord>> {
+        dispatch!(self, get_historical_data())
+    }
+
+    pub async fn update_historical_record(&self, record: &HistoricalRecord) -> Result<()> {
+        dispatch!(self, update_historical_record(record))
+    }
+
+    pub async fn update_historical_records(&self, records: &[HistoricalRecord]) -> Result<()> {
+        dispatch!(self, update_historical_records(records))
+    }
+
+    pub async fn write_historical_rows(&self, rows: &[(usize, HistoricalRecord)]) -> Result<()> {
+        dispatch!(self, write_historical_rows(rows))
+    }
+
+    /// Header drift per sheet; the local store is typed JSON and has no header rows to drift
+    pub async fn check_schema(&self) -> Result<Vec<HeaderDrift>> {
+        match self {
+            Store::Sheets(s) => Ok(s.check_schema().await?),
+            Store::Local(_) => Ok(Vec::new()),
+        }
+    }
+}
+
+/// Split QuarterlyData rows into the cache's per-quarter dividend, actual EPS and estimated
+/// EPS maps, skipping empty cells
+fn quarterly_maps(rows: &[QuarterlyData]) -> (BTreeMap<String, f64>, BTreeMap<String, f64>, BTreeMap<String, f64>) {
+    let collect = |value: fn(&QuarterlyData) -> Option<f64>| -> BTreeMap<String, f64> {
+        rows.iter()
+            .filter_map(|row| value(row).map(|v| (row.quarter.clone(), v)))
+            .collect()
+    };
+    (collect(|q| q.dividend), collect(|q| q.eps_actual), collect(|q| q.eps_estimated))
+}
+
 pub struct DbStore {
-    pub sheets_store: SheetsStore,
+    pub store: Store,
+    pub write_mode: CacheWriteMode,
+    market_cache: RwLock<Option<(MarketCache, Instant)>>,
+    market_cache_ttl: Duration,
+    historical_data: RwLock<Option<(Vec<HistoricalRecord>, Instant)>>,
+    /// Bumped by every historical write, so a read that loaded before the write can tell
+    /// its result is stale and not cache it
+    historical_generation: AtomicU64,
+    /// Held shared by every write to the store; shutdown takes it exclusively to let
+    /// in-flight writes finish and keep new ones from starting
+    writes: RwLock<()>,
 }
 
 impl DbStore {
+    /// Connect to Google Sheets, serving market cache reads from memory for `market_cache_ttl`
     pub async fn new(
         spreadsheet_id: &str,
-        service_account_json_path: &str
+        service_account_json_path: &str,
+        market_cache_ttl: Duration,
     ) -> Result<Self> {
         let config = SheetsConfig {
             spreadsheet_id: spreadsheet_id.to_string(),
             service_account_json_path: service_account_json_path.to_string(),
         };
 
-        let sheets_store = SheetsStore::new(config);
+        Ok(Self::with_store(Store::Sheets(SheetsStore::new(config)), market_cache_ttl))
+    }
 
-        Ok(DbStore {
-            sheets_store
-        })
+    /// Use a JSON file (see `cargo run --bin seed_local`) instead of Google Sheets
+    pub fn new_local(path: &str) -> Self {
+        Self::with_store(Store::Local(LocalStore::new(path)), market_cache_ttl_from_env())
+    }
+
+    fn with_store(store: Store, market_cache_ttl: Duration) -> Self {
+        DbStore {
+            store,
+            write_mode: CacheWriteMode::from_env(),
+            market_cache: RwLock::new(None),
+            market_cache_ttl,
+            historical_data: RwLock::new(None),
+            historical_generation: AtomicU64::new(0),
+            writes: RwLock::new(()),
+        }
+    }
+
+    /// Wait for in-flight writes to finish and block any further ones while the guard is held
+    pub async fn block_writes(&self) -> RwLockWriteGuard<'_, ()> {
+        self.writes.write().await
+    }
+
+    /// Whether request handlers may fetch fresh data and write it to the cache
+    pub fn handlers_may_write(&self) -> bool {
+        self.write_mode == CacheWriteMode::WriteThrough
     }
 
     pub async fn get_market_cache(&self) -> Result<MarketCache> {
-        let raw_cache: RawMarketCache = self.sheets_store.get_market_cache().await?;
+        if let Some((cache, loaded_at)) = self.market_cache.read().await.as_ref() {
+            if loaded_at.elapsed() < self.market_cache_ttl {
+                debug!("Serving market cache from memory");
+                return Ok(cache.clone());
+            }
+        }
+
+        let cache = self.read_market_cache().await?;
+        *self.market_cache.write().await = Some((cache.clone(), Instant::now()));
+        Ok(cache)
+    }
+
+    /// Drop the in-memory market cache so the next read goes to Sheets
+    pub async fn invalidate_market_cache(&self) {
+        *self.market_cache.write().await = None;
+    }
+
+    async fn read_market_cache(&self) -> Result<MarketCache> {
+        // The quarterly maps live in the QuarterlyData sheet rather than the cache row
+        let (raw_cache, quarterly) = tokio::try_join!(
+            self.store.get_market_cache(),
+            self.store.get_quarterly_data(),
+        )?;
+        let (quarterly_dividends, eps_actual, eps_estimated) = quarterly_maps(&quarterly);
+        let yahoo_price = DateTime::parse_from_rfc3339(&raw_cache.timestamp_yahoo)?.with_timezone(&Utc);
+        // Caches written before the market-hours timestamp existed fall back to the Yahoo poll time
+        let last_market_price_update = DateTime::parse_from_rfc3339(&raw_cache.timestamp_market_price)
+            .map(|ts| ts.with_timezone(&Utc))
+            .unwrap_or(yahoo_price);
+        // Caches without a GDP column yet read as never fetched
+        let gdp_data = DateTime::parse_from_rfc3339(&raw_cache.timestamp_gdp)
+            .map(|ts| ts.with_timezone(&Utc))
+            .unwrap_or(DateTime::UNIX_EPOCH);
 
         Ok(MarketCache {
             timestamps: Timestamps {
-                yahoo_price: DateTime::parse_from_rfc3339(&raw_cache.timestamp_yahoo)?.with_timezone(&Utc),
+                yahoo_price,
                 ycharts_data: DateTime::parse_from_rfc3339(&raw_cache.timestamp_ycharts)?.with_timezone(&Utc),
                 treasury_data: DateTime::parse_from_rfc3339(&raw_cache.timestamp_treasury)?.with_timezone(&Utc),
                 bls_data: DateTime::parse_from_rfc3339(&raw_cache.timestamp_bls)?.with_timezone(&Utc),
+                last_market_price_update,
+                gdp_data,
             },
             daily_close_sp500_price: raw_cache.daily_close_sp500_price,
             current_sp500_price: raw_cache.current_sp500_price,
-            quarterly_dividends: HashMap::new(),
-            eps_actual: HashMap::new(),
-            eps_estimated: HashMap::new(),
+            quarterly_dividends,
+            eps_actual,
+            eps_estimated,
             current_cape: raw_cache.current_cape,
             cape_period: raw_cache.cape_period,
             tips_yield_20y: raw_cache.tips_yield_20y,
@@ -50,6 +265,10 @@ impl DbStore {
             inflation_rate: raw_cache.inflation_rate,
             latest_monthly_return: raw_cache.latest_monthly_return,  // Added
             latest_month: raw_cache.latest_month,                    // Added
+            last_year_close_captured: raw_cache.last_year_close_captured,
+            gdp: raw_cache.gdp,
+            gdp_period: raw_cache.gdp_period,
+            price_source: raw_cache.price_source,
         })
     }
 
@@ -69,22 +288,320 @@ impl DbStore {
             inflation_rate: cache.inflation_rate,
             latest_monthly_return: cache.latest_monthly_return,  // Added
             latest_month: cache.latest_month.clone(),           // Added
+            last_year_close_captured: cache.last_year_close_captured,
+            timestamp_market_price: cache.timestamps.last_market_price_update.to_rfc3339(),
+            gdp: cache.gdp,
+            gdp_period: cache.gdp_period.clone(),
+            timestamp_gdp: cache.timestamps.gdp_data.to_rfc3339(),
+            price_source: cache.price_source.clone(),
         };
 
-        self.sheets_store.update_market_cache(&raw_cache).await?;
+        let _write = self.writes.read().await;
+        self.store.update_market_cache(&raw_cache).await?;
+        *self.market_cache.write().await = Some((cache.clone(), Instant::now()));
         Ok(())
     }
 
+    /// Monthly total returns in chronological order
+    pub async fn get_monthly_data(&self) -> Result<Vec<MonthlyData>> {
+        let mut months = self.store.get_monthly_data().await?;
+        months.sort_by(|a, b| a.month.cmp(&b.month));
+        Ok(months)
+    }
+
+    pub async fn update_monthly_data(&self, data: &[MonthlyData]) -> Result<()> {
+        let _write = self.writes.read().await;
+        self.store.update_monthly_data(data).await
+    }
+
+    pub async fn update_quarterly_data(&self, data: &[QuarterlyData]) -> Result<()> {
+        let _write = self.writes.read().await;
+        self.store.update_quarterly_data(data).await
+    }
+
+    /// Historical records with at most one entry per year (duplicate rows are merged),
+    /// served from memory until a write invalidates them or the TTL runs out
     pub async fn get_historical_data(&self) -> Result<Vec<HistoricalRecord>> {
-        self.sheets_store.get_historical_data().await
+        if let Some((records, loaded_at)) = self.historical_data.read().await.as_ref() {
+            if loaded_at.elapsed() < HISTORICAL_CACHE_TTL {
+                debug!("Serving historical data from memory");
+                return Ok(records.clone());
+            }
+        }
+
+        let generation = self.historical_generation.load(Ordering::SeqCst);
+        let records = dedupe_historical_years(self.store.get_historical_data().await?);
+        self.cache_historical_data(records.clone(), generation).await;
+        Ok(records)
+    }
+
+    /// Keep `records` in memory unless a write has invalidated the series since `generation`
+    /// was read, in which case they may predate it. Returns whether they were kept.
+    async fn cache_historical_data(&self, records: Vec<HistoricalRecord>, generation: u64) -> bool {
+        let mut cached = self.historical_data.write().await;
+        if self.historical_generation.load(Ordering::SeqCst) != generation {
+            debug!("Historical data changed while it was being read, not caching it");
+            return false;
+        }
+        *cached = Some((records, Instant::now()));
+        true
+    }
+
+    /// Drop the in-memory historical series so the next read goes to Sheets
+    pub async fn invalidate_historical_data(&self) {
+        let mut cached = self.historical_data.write().await;
+        self.historical_generation.fetch_add(1, Ordering::SeqCst);
+        *cached = None;
     }
 
     pub async fn get_historical_year(&self, year: i32) -> Result<Option<HistoricalRecord>> {
-        let records = self.sheets_store.get_historical_data().await?;
+        let records = self.get_historical_data().await?;
         Ok(records.into_iter().find(|r| r.year == year))
     }
 
     pub async fn update_historical_record(&self, record: HistoricalRecord) -> Result<()> {
-        self.sheets_store.update_historical_record(&record).await
+        self.update_historical_records(std::slice::from_ref(&record)).await
+    }
+
+    /// Overwrite several existing historical years with one store write. Like the stores'
+    /// `update_historical_records`, fails without writing if any year has no row; use
+    /// `modify_historical_years` to append.
+    pub async fn update_historical_records(&self, records: &[HistoricalRecord]) -> Result<()> {
+        let _write = self.writes.read().await;
+        let result = self.store.update_historical_records(records).await;
+        self.invalidate_historical_data().await;
+        result
+    }
+
+    /// Read-modify-write a single historical year. The year's row is appended if it doesn't exist.
+    /// If the sheet changes between our read and write, `apply` is re-run against the fresh rows.
+    pub async fn modify_historical_year<F>(&self, year: i32, apply: F) -> Result<()>
+    where
+        F: Fn(&mut HistoricalRecord),
+    {
+        self.modify_historical_years(&[year], |_, record| apply(record)).await
+    }
+
+    /// `modify_historical_year` for several years at once: one read, then every changed row in a
+    /// single store write. Years without a row are appended in the order given.
+    pub async fn modify_historical_years<F>(&self, years: &[i32], apply: F) -> Result<()>
+    where
+        F: Fn(i32, &mut HistoricalRecord),
+    {
+        let years: Vec<i32> = years.iter().enumerate()
+            .filter(|(i, year)| !years[..*i].contains(year))
+            .map(|(_, &year)| year)
+            .collect();
+        if years.is_empty() {
+            return Ok(());
+        }
+
+        let mut records = self.store.get_historical_data().await?;
+
+        for attempt in 1..=MAX_WRITE_ATTEMPTS {
+            let fingerprint = rows_fingerprint(&records);
+            let mut rows = Vec::with_capacity(years.len());
+            let mut next_row = records.len();
+            for &year in &years {
+                // Duplicate rows for the year are merged and written to the last occurrence
+                let (row_index, mut record) = match records.iter().rposition(|r| r.year == year) {
+                    Some(idx) => {
+                        let same_year = records.iter().filter(|r| r.year == year).cloned().collect();
+                        (idx, dedupe_historical_years(same_year).remove(0))
+                    }
+                    None => {
+                        next_row += 1;
+                        (next_row - 1, HistoricalRecord { year, ..Default::default() })
+                    }
+                };
+                apply(year, &mut record);
+                rows.push((row_index, record));
+            }
+
+            let current = self.store.get_historical_data().await?;
+            if rows_fingerprint(&current) != fingerprint {
+                warn!("HistoricalData changed while updating {:?} (attempt {}), re-applying to fresh data", years, attempt);
+                records = current;
+                continue;
+            }
+
+            // A failed write may still have landed, so drop the cached series either way
+            let _write = self.writes.read().await;
+            let result = self.store.write_historical_rows(&rows).await;
+            self.invalidate_historical_data().await;
+            return result;
+        }
+
+        Err(anyhow::anyhow!(
+            "HistoricalData kept changing while updating {:?}, giving up after {} attempts",
+            years, MAX_WRITE_ATTEMPTS
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::local_store::LocalSnapshot;
+    use crate::services::test_support::{local_db, mock_sheets_store, raw_market_cache};
+
+    #[tokio::test]
+    async fn store_time_is_accumulated_only_inside_a_timed_request() {
+        let store = local_db(&LocalSnapshot { market_cache: Some(raw_market_cache()), ..Default::default() });
+
+        let store_time = with_store_timing(async {
+            assert_eq!(request_store_time(), Some(Duration::ZERO));
+            store.db.store.get_market_cache().await.unwrap();
+            let after_one = request_store_time().unwrap();
+            store.db.store.get_quarterly_data().await.unwrap();
+            let after_two = request_store_time().unwrap();
+            assert!(after_two > after_one);
+            after_two
+        }).await;
+
+        assert!(store_time > Duration::ZERO);
+        store.db.store.get_market_cache().await.unwrap();
+        assert_eq!(request_store_time(), None);
+    }
+
+    #[tokio::test]
+    async fn a_read_that_straddles_a_write_does_not_cache_stale_rows() {
+        let snapshot = LocalSnapshot {
+            historical_data: vec![HistoricalRecord { year: 2023, eps: 200.0, ..Default::default() }],
+            ..Default::default()
+        };
+        let store = local_db(&snapshot);
+
+        // A read loads the rows, then a write lands before it stores them
+        let generation = store.db.historical_generation.load(Ordering::SeqCst);
+        let stale = store.db.store.get_historical_data().await.unwrap();
+        store.db
+            .update_historical_record(HistoricalRecord { year: 2023, eps: 210.0, ..Default::default() })
+            .await
+            .unwrap();
+
+        assert!(!store.db.cache_historical_data(stale, generation).await);
+        let record = store.db.get_historical_year(2023).await.unwrap().unwrap();
+        assert_eq!(record.eps, 210.0);
+
+        // With no write in between the result is cached as usual
+        let generation = store.db.historical_generation.load(Ordering::SeqCst);
+        let fresh = store.db.store.get_historical_data().await.unwrap();
+        assert!(store.db.cache_historical_data(fresh, generation).await);
+    }
+
+    #[tokio::test]
+    async fn a_second_read_within_the_ttl_does_not_touch_the_store() {
+        let snapshot = |tbill_yield| LocalSnapshot {
+            market_cache: Some(RawMarketCache { tbill_yield, ..raw_market_cache() }),
+            ..Default::default()
+        };
+        let store = local_db(&snapshot(4.5));
+        let path = store.path.to_str().unwrap();
+        let cached = DbStore::with_store(Store::Local(LocalStore::new(path)), Duration::from_secs(60));
+        let uncached = DbStore::with_store(Store::Local(LocalStore::new(path)), Duration::ZERO);
+
+        with_store_timing(async {
+            assert_eq!(cached.get_market_cache().await.unwrap().tbill_yield, 4.5);
+            let after_first_read = request_store_time().unwrap();
+
+            // Change the stored value behind the cache's back
+            std::fs::write(&store.path, serde_json::to_vec(&snapshot(5.0)).unwrap()).unwrap();
+
+            assert_eq!(cached.get_market_cache().await.unwrap().tbill_yield, 4.5);
+            assert_eq!(request_store_time().unwrap(), after_first_read);
+
+            // A zero TTL reads through every time
+            assert_eq!(uncached.get_market_cache().await.unwrap().tbill_yield, 5.0);
+            assert!(request_store_time().unwrap() > after_first_read);
+        }).await;
+    }
+
+    #[tokio::test]
+    async fn quarterly_rows_fill_the_market_cache_maps() {
+        let rows = vec![
+            QuarterlyData { quarter: "2024Q2".into(), dividend: Some(18.9), eps_actual: Some(54.0), eps_estimated: None },
+            QuarterlyData { quarter: "2024Q3".into(), dividend: None, eps_actual: None, eps_estimated: Some(60.0) },
+        ];
+
+        let (dividends, eps_actual, eps_estimated) = quarterly_maps(&rows);
+        assert_eq!(dividends, BTreeMap::from([("2024Q2".to_string(), 18.9)]));
+        assert_eq!(eps_actual, BTreeMap::from([("2024Q2".to_string(), 54.0)]));
+        assert_eq!(eps_estimated, BTreeMap::from([("2024Q3".to_string(), 60.0)]));
+
+        // And they reach the cache read from the store
+        let store = local_db(&LocalSnapshot {
+            market_cache: Some(raw_market_cache()),
+            quarterly_data: rows,
+            ..Default::default()
+        });
+        let cache = store.db.get_market_cache().await.unwrap();
+        assert_eq!(cache.quarterly_dividends, dividends);
+        assert_eq!(cache.eps_actual, eps_actual);
+        assert_eq!(cache.eps_estimated, eps_estimated);
+    }
+
+    #[tokio::test]
+    async fn modifying_three_years_is_one_sheets_write() {
+        let (sheets, _key, writes) = mock_sheets_store(serde_json::json!([
+            ["year", "sp500_price"],
+            ["2022", "3839.5"],
+            ["2023", "4769.83"],
+        ]));
+        let db = DbStore::with_store(Store::Sheets(sheets), Duration::ZERO);
+
+        // Two existing rows and one appended
+        db.modify_historical_years(&[2022, 2023, 2024, 2023], |year, record| record.eps = year as f64)
+            .await
+            .unwrap();
+
+        assert_eq!(writes.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn updating_records_is_one_sheets_write_and_a_missing_year_writes_nothing() {
+        let (sheets, _key, writes) = mock_sheets_store(serde_json::json!([
+            ["year", "sp500_price"],
+            ["2021", "4766.18"],
+            ["2022", "3839.5"],
+            ["2023", "4769.83"],
+        ]));
+        let db = DbStore::with_store(Store::Sheets(sheets), Duration::ZERO);
+        let records: Vec<HistoricalRecord> = (2021..=2023)
+            .map(|year| HistoricalRecord { year, eps: 200.0, ..Default::default() })
+            .collect();
+
+        db.update_historical_records(&records).await.unwrap();
+        assert_eq!(writes.load(Ordering::SeqCst), 1);
+
+        let missing = HistoricalRecord { year: 2024, eps: 210.0, ..Default::default() };
+        assert!(db.update_historical_records(&[records[0].clone(), missing]).await.is_err());
+        assert_eq!(writes.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn modified_years_are_written_in_place_and_missing_ones_appended() {
+        let snapshot = LocalSnapshot {
+            historical_data: vec![
+                HistoricalRecord { year: 2022, sp500_price: 3839.5, ..Default::default() },
+                HistoricalRecord { year: 2023, sp500_price: 4769.83, ..Default::default() },
+            ],
+            ..Default::default()
+        };
+        let store = local_db(&snapshot);
+
+        store.db
+            .modify_historical_years(&[2025, 2023, 2024], |year, record| record.eps = year as f64)
+            .await
+            .unwrap();
+
+        let records = store.db.get_historical_data().await.unwrap();
+        let years: Vec<(i32, f64, f64)> = records.iter().map(|r| (r.year, r.sp500_price, r.eps)).collect();
+        assert_eq!(years, vec![
+            (2022, 3839.5, 0.0),
+            (2023, 4769.83, 2023.0),
+            (2025, 0.0, 2025.0),
+            (2024, 0.0, 2024.0),
+        ]);
     }
 }
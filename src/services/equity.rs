@@ -2,337 +2,1167 @@
 use reqwest::{self, Client};
 use scraper::{Html, Selector};
 use serde::Serialize;
-use log::{error,info};
+use log::{error, info, warn};
 use regex::Regex;
-use chrono::{DateTime, Utc, NaiveTime, Datelike, Duration};
-use std::collections::HashMap;
-use std::sync::Arc;
+use chrono::{DateTime, Utc, NaiveDate, NaiveTime, Datelike, Duration};
+use std::collections::BTreeMap;
+use std::sync::{Arc, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use tokio::sync::{broadcast, Mutex};
 use chrono_tz::US::Central;
 use anyhow::Result;
 
-use crate::models::{HistoricalRecord, MonthlyData, QuarterlyData};
+use crate::models::{HistoricalRecord, MarketCache, MonthlyData, QuarterlyData};
+use crate::services::error::ServiceError;
 
-use super::{calculations::{calculate_market_metrics, MarketMetrics}, db::DbStore};
+use super::{calculations::{
+    calculate_average_dividend_yield, calculate_drawdown_series, calculate_earnings_yield, calculate_market_metrics, calculate_real_price_series,
+    approximate_total_return, calculate_pe_ratio, calculate_real_return_series, calculate_sharpe_like_ratio, ingestion_decimals,
+    round_to, AverageDividendYield, DrawdownPoint, HistoricalRecordWithRealPrice, MarketMetrics, RealReturnPoint, SharpeLikeRatio,
+}, db::{DbStore, MAX_WRITE_ATTEMPTS}, http::{HttpFetcher, ReqwestFetcher, RetryPolicy}, sheets::rows_fingerprint};
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct QuarterlyValue {
     pub final_quarter: String,
     pub value: f64,
+    /// Set when a TTM value was annualized from fewer than four quarters
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub estimated: bool,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct MarketData {
+    /// Tracked index prices; see `MarketCache` for why they keep the `sp500` names
     pub daily_close_sp500_price: f64,
     pub current_sp500_price: f64,
     pub ttm_dividend: Option<QuarterlyValue>,
+    pub ttm_dividend_yield: Option<f64>,
+    /// Sum of the four most recent actual EPS quarters (may be negative)
+    pub ttm_eps: Option<QuarterlyValue>,
+    /// `None` when trailing EPS is zero or negative
+    pub trailing_pe: Option<f64>,
+    /// Trailing EPS over price; `None` when trailing EPS is zero or negative
+    pub earnings_yield: Option<f64>,
     pub latest_eps_actual: Option<QuarterlyValue>,
     pub estimated_eps_sum: Option<QuarterlyValue>,
     pub cape: f64,
     pub cape_period: String,
+    /// Which source the current price came from (`yahoo` or `stooq`); empty for caches
+    /// written before this was recorded
+    pub price_source: String,
     pub last_update: DateTime<Utc>
 }
 
+/// Result of a single YCharts scrape, including the raw stat text it was parsed from
+#[derive(Debug, Serialize)]
+pub struct YChartsRawValue {
+    pub url: String,
+    pub raw_text: String,
+    pub period: String,
+    pub value: f64,
+}
+
+const YCHARTS_DIVIDEND_URL: &str = "https://ycharts.com/indicators/sp_500_dividends_per_share";
+const YCHARTS_EPS_URL: &str = "https://ycharts.com/indicators/sp_500_eps";
+const YCHARTS_FORWARD_EPS_URL: &str = "https://ycharts.com/indicators/sp_500_earnings_per_share_forward_estimate";
+const YCHARTS_CAPE_URL: &str = "https://ycharts.com/indicators/cyclically_adjusted_pe_ratio";
+const YCHARTS_MONTHLY_RETURN_URL: &str = "https://ycharts.com/indicators/sp_500_monthly_total_return";
+
+/// Indicators whose key stat is a percentage, even when the page leaves the '%' off
+const YCHARTS_PERCENTAGE_URLS: [&str; 1] = [YCHARTS_MONTHLY_RETURN_URL];
+
+/// YCharts indicator pages we scrape, keyed by the short name used in the API
+pub const YCHARTS_INDICATORS: [(&str, &str); 5] = [
+    ("dividend", YCHARTS_DIVIDEND_URL),
+    ("eps", YCHARTS_EPS_URL),
+    ("forward_eps", YCHARTS_FORWARD_EPS_URL),
+    ("cape", YCHARTS_CAPE_URL),
+    ("monthly_return", YCHARTS_MONTHLY_RETURN_URL),
+];
+
+pub fn ycharts_indicator_url(indicator: &str) -> Option<&'static str> {
+    YCHARTS_INDICATORS.iter()
+        .find(|(name, _)| *name == indicator)
+        .map(|(_, url)| *url)
+}
+
 #[derive(Debug)]
 struct YChartsData {
-    quarterly_dividends: HashMap<String, f64>,
-    eps_actual: HashMap<String, f64>,
-    eps_estimated: HashMap<String, f64>,
+    quarterly_dividends: BTreeMap<String, f64>,
+    eps_actual: BTreeMap<String, f64>,
+    eps_estimated: BTreeMap<String, f64>,
     cape: (f64, String), // (value, period)
     monthly_return: Option<(String, f64)>, // (period, value)
 }
 
-async fn get_quarterly_calculations(db: &Arc<DbStore>) -> Result<(Option<QuarterlyValue>, Option<QuarterlyValue>, Option<QuarterlyValue>)> {
-    let quarterly_data = db.sheets_store.get_quarterly_data().await?;
+impl YChartsData {
+    /// True when every indicator failed to fetch
+    fn is_empty(&self) -> bool {
+        self.quarterly_dividends.is_empty()
+            && self.eps_actual.is_empty()
+            && self.eps_estimated.is_empty()
+            && self.cape.1.is_empty()
+            && self.monthly_return.is_none()
+    }
+}
+
+/// Fewest quarters a TTM value may be built from, from `TTM_MIN_QUARTERS` (3 or 4, default 4).
+/// With three, a missing fourth quarter is annualized and the result is flagged as estimated.
+fn ttm_min_quarters() -> usize {
+    let Ok(raw) = std::env::var("TTM_MIN_QUARTERS") else {
+        return 4;
+    };
+    match raw.trim().parse::<usize>() {
+        Ok(n) if (3..=4).contains(&n) => n,
+        _ => {
+            warn!("Ignoring TTM_MIN_QUARTERS={:?}: must be 3 or 4", raw);
+            4
+        }
+    }
+}
+
+/// Sum the latest four quarters of `value`, labelled with the latest of them. With fewer than
+/// four but at least `min_quarters`, the sum is annualized (`sum * 4 / n`) and marked
+/// `estimated`. `sorted_data` must be in ascending quarter order.
+fn sum_latest_quarters(
+    sorted_data: &[QuarterlyData],
+    value: fn(&QuarterlyData) -> Option<f64>,
+    min_quarters: usize,
+) -> Option<QuarterlyValue> {
+    let mut quarters_found = 0;
+    let mut sum = 0.0;
+    let mut final_quarter = None;
+
+    for record in sorted_data.iter().rev() {
+        if let Some(v) = value(record) {
+            if quarters_found == 0 {
+                final_quarter = Some(record.quarter.clone());
+            }
+            sum += v;
+            quarters_found += 1;
+            if quarters_found == 4 {
+                break;
+            }
+        }
+    }
+
+    if quarters_found == 0 || quarters_found < min_quarters.min(4) {
+        return None;
+    }
+
+    Some(QuarterlyValue {
+        final_quarter: final_quarter.unwrap(),
+        value: sum * 4.0 / quarters_found as f64,
+        estimated: quarters_found < 4,
+    })
+}
+
+/// Sum of the next four estimated quarters after the latest actual, labelled with the last
+/// of them. `sorted_data` must be in ascending quarter order. `None` unless four consecutive
+/// rows carry an estimate.
+fn sum_next_estimated_quarters(sorted_data: &[QuarterlyData]) -> Option<QuarterlyValue> {
+    // Estimates are only forward-looking from the quarter after the latest actual;
+    // anything at or before it has been superseded by reported earnings
+    let first_forward_idx = sorted_data.iter()
+        .rposition(|q| q.eps_actual.is_some())
+        .map_or(0, |idx| idx + 1);
+
+    // Start at the first forward quarter with an estimate and stop at the first gap
+    let mut sum = 0.0;
+    let mut quarters_found = 0;
+    let mut final_quarter = None;
+    for record in sorted_data[first_forward_idx..].iter()
+        .skip_while(|q| q.eps_estimated.is_none())
+        .take(4)
+    {
+        let Some(eps) = record.eps_estimated else {
+            break;
+        };
+        sum += eps;
+        quarters_found += 1;
+        final_quarter = Some(record.quarter.clone());
+    }
+
+    if quarters_found < 4 {
+        return None;
+    }
+
+    Some(QuarterlyValue {
+        final_quarter: final_quarter?,
+        value: sum,
+        estimated: false,
+    })
+}
+
+/// `(year, quarter)` of a `YYYYQn` label for ordering; malformed parts sort as 0
+pub fn quarter_sort_key(label: &str) -> (i32, i32) {
+    let year: i32 = label.get(..4).and_then(|y| y.parse().ok()).unwrap_or(0);
+    let quarter: i32 = label.get(5..).and_then(|q| q.parse().ok()).unwrap_or(0);
+    (year, quarter)
+}
+
+/// Values derived from the QuarterlyData sheet
+struct QuarterlyCalculations {
+    ttm_dividend: Option<QuarterlyValue>,
+    ttm_eps: Option<QuarterlyValue>,
+    latest_eps_actual: Option<QuarterlyValue>,
+    estimated_eps_sum: Option<QuarterlyValue>,
+}
+
+async fn get_quarterly_calculations(db: &Arc<DbStore>) -> Result<QuarterlyCalculations> {
+    let quarterly_data = db.store.get_quarterly_data().await?;
     
     // Sort quarters in descending order (most recent first)
     let mut sorted_data = quarterly_data.clone();
-    sorted_data.sort_by(|a, b| {
-        let parse_quarter = |q: &str| {
-            let year: i32 = q[..4].parse().unwrap_or(0);
-            let quarter: i32 = q[5..].parse().unwrap_or(0);
-            (year, quarter)
-        };
-        let (year_b, q_b) = parse_quarter(&b.quarter);
-        let (year_a, q_a) = parse_quarter(&a.quarter);
-        (year_a, q_a).cmp(&(year_b, q_b))
-    });
-
-    // Calculate TTM dividend (sum of most recent 4 quarters)
-    let ttm_dividend = {
-        let mut quarters_found = 0;
-        let mut sum = 0.0;
-        let mut final_quarter = None;
-
-        for record in sorted_data.iter().rev() {
-            if let Some(dividend) = record.dividend {
-                if quarters_found == 0 {
-                    final_quarter = Some(record.quarter.clone());
-                }
-                sum += dividend;
-                quarters_found += 1;
-                if quarters_found == 4 {
-                    break;
-                }
-            }
-        }
+    sorted_data.sort_by_key(|q| quarter_sort_key(&q.quarter));
 
-        if quarters_found == 4 {
-            Some(QuarterlyValue {
-                final_quarter: final_quarter.unwrap(),
-                value: sum,
-            })
-        } else {
-            None
-        }
-    };
+    // Calculate TTM dividend and EPS (sum of most recent 4 quarters)
+    let min_quarters = ttm_min_quarters();
+    let ttm_dividend = sum_latest_quarters(&sorted_data, |q| q.dividend, min_quarters);
+    let ttm_eps = sum_latest_quarters(&sorted_data, |q| q.eps_actual, min_quarters);
 
     // Get latest actual EPS
     let latest_eps_actual = sorted_data.iter().rev()
         .find(|q| q.eps_actual.is_some())
         .map(|q| QuarterlyValue {
             final_quarter: q.quarter.clone(),
-            value: q.eps_actual.unwrap()
+            value: q.eps_actual.unwrap(),
+            estimated: false,
         });
 
     // Calculate sum of next 4 quarters of estimated EPS
-    let estimated_eps_sum = {
-        let mut quarters_found = 0;
-        let mut sum = 0.0;
-        let mut final_quarter = None;
-
-        // Find first quarter with estimate
-        if let Some(start_idx) = sorted_data.iter()
-            .position(|q| q.eps_estimated.is_some()) {
-                
-            let mut consecutive_quarters = true;
-            let mut current_idx = start_idx;
-            
-            while current_idx < sorted_data.len() && quarters_found < 4 {
-                if let Some(eps) = sorted_data[current_idx].eps_estimated {
-                    if quarters_found == 0 {
-                        final_quarter = Some(sorted_data[current_idx + 3].quarter.clone());
-                    }
-                    sum += eps;
-                    quarters_found += 1;
-                } else {
-                    consecutive_quarters = false;
-                    break;
-                }
-                current_idx += 1;
-            }
+    let estimated_eps_sum = sum_next_estimated_quarters(&sorted_data);
 
-            if quarters_found == 4 && consecutive_quarters {
-                Some(QuarterlyValue {
-                    final_quarter: final_quarter.unwrap(),
-                    value: sum,
-                })
-            } else {
-                None
-            }
+    Ok(QuarterlyCalculations {
+        ttm_dividend,
+        ttm_eps,
+        latest_eps_actual,
+        estimated_eps_sum,
+    })
+}
+
+/// Outcome of a coalesced refresh; errors are kept as strings so the result can be cloned
+type SharedRefreshResult = std::result::Result<(MarketData, RefreshReport), String>;
+
+/// What a coalesced refresh ran, so a waiting caller only joins a refresh that did what it asked for
+#[derive(Debug, Clone, PartialEq)]
+enum RefreshScope {
+    /// Whatever `get_market_data` found due
+    Due,
+    /// These parts regardless of the time-of-day gates
+    Forced(Vec<RefreshPart>),
+}
+
+impl RefreshScope {
+    /// `get_market_data` with `force` set forces the daily close and fundamentals
+    fn of_get_market_data(force: bool) -> Self {
+        if force {
+            RefreshScope::Forced(vec![RefreshPart::DailyClose, RefreshPart::Fundamentals])
         } else {
-            None
+            RefreshScope::Due
         }
-    };
+    }
+
+    fn covers(&self, wanted: &RefreshScope) -> bool {
+        match (self, wanted) {
+            (_, RefreshScope::Due) => true,
+            (RefreshScope::Forced(ran), RefreshScope::Forced(wanted)) => wanted.iter().all(|part| ran.contains(part)),
+            (RefreshScope::Due, RefreshScope::Forced(_)) => false,
+        }
+    }
+}
+
+/// Result of the most recent coalesced refresh and what it ran, shared with callers that
+/// waited on it
+static LAST_REFRESH: OnceLock<Mutex<Option<(RefreshScope, SharedRefreshResult)>>> = OnceLock::new();
+/// Number of refreshes completed so far, used to detect that one finished while we waited
+static REFRESHES_COMPLETED: AtomicU64 = AtomicU64::new(0);
+
+/// Run `get_market_data`, coalescing concurrent callers: anyone arriving while a refresh is
+/// in flight waits for it and receives the same result instead of starting a second one.
+pub async fn refresh_market_data(db: &Arc<DbStore>, force: bool) -> Result<MarketData> {
+    refresh_market_data_with_report(db, force).await.map(|(data, _)| data)
+}
+
+/// `refresh_market_data` plus the report of what the refresh changed. Callers that joined
+/// an in-flight refresh receive that refresh's report. A forced caller only joins a refresh
+/// that was itself forced.
+pub async fn refresh_market_data_with_report(db: &Arc<DbStore>, force: bool) -> Result<(MarketData, RefreshReport)> {
+    single_flight(RefreshScope::of_get_market_data(force), || get_market_data_with_report(db, force)).await
+}
+
+/// Run `refresh` unless a refresh covering `scope` finished while we waited for the previous
+/// one, in which case its result is returned instead. Only one refresh runs at a time, so
+/// cache and store writes from different callers never interleave.
+async fn single_flight<F, Fut>(scope: RefreshScope, refresh: F) -> Result<(MarketData, RefreshReport)>
+where
+    F: FnOnce() -> Fut,
+    Fut: std::future::Future<Output = Result<(MarketData, RefreshReport)>>,
+{
+    let seen = REFRESHES_COMPLETED.load(Ordering::SeqCst);
+    let mut last = LAST_REFRESH.get_or_init(|| Mutex::new(None)).lock().await;
 
-    Ok((ttm_dividend, latest_eps_actual, estimated_eps_sum))
+    if REFRESHES_COMPLETED.load(Ordering::SeqCst) > seen {
+        if let Some((last_scope, result)) = last.as_ref() {
+            if last_scope.covers(&scope) {
+                info!("Joined an in-flight market data refresh");
+                return result.clone().map_err(|e| anyhow::anyhow!(e));
+            }
+        }
+    }
+
+    let result = refresh().await;
+    *last = Some((scope, result.as_ref().map(|result| result.clone()).map_err(|e| e.to_string())));
+    REFRESHES_COMPLETED.fetch_add(1, Ordering::SeqCst);
+    result
+}
+
+/// Old and new value of a price the refresh replaced
+#[derive(Debug, Clone, Serialize)]
+pub struct PriceChange {
+    pub old: f64,
+    pub new: f64,
+}
+
+/// What a refresh actually changed, so callers don't have to diff the cache before and after
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RefreshReport {
+    /// Refresh portions that fetched new data
+    pub updated: Vec<RefreshPart>,
+    pub price: Option<PriceChange>,
+    pub daily_close: Option<PriceChange>,
+    /// Year whose final close was written to the historical sheet
+    pub prior_year_close_captured: Option<i32>,
+    /// Market cache fields whose value changed after the YCharts scrape
+    pub ycharts_fields_changed: Vec<&'static str>,
+    /// Month appended to the monthly sheet
+    pub monthly_written: Option<String>,
+    pub quarterly_written: bool,
+    /// Year whose historical row was updated from quarterly, monthly or CAPE data
+    pub historical_year_updated: Option<i32>,
 }
 
-pub async fn get_market_data(db: &Arc<DbStore>) -> Result<MarketData> {
+impl RefreshReport {
+    fn mark_updated(&mut self, part: RefreshPart) {
+        if !self.updated.contains(&part) {
+            self.updated.push(part);
+        }
+    }
+}
+
+/// Refresh whatever is due and build the market data. The daily close and fundamentals
+/// only refresh at the daily update time (3:30 PM Central by default) unless `force` is set.
+pub async fn get_market_data(db: &Arc<DbStore>, force: bool) -> Result<MarketData> {
+    get_market_data_with_report(db, force).await.map(|(data, _)| data)
+}
+
+/// `get_market_data` plus a report of which sub-updates fired
+pub async fn get_market_data_with_report(db: &Arc<DbStore>, force: bool) -> Result<(MarketData, RefreshReport)> {
     let mut cache = db.get_market_cache().await?;
+    let mut report = RefreshReport::default();
     let mut data_updated = false;
 
-    // Existing price update logic...
+    // Must run before the daily close below is overwritten with this year's price
+    if capture_prior_year_close(db, &mut cache, &mut report).await? {
+        data_updated = true;
+    }
+
     if cache.current_sp500_price == 0.0 {
-        info!("Initial fetch of current S&P 500 price");
-        if let Ok(price) = fetch_sp500_price().await {
-            cache.current_sp500_price = price;
-            cache.timestamps.yahoo_price = Utc::now();
-            data_updated = true;
+        info!("Initial fetch of current {} price", index_symbol());
+        data_updated |= refresh_current_price(&mut cache, &mut report).await;
+    }
+
+    let refresh_interval = price_refresh_interval();
+    if price_refresh_due(cache.timestamps.yahoo_price, Utc::now(), refresh_interval) {
+        info!("Updating current {} price ({}-minute interval)", index_symbol(), refresh_interval.num_minutes());
+        data_updated |= refresh_current_price(&mut cache, &mut report).await;
+    }
+
+    if force || should_update_daily(Utc::now(), daily_update_time(), cache.timestamps.ycharts_data) {
+        if force {
+            info!("Forced update - performing daily updates");
+        } else {
+            info!("Past market close and not yet updated today - performing daily updates");
         }
+        data_updated |= refresh_daily_close(&mut cache, &mut report).await;
+        data_updated |= refresh_fundamentals(db, &mut cache, &mut report).await;
     }
 
-    if cache.timestamps.yahoo_price < Utc::now() - Duration::minutes(15) {
-        info!("Updating current S&P 500 price (15-minute interval)");
-        if let Ok(price) = fetch_sp500_price().await {
-            cache.current_sp500_price = price;
-            cache.timestamps.yahoo_price = Utc::now();
-            data_updated = true;
+    if data_updated {
+        info!("Cache updated");
+        db.update_market_cache(&cache).await?;
+        report.historical_year_updated = check_historical_updates(db, &cache).await?;
+    }
+
+    Ok((build_market_data(db, &cache).await?, report))
+}
+
+/// Portions of `get_market_data` that can be forced from the admin refresh endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RefreshPart {
+    Price,
+    Fundamentals,
+    DailyClose,
+}
+
+impl RefreshPart {
+    pub const ALL: [RefreshPart; 3] = [RefreshPart::Price, RefreshPart::Fundamentals, RefreshPart::DailyClose];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RefreshPart::Price => "price",
+            RefreshPart::Fundamentals => "fundamentals",
+            RefreshPart::DailyClose => "daily_close",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|part| part.name() == name)
+    }
+}
+
+/// Run the selected refresh portions regardless of the time-of-day gates.
+/// Returns a report of what changed (including the parts that fetched new data) alongside
+/// the resulting market data. Shares the single flight with `refresh_market_data`, joining an
+/// in-flight refresh that forced at least the same parts.
+pub async fn force_refresh(db: &Arc<DbStore>, parts: &[RefreshPart]) -> Result<(RefreshReport, MarketData)> {
+    let (data, report) = single_flight(RefreshScope::Forced(parts.to_vec()), || force_refresh_parts(db, parts)).await?;
+    Ok((report, data))
+}
+
+async fn force_refresh_parts(db: &Arc<DbStore>, parts: &[RefreshPart]) -> Result<(MarketData, RefreshReport)> {
+    let mut cache = db.get_market_cache().await?;
+    let mut report = RefreshReport::default();
+
+    for &part in parts {
+        info!("Forcing {} refresh", part.name());
+        match part {
+            RefreshPart::Price => refresh_current_price(&mut cache, &mut report).await,
+            RefreshPart::DailyClose => refresh_daily_close(&mut cache, &mut report).await,
+            RefreshPart::Fundamentals => refresh_fundamentals(db, &mut cache, &mut report).await,
+        };
+    }
+
+    if !report.updated.is_empty() {
+        db.update_market_cache(&cache).await?;
+        report.historical_year_updated = check_historical_updates(db, &cache).await?;
+    }
+
+    Ok((build_market_data(db, &cache).await?, report))
+}
+
+/// Regular NYSE session on a trading day: 9:30-16:00 Eastern, which is 8:30-15:00 Central
+pub fn is_market_hours(at: DateTime<Utc>) -> bool {
+    let central = at.with_timezone(&Central);
+    let open = NaiveTime::from_hms_opt(8, 30, 0).unwrap();
+    let close = NaiveTime::from_hms_opt(15, 0, 0).unwrap();
+    is_trading_day(central.date_naive())
+        && central.time() >= open
+        && central.time() < close
+}
+
+/// Minimum age of the cached price before a request refreshes it, from
+/// `PRICE_REFRESH_MINUTES` (default 15)
+fn price_refresh_interval() -> Duration {
+    let minutes = std::env::var("PRICE_REFRESH_MINUTES")
+        .ok()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(15);
+    Duration::minutes(minutes)
+}
+
+/// Whether the price fetched at `last_fetch` is older than `interval` while the market is
+/// open. Outside the session the price is frozen, so a stale one isn't refetched.
+fn price_refresh_due(last_fetch: DateTime<Utc>, now: DateTime<Utc>, interval: Duration) -> bool {
+    is_market_hours(now) && last_fetch < now - interval
+}
+
+/// Trading days after `last_update` whose daily update never ran, oldest first and limited to the
+/// most recent `max_days`. Today counts only once its update time has passed.
+pub fn missed_trading_days(last_update: NaiveDate, today: NaiveDate, include_today: bool, max_days: usize) -> Vec<NaiveDate> {
+    let latest = if include_today { Some(today) } else { today.pred_opt() };
+    let mut missed: Vec<NaiveDate> = latest.into_iter()
+        .flat_map(|day| day.iter_days().rev())
+        .take_while(|day| *day > last_update)
+        .filter(|day| is_trading_day(*day))
+        .take(max_days)
+        .collect();
+    missed.reverse();
+    missed
+}
+
+/// Price snapshot pushed to `/api/v1/equity/stream` subscribers
+#[derive(Debug, Clone, Serialize)]
+pub struct PriceUpdate {
+    pub current_sp500_price: f64,
+    pub daily_close_sp500_price: f64,
+    pub as_of: DateTime<Utc>,
+}
+
+impl PriceUpdate {
+    pub fn from_cache(cache: &MarketCache) -> Self {
+        PriceUpdate {
+            current_sp500_price: cache.current_sp500_price,
+            daily_close_sp500_price: cache.daily_close_sp500_price,
+            as_of: cache.timestamps.yahoo_price,
+        }
+    }
+}
+
+/// Buffered price updates per subscriber before slow ones start skipping
+const PRICE_UPDATE_CHANNEL_CAPACITY: usize = 16;
+
+static PRICE_UPDATES: OnceLock<broadcast::Sender<PriceUpdate>> = OnceLock::new();
+
+fn price_updates() -> &'static broadcast::Sender<PriceUpdate> {
+    PRICE_UPDATES.get_or_init(|| broadcast::channel(PRICE_UPDATE_CHANNEL_CAPACITY).0)
+}
+
+/// Receive every price the refresh path writes to the cache from now on
+pub fn subscribe_price_updates() -> broadcast::Receiver<PriceUpdate> {
+    price_updates().subscribe()
+}
+
+fn publish_price_update(cache: &MarketCache) {
+    // Sending only fails when nobody is subscribed
+    let _ = price_updates().send(PriceUpdate::from_cache(cache));
+}
+
+/// Store a freshly fetched price. `yahoo_price` moves on every fetch, but
+/// `last_market_price_update` only when the fetch happened during trading hours.
+fn record_current_price(cache: &mut MarketCache, price: f64, source: &str, now: DateTime<Utc>) {
+    cache.current_sp500_price = price;
+    cache.price_source = source.to_string();
+    cache.timestamps.yahoo_price = now;
+    if is_market_hours(now) {
+        cache.timestamps.last_market_price_update = now;
+    }
+}
+
+/// Fetch the current index price into the cache. Returns true if the price was updated.
+async fn refresh_current_price(cache: &mut MarketCache, report: &mut RefreshReport) -> bool {
+    match fetch_tracked_index_price(&RetryPolicy::default()).await {
+        Ok((price, source)) => {
+            report.mark_updated(RefreshPart::Price);
+            report.price = Some(PriceChange { old: cache.current_sp500_price, new: price });
+            record_current_price(cache, price, source, Utc::now());
+            publish_price_update(cache);
+            true
+        }
+        Err(e) => {
+            warn!("Failed to refresh current {} price: {}", index_symbol(), e);
+            false
         }
     }
+}
 
-    if should_update_daily() {
-        info!("Market close time - performing daily updates");
-        if let Ok(price) = fetch_sp500_price().await {
+/// Record the current index price as the daily close. Returns true if the close was updated.
+async fn refresh_daily_close(cache: &mut MarketCache, report: &mut RefreshReport) -> bool {
+    match fetch_tracked_index_price(&RetryPolicy::default()).await {
+        Ok((price, source)) => {
+            report.mark_updated(RefreshPart::DailyClose);
+            report.daily_close = Some(PriceChange { old: cache.daily_close_sp500_price, new: price });
             cache.daily_close_sp500_price = price;
             cache.current_sp500_price = price;
-            data_updated = true;
+            cache.price_source = source.to_string();
+            publish_price_update(cache);
+            true
+        }
+        Err(e) => {
+            warn!("Failed to refresh {} daily close: {}", index_symbol(), e);
+            false
         }
+    }
+}
 
-        if let Ok(ycharts_data) = fetch_ycharts_data().await {
-            // Check if we got a new monthly return
-            if let Some((month, return_value)) = &ycharts_data.monthly_return {
-                // Update the monthly data sheet if it's a new month
-                if let Err(e) = update_monthly_data(db, month, *return_value).await {
-                    error!("Failed to update monthly data sheet: {}", e);
-                }
-            }
-            
-            // Update quarterly dividend data
-            if !ycharts_data.quarterly_dividends.is_empty() {
-                if let Err(e) = update_quarterly_data(db, &ycharts_data.quarterly_dividends, "dividend").await {
-                    error!("Failed to update quarterly dividend data: {}", e);
-                }
-            }
-            
-            // Update quarterly EPS actual data
-            if !ycharts_data.eps_actual.is_empty() {
-                if let Err(e) = update_quarterly_data(db, &ycharts_data.eps_actual, "eps_actual").await {
-                    error!("Failed to update quarterly EPS actual data: {}", e);
-                }
-            }
-            
-            // Update quarterly EPS estimated data
-            if !ycharts_data.eps_estimated.is_empty() {
-                if let Err(e) = update_quarterly_data(db, &ycharts_data.eps_estimated, "eps_estimated").await {
-                    error!("Failed to update quarterly EPS estimated data: {}", e);
-                }
-            }
-            
-            update_cache_from_ycharts(&mut cache, ycharts_data);
-            cache.timestamps.ycharts_data = Utc::now();
-            data_updated = true;
+/// Scrape YCharts and write monthly returns, quarterly data and CAPE through to the store.
+/// Returns true if the cache was updated.
+async fn refresh_fundamentals(db: &Arc<DbStore>, cache: &mut MarketCache, report: &mut RefreshReport) -> bool {
+    let fetcher = match Client::builder().user_agent("Mozilla/5.0").build() {
+        Ok(client) => ReqwestFetcher::new(client),
+        Err(e) => {
+            warn!("Failed to refresh YCharts fundamentals: {}", e);
+            return false;
         }
+    };
+    refresh_fundamentals_with(db, &fetcher, cache, report).await
+}
+
+/// `refresh_fundamentals` through `fetcher`. If no indicator could be scraped nothing is
+/// written and the YCharts timestamp stays put, so the daily update is retried.
+async fn refresh_fundamentals_with(
+    db: &Arc<DbStore>,
+    fetcher: &impl HttpFetcher,
+    cache: &mut MarketCache,
+    report: &mut RefreshReport,
+) -> bool {
+    let ycharts_data = fetch_ycharts_data_with(fetcher).await;
+    if ycharts_data.is_empty() {
+        warn!("Failed to refresh YCharts fundamentals: no indicator could be fetched");
+        return false;
     }
 
-    if data_updated {
-        info!("Cache updated");
-        db.update_market_cache(&cache).await?;
-        check_historical_updates(db, &cache).await?;
+    // Check if we got a new monthly return
+    if let Some((month, return_value)) = &ycharts_data.monthly_return {
+        // Update the monthly data sheet if it's a new month
+        match update_monthly_data(db, month, *return_value).await {
+            Ok(true) => report.monthly_written = Some(month.clone()),
+            Ok(false) => {}
+            Err(e) => error!("Failed to update monthly data sheet: {}", e),
+        }
+    }
+
+    // Update quarterly dividend and EPS data in one read-modify-write
+    let quarterly_updates = QuarterlyUpdates {
+        dividends: &ycharts_data.quarterly_dividends,
+        eps_actual: &ycharts_data.eps_actual,
+        eps_estimated: &ycharts_data.eps_estimated,
+    };
+    match update_quarterly_data(db, &quarterly_updates).await {
+        Ok(written) => report.quarterly_written = written,
+        Err(e) => error!("Failed to update quarterly data: {}", e),
     }
 
+    report.ycharts_fields_changed = update_cache_from_ycharts(cache, ycharts_data);
+    report.mark_updated(RefreshPart::Fundamentals);
+    cache.timestamps.ycharts_data = Utc::now();
+    true
+}
+
+/// Read-only variant of `get_market_data`: builds the payload from the current cache
+/// without fetching prices or writing anything back
+pub async fn build_market_data_from_cache(db: &Arc<DbStore>) -> Result<MarketData> {
+    let cache = db.get_market_cache().await?;
+    build_market_data(db, &cache).await
+}
+
+async fn build_market_data(db: &Arc<DbStore>, cache: &MarketCache) -> Result<MarketData> {
     // Get latest quarterly data
-    let (ttm_dividend, latest_eps_actual, estimated_eps_sum) = get_quarterly_calculations(db).await?;
+    let quarterly = get_quarterly_calculations(db).await?;
+    let price = cache.current_sp500_price;
     
+    let ttm_dividend_yield = calculate_ttm_dividend_yield(quarterly.ttm_dividend.as_ref(), price);
+    let ttm_eps_value = quarterly.ttm_eps.as_ref().map(|eps| eps.value);
+
     Ok(MarketData {
         daily_close_sp500_price: cache.daily_close_sp500_price,
-        current_sp500_price: cache.current_sp500_price,
-        ttm_dividend,
-        latest_eps_actual,
-        estimated_eps_sum,
+        current_sp500_price: price,
+        ttm_dividend: quarterly.ttm_dividend,
+        ttm_dividend_yield,
+        trailing_pe: ttm_eps_value.and_then(|eps| calculate_pe_ratio(price, eps)),
+        earnings_yield: ttm_eps_value.and_then(|eps| calculate_earnings_yield(price, eps)),
+        ttm_eps: quarterly.ttm_eps,
+        latest_eps_actual: quarterly.latest_eps_actual,
+        estimated_eps_sum: quarterly.estimated_eps_sum,
         cape: cache.current_cape,
         cape_period: cache.cape_period.clone(),
+        price_source: cache.price_source.clone(),
         last_update: cache.timestamps.ycharts_data,
     })
 }
 
-fn should_update_daily() -> bool {
-    let current_ct = Utc::now().with_timezone(&Central);
-    let target_time = NaiveTime::from_hms_opt(15, 30, 0).unwrap();
-    let current_time = current_ct.time();
-    current_time >= target_time && 
-    current_time < target_time + chrono::Duration::minutes(1)
+/// Trailing-twelve-month dividend divided by the current price, if both are available
+fn calculate_ttm_dividend_yield(ttm_dividend: Option<&QuarterlyValue>, price: f64) -> Option<f64> {
+    match ttm_dividend {
+        Some(ttm) if price > 0.0 => Some(ttm.value / price),
+        _ => None,
+    }
 }
 
-async fn fetch_sp500_price() -> Result<f64> {
-    // Try Yahoo Finance API first
-    let api_url = "https://query1.finance.yahoo.com/v8/finance/chart/%5EGSPC?interval=1d&range=1d";
-    let client = Client::builder()
-        .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36")
-        .build()?;
-        
-    // First try the API endpoint
-    match client.get(api_url).send().await {
-        Ok(response) => {
-            if let Ok(text) = response.text().await {
-                let price_re = Regex::new(r#""regularMarketPrice":([0-9.]+)"#)?;
-                if let Some(caps) = price_re.captures(&text) {
-                    if let Ok(price) = caps.get(1).unwrap().as_str().parse::<f64>() {
-                        info!("Found S&P 500 price via API: {}", price);
-                        return Ok(price);
-                    }
-                }
-            }
-        }
-        Err(_) => {
-            info!("API request failed, falling back to web scraping");
-        }
+/// Minutes to wait before each retry of the scheduled daily update
+const DAILY_UPDATE_RETRY_DELAYS_MINUTES: [u64; 3] = [5, 15, 30];
+
+/// Set while the scheduled daily update (including its retries) is running
+static DAILY_UPDATE_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Scheduled market-close update: records the daily close and refreshes fundamentals,
+/// retrying after 5, 15 and 30 minutes on failure. Retries stop at the end of the Central
+/// day, and a run that fires while a previous one is still retrying is skipped, as is any
+/// run on a weekend or NYSE holiday.
+pub async fn run_scheduled_daily_update(db: &Arc<DbStore>) -> Result<()> {
+    let today = Utc::now().with_timezone(&Central).date_naive();
+    if !is_trading_day(today) {
+        info!("{} is not a trading day, skipping the daily update", today);
+        return Ok(());
     }
-    
-    // Fallback to web scraping
-    let url = "https://finance.yahoo.com/quote/%5EGSPC";
-    let resp = client.get(url)
-        .header("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,image/webp,*/*;q=0.8")
-        .header("Accept-Language", "en-US,en;q=0.5")
-        .header("Accept-Encoding", "gzip, deflate")
-        .header("Connection", "keep-alive")
-        .header("Upgrade-Insecure-Requests", "1")
-        .send()
-        .await?
-        .text()
-        .await?;
 
-    // Try multiple patterns for extracting the price
-    let patterns = vec![
-        r#""regularMarketPrice":\{"raw":([0-9.]+),"fmt":"[^"]*"\}"#,
-        r#""regularMarketPrice":\{"raw":([0-9.]+)"#,
-        r#"data-symbol="\^GSPC"[^>]*data-value="([0-9.]+)""#,
-        r#"data-field="regularMarketPrice"[^>]*>([0-9,]+\.[0-9]+)"#,
-        r#"<span[^>]*data-symbol="\^GSPC"[^>]*>([0-9,]+\.[0-9]+)</span>"#,
-    ];
-    
-    for pattern in patterns {
-        let re = Regex::new(pattern)?;
-        if let Some(caps) = re.captures(&resp) {
-            let price_str = caps.get(1).unwrap().as_str().replace(",", "");
-            if let Ok(price) = price_str.parse::<f64>() {
-                info!("Found S&P 500 price: {} using pattern: {}", price, pattern);
-                return Ok(price);
-            }
-        }
+    if DAILY_UPDATE_RUNNING.swap(true, Ordering::SeqCst) {
+        warn!("Previous daily update is still retrying, skipping this run");
+        return Ok(());
     }
-    
-    // Fallback: look for any reasonable price-like number
-    let price_re = Regex::new(r"([0-9]{4}\.[0-9]{2})")?;
-    for cap in price_re.captures_iter(&resp) {
-        let price_str = cap.get(1).unwrap().as_str();
-        if let Ok(price) = price_str.parse::<f64>() {
-            if price > 3000.0 && price < 7000.0 {
-                info!("Found S&P 500 price using fallback: {}", price);
-                return Ok(price);
+
+    let result = daily_update_with_retries(db).await;
+    DAILY_UPDATE_RUNNING.store(false, Ordering::SeqCst);
+    result
+}
+
+async fn daily_update_with_retries(db: &Arc<DbStore>) -> Result<()> {
+    let run_date = Utc::now().with_timezone(&Central).date_naive();
+    let parts = [RefreshPart::DailyClose, RefreshPart::Fundamentals];
+    let mut attempt = 1;
+
+    loop {
+        match force_refresh(db, &parts).await {
+            Ok((report, _)) if report.updated.len() == parts.len() => {
+                info!("Daily update succeeded on attempt {}", attempt);
+                return Ok(());
             }
+            Ok((report, _)) => {
+                let failed: Vec<&str> = parts.iter()
+                    .filter(|part| !report.updated.contains(part))
+                    .map(|part| part.name())
+                    .collect();
+                warn!("Daily update attempt {} did not refresh: {}", attempt, failed.join(", "));
+            }
+            Err(e) => warn!("Daily update attempt {} failed: {}", attempt, e),
         }
-    }
 
-    Err(anyhow::anyhow!("Price not found in Yahoo Finance response"))
+        let Some(&delay) = DAILY_UPDATE_RETRY_DELAYS_MINUTES.get(attempt - 1) else {
+            return Err(anyhow::anyhow!("Daily update failed after {} attempts", attempt));
+        };
+        info!("Retrying daily update in {} minutes", delay);
+        tokio::time::sleep(std::time::Duration::from_secs(delay * 60)).await;
+
+        if Utc::now().with_timezone(&Central).date_naive() != run_date {
+            return Err(anyhow::anyhow!("Daily update window for {} has passed after {} attempts", run_date, attempt));
+        }
+        attempt += 1;
+    }
 }
 
-async fn fetch_ycharts_value(url: &str) -> Result<(String, f64)> {
-    info!("Fetching data from URL: {}", url);
-    
-    let client = reqwest::Client::new();
-    let response = client
-        .get(url)
-        .header("User-Agent", "Mozilla/5.0")
-        .send()
-        .await?
-        .text()
-        .await?;
+/// Central time of the daily close update when `DAILY_UPDATE_TIME` is unset
+pub const DEFAULT_DAILY_UPDATE_TIME: &str = "15:30";
 
-    let document = Html::parse_document(&response);
-    let value_selector = Selector::parse("div.key-stat-title").unwrap();
-    
-    let stat = document.select(&value_selector)
-        .next()
-        .and_then(|el| el.text().next())
-        .ok_or_else(||anyhow::anyhow!("Failed to find stat"))?
-        .trim();
-    
-    info!("Found stat text: {}", stat);
+static DAILY_UPDATE_TIME: OnceLock<NaiveTime> = OnceLock::new();
 
-    // IMPROVED REGEX - handles the current YCharts format better
-    let re = Regex::new(r"([-+]?\d*\.?\d+)%?\s*(?:USD)?\s*(?:for)?\s+(?:(Q\d)\s+(\d{4})|(Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec)\s+(\d{4}))")?;
-    
-    if let Some(caps) = re.captures(stat) {
+/// Parse an `HH:MM` update time
+pub fn parse_daily_update_time(value: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M").ok()
+}
+
+/// Set the daily update time used by `get_market_data`; only the first call takes effect
+pub fn set_daily_update_time(time: NaiveTime) {
+    if DAILY_UPDATE_TIME.set(time).is_err() {
+        warn!("Daily update time already set, ignoring {}", time);
+    }
+}
+
+/// Configured daily update time (Central), 3:30 PM unless set at startup
+pub fn daily_update_time() -> NaiveTime {
+    *DAILY_UPDATE_TIME.get_or_init(|| NaiveTime::from_hms_opt(15, 30, 0).unwrap())
+}
+
+/// True once `target_time` (Central) has passed on a trading day and the fundamentals
+/// haven't been refreshed yet that Central day. `last_update` is the YCharts timestamp.
+fn should_update_daily(now: DateTime<Utc>, target_time: NaiveTime, last_update: DateTime<Utc>) -> bool {
+    let current_ct = now.with_timezone(&Central);
+    let today = current_ct.date_naive();
+    is_trading_day(today) &&
+    current_ct.time() >= target_time &&
+    last_update.with_timezone(&Central).date_naive() < today
+}
+
+/// Weekday that isn't an NYSE holiday
+pub fn is_trading_day(date: NaiveDate) -> bool {
+    !matches!(date.weekday(), chrono::Weekday::Sat | chrono::Weekday::Sun) && !is_nyse_holiday(date)
+}
+
+/// Full-day NYSE closures for the date's year, with weekend holidays moved to the observed day
+fn is_nyse_holiday(date: NaiveDate) -> bool {
+    let year = date.year();
+    let nth = |month, weekday, n| NaiveDate::from_weekday_of_month_opt(year, month, weekday, n);
+    let fixed = |month, day| NaiveDate::from_ymd_opt(year, month, day).map(observed);
+
+    // New Year's falling on a Saturday isn't moved back into the previous year
+    let new_years = match NaiveDate::from_ymd_opt(year, 1, 1) {
+        Some(day) if day.weekday() == chrono::Weekday::Sat => None,
+        day => day.map(observed),
+    };
+    // Memorial Day is the last Monday of May
+    let memorial = nth(5, chrono::Weekday::Mon, 5).or_else(|| nth(5, chrono::Weekday::Mon, 4));
+    // Juneteenth has been a market holiday since 2022
+    let juneteenth = if year >= 2022 { fixed(6, 19) } else { None };
+
+    [
+        new_years,
+        nth(1, chrono::Weekday::Mon, 3),
+        nth(2, chrono::Weekday::Mon, 3),
+        easter_sunday(year).map(|easter| easter - Duration::days(2)),
+        memorial,
+        juneteenth,
+        fixed(7, 4),
+        nth(9, chrono::Weekday::Mon, 1),
+        nth(11, chrono::Weekday::Thu, 4),
+        fixed(12, 25),
+    ]
+    .into_iter()
+    .flatten()
+    .any(|holiday| holiday == date)
+}
+
+/// Saturday holidays are observed on Friday, Sunday holidays on Monday
+fn observed(date: NaiveDate) -> NaiveDate {
+    match date.weekday() {
+        chrono::Weekday::Sat => date - Duration::days(1),
+        chrono::Weekday::Sun => date + Duration::days(1),
+        _ => date,
+    }
+}
+
+/// Gregorian Easter (anonymous Gregorian algorithm)
+fn easter_sunday(year: i32) -> Option<NaiveDate> {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32)
+}
+
+/// Yahoo symbol for the S&P 500, the index tracked unless `INDEX_SYMBOL` says otherwise
+const SP500_SYMBOL: &str = "^GSPC";
+
+/// Plausible price band per instrument, used to reject obviously mis-scraped numbers
+const PLAUSIBLE_PRICE_BOUNDS: [(&str, f64, f64); 3] = [
+    (SP500_SYMBOL, 2000.0, 15000.0),
+    ("^DJI", 15000.0, 100000.0),
+    ("^IXIC", 5000.0, 50000.0),
+];
+
+/// Yahoo symbol of the tracked index, from `INDEX_SYMBOL` (default `^GSPC`)
+pub fn index_symbol() -> String {
+    std::env::var("INDEX_SYMBOL")
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| SP500_SYMBOL.to_string())
+}
+
+pub fn plausible_price_bounds(symbol: &str) -> Option<(f64, f64)> {
+    PLAUSIBLE_PRICE_BOUNDS.iter()
+        .find(|(s, _, _)| *s == symbol)
+        .map(|(_, min, max)| (*min, *max))
+}
+
+/// Whether a scraped price falls inside the instrument's plausible band.
+/// Symbols without configured bounds accept any positive price.
+fn is_plausible_price(symbol: &str, price: f64) -> bool {
+    match plausible_price_bounds(symbol) {
+        Some((min, max)) => price > min && price < max,
+        None => price > 0.0,
+    }
+}
+
+/// Symbol as it appears in a Yahoo URL path (`^GSPC` -> `%5EGSPC`)
+fn yahoo_path_symbol(symbol: &str) -> String {
+    symbol.replace('^', "%5E")
+}
+
+/// Regex patterns that pull `symbol`'s price out of a Yahoo quote page, most specific first
+fn quote_page_patterns(symbol: &str) -> Vec<String> {
+    let escaped = regex::escape(symbol);
+    vec![
+        r#""regularMarketPrice":\{"raw":([0-9.]+),"fmt":"[^"]*"\}"#.to_string(),
+        r#""regularMarketPrice":\{"raw":([0-9.]+)"#.to_string(),
+        format!(r#"data-symbol="{}"[^>]*data-value="([0-9.]+)""#, escaped),
+        r#"data-field="regularMarketPrice"[^>]*>([0-9,]+\.[0-9]+)"#.to_string(),
+        format!(r#"<span[^>]*data-symbol="{}"[^>]*>([0-9,]+\.[0-9]+)</span>"#, escaped),
+    ]
+}
+
+/// Where the current index price came from, recorded in the cache and `MarketData`
+pub const PRICE_SOURCE_YAHOO: &str = "yahoo";
+pub const PRICE_SOURCE_STOOQ: &str = "stooq";
+
+/// Fetch the index price from Yahoo (retried per `policy`), falling back to Stooq.
+/// Returns the price and the source that produced it.
+async fn fetch_tracked_index_price(policy: &RetryPolicy) -> Result<(f64, &'static str)> {
+    let symbol = index_symbol();
+    let yahoo_err = match policy.run(&format!("{} price fetch", symbol), || fetch_index_price(&symbol)).await {
+        Ok(price) => return Ok((price, PRICE_SOURCE_YAHOO)),
+        Err(e) => e,
+    };
+
+    warn!("Yahoo price fetch for {} failed ({}), falling back to Stooq", symbol, yahoo_err);
+    match fetch_stooq_index_price().await {
+        Ok(price) => Ok((price, PRICE_SOURCE_STOOQ)),
+        Err(stooq_err) => Err(anyhow::anyhow!(
+            "{} price unavailable. Yahoo: {}; Stooq fallback: {}", symbol, yahoo_err, stooq_err
+        )),
+    }
+}
+
+/// Stooq's symbol for the indices we have plausible bounds for
+fn stooq_symbol(symbol: &str) -> Option<&'static str> {
+    match symbol {
+        SP500_SYMBOL => Some("^spx"),
+        "^DJI" => Some("^dji"),
+        "^IXIC" => Some("^ndq"),
+        _ => None,
+    }
+}
+
+/// Latest close of the tracked index from Stooq's quote CSV
+async fn fetch_stooq_index_price() -> Result<f64> {
+    let symbol = index_symbol();
+    let stooq = stooq_symbol(&symbol)
+        .ok_or_else(|| anyhow::anyhow!("No Stooq symbol known for {}", symbol))?;
+
+    let client = Client::builder()
+        .timeout(std::time::Duration::from_secs(30))
+        .build()?;
+    let body = client.get("https://stooq.com/q/l/")
+        .query(&[("s", stooq), ("f", "sd2t2ohlcv"), ("h", ""), ("e", "csv")])
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    let price = parse_stooq_price(&body, &symbol)?;
+    info!("Found {} price via Stooq: {}", symbol, price);
+    Ok(price)
+}
+
+/// Close price from a Stooq quote CSV (`Symbol,Date,Time,Open,High,Low,Close,Volume`).
+/// Stooq reports a missing quote as `N/D`.
+pub fn parse_stooq_price(csv_body: &str, symbol: &str) -> Result<f64> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(csv_body.as_bytes());
+    let close_idx = reader.headers()?
+        .iter()
+        .position(|h| h.eq_ignore_ascii_case("Close"))
+        .ok_or_else(|| anyhow::anyhow!("Stooq response has no Close column"))?;
+
+    let row = reader.records().next()
+        .ok_or_else(|| anyhow::anyhow!("Stooq response has no quote rows"))??;
+    let raw = row.get(close_idx).unwrap_or("");
+    let price: f64 = raw.parse()
+        .map_err(|_| anyhow::anyhow!("Stooq close '{}' for {} is not a price", raw, symbol))?;
+
+    if !is_plausible_price(symbol, price) {
+        return Err(anyhow::anyhow!("Ignoring implausible {} price from Stooq: {}", symbol, price));
+    }
+    Ok(price)
+}
+
+/// `regularMarketPrice` from a Yahoo chart API response, if present and plausible
+pub fn parse_yahoo_chart_price(body: &str, symbol: &str) -> Option<f64> {
+    let price_re = Regex::new(r#""regularMarketPrice":([0-9.]+)"#).unwrap();
+    let price = price_re.captures(body)?.get(1)?.as_str().parse::<f64>().ok()?;
+    if is_plausible_price(symbol, price) {
+        Some(price)
+    } else {
+        warn!("Ignoring implausible {} price from API: {}", symbol, price);
+        None
+    }
+}
+
+/// Price scraped from a Yahoo quote page, trying the known patterns before a plausible-number sweep
+pub fn parse_yahoo_quote_page(html: &str, symbol: &str) -> Option<f64> {
+    // Try multiple patterns for extracting the price
+    for pattern in quote_page_patterns(symbol) {
+        let re = Regex::new(&pattern).unwrap();
+        if let Some(caps) = re.captures(html) {
+            let price_str = caps.get(1).unwrap().as_str().replace(",", "");
+            if let Ok(price) = price_str.parse::<f64>() {
+                if is_plausible_price(symbol, price) {
+                    info!("Found {} price: {} using pattern: {}", symbol, price, pattern);
+                    return Some(price);
+                }
+                warn!("Ignoring implausible {} price {} from pattern: {}", symbol, price, pattern);
+            }
+        }
+    }
+
+    // Fallback: look for any reasonable price-like number. Only safe when the symbol has
+    // a plausible band, otherwise any number on the page would be accepted.
+    if plausible_price_bounds(symbol).is_some() {
+        let price_re = Regex::new(r"([0-9]{4,6}\.[0-9]{2})").unwrap();
+        for cap in price_re.captures_iter(html) {
+            let price_str = cap.get(1).unwrap().as_str();
+            if let Ok(price) = price_str.parse::<f64>() {
+                if is_plausible_price(symbol, price) {
+                    info!("Found {} price using fallback: {}", symbol, price);
+                    return Some(price);
+                }
+            }
+        }
+    }
+
+    None
+}
+
+async fn fetch_index_price(symbol: &str) -> Result<f64> {
+    let path_symbol = yahoo_path_symbol(symbol);
+
+    // Try Yahoo Finance API first
+    let api_url = format!("https://query1.finance.yahoo.com/v8/finance/chart/{}?interval=1d&range=1d", path_symbol);
+    let client = Client::builder()
+        .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36")
+        .build()?;
+        
+    // First try the API endpoint
+    match client.get(&api_url).send().await {
+        Ok(response) => {
+            if let Ok(text) = response.text().await {
+                if let Some(price) = parse_yahoo_chart_price(&text, symbol) {
+                    info!("Found {} price via API: {}", symbol, price);
+                    return Ok(price);
+                }
+            }
+        }
+        Err(_) => {
+            info!("API request failed, falling back to web scraping");
+        }
+    }
+    
+    // Fallback to web scraping
+    let url = format!("https://finance.yahoo.com/quote/{}", path_symbol);
+    let resp = client.get(&url)
+        .header("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,image/webp,*/*;q=0.8")
+        .header("Accept-Language", "en-US,en;q=0.5")
+        .header("Accept-Encoding", "gzip, deflate")
+        .header("Connection", "keep-alive")
+        .header("Upgrade-Insecure-Requests", "1")
+        .send()
+        .await?
+        .text()
+        .await?;
+
+    parse_yahoo_quote_page(&resp, symbol)
+        .ok_or_else(|| anyhow::anyhow!("{} price not found in Yahoo Finance response", symbol))
+}
+
+async fn fetch_ycharts_value(fetcher: &impl HttpFetcher, url: &str) -> Result<(String, f64)> {
+    let raw = fetch_ycharts_value_raw_with(fetcher, url).await?;
+    Ok((raw.period, raw.value))
+}
+
+/// Why a YCharts page couldn't be scraped, so callers can react to a challenge page
+/// differently from a layout change
+#[derive(Debug)]
+pub enum ScrapeError {
+    /// A bot-challenge or cookie-consent page was served instead of the indicator, with a
+    /// 200 or an error status
+    Interstitial { marker: &'static str },
+    /// The page loaded but had no key stat element
+    StatNotFound,
+}
+
+impl std::fmt::Display for ScrapeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ScrapeError::Interstitial { marker } => write!(f, "YCharts returned an interstitial page ({})", marker),
+            ScrapeError::StatNotFound => write!(f, "Failed to find stat"),
+        }
+    }
+}
+
+impl std::error::Error for ScrapeError {}
+
+/// Text found on challenge/consent pages; only checked once the key stat is known to be missing
+const INTERSTITIAL_MARKERS: [&str; 8] = [
+    "Just a moment...",
+    "Checking your browser",
+    "challenge-platform",
+    "cf-chl-",
+    "Attention Required! | Cloudflare",
+    "Enable JavaScript and cookies to continue",
+    "Please verify you are a human",
+    "We value your privacy",
+];
+
+/// Return the first interstitial marker found in a page body, if any
+pub fn detect_interstitial(body: &str) -> Option<&'static str> {
+    INTERSTITIAL_MARKERS.iter().copied().find(|marker| body.contains(marker))
+}
+
+/// Where YCharts has put the key stat text, tried in order; YCharts rotates its classes, so
+/// add the new one here when the logs show every candidate missing
+const YCHARTS_STAT_SELECTORS: [&str; 3] = [
+    "div.key-stat-title",
+    "span.stat-value",
+    "[data-testid=\"key-stat\"]",
+];
+
+/// Return the key stat text and the selector that found it, or None if no candidate matched
+pub fn find_key_stat(document: &Html) -> Option<(&'static str, String)> {
+    YCHARTS_STAT_SELECTORS.iter().copied().find_map(|candidate| {
+        let selector = Selector::parse(candidate).ok()?;
+        let text = document.select(&selector).next()?.text().next()?.trim().to_string();
+        Some((candidate, text))
+    })
+}
+
+/// Fetch a YCharts indicator and return the parsed value along with the raw stat text
+pub async fn fetch_ycharts_value_raw(url: &str) -> Result<YChartsRawValue> {
+    let fetcher = ReqwestFetcher::new(Client::builder().user_agent("Mozilla/5.0").build()?);
+    fetch_ycharts_value_raw_with(&fetcher, url).await
+}
+
+/// `fetch_ycharts_value_raw` against any `HttpFetcher`, e.g. a `MockFetcher` serving a saved page
+pub async fn fetch_ycharts_value_raw_with(fetcher: &impl HttpFetcher, url: &str) -> Result<YChartsRawValue> {
+    info!("Fetching data from URL: {}", url);
+
+    // Challenge pages often come with a 403 or 503, so look at the body before the status
+    let (status, response) = fetcher.get(url).await?;
+    if !status.is_success() {
+        if let Some(marker) = detect_interstitial(&response) {
+            warn!("YCharts served an interstitial page with {} for {} (matched '{}')", status, url, marker);
+            return Err(ScrapeError::Interstitial { marker }.into());
+        }
+        return Err(ServiceError::Http(format!("YCharts returned {} for {}", status, url)).into());
+    }
+
+    let document = Html::parse_document(&response);
+    let stat = match find_key_stat(&document) {
+        Some((selector, stat)) => {
+            info!("Matched YCharts key stat with selector '{}'", selector);
+            stat
+        }
+        None => {
+            if let Some(marker) = detect_interstitial(&response) {
+                warn!("YCharts served an interstitial page for {} (matched '{}')", url, marker);
+                return Err(ScrapeError::Interstitial { marker }.into());
+            }
+            return Err(ScrapeError::StatNotFound.into());
+        }
+    };
+    
+    info!("Found stat text: {}", stat);
+    let (period, value) = parse_ycharts_stat(&stat, YCHARTS_PERCENTAGE_URLS.contains(&url))?;
+
+    Ok(YChartsRawValue {
+        url: url.to_string(),
+        raw_text: stat,
+        period,
+        value,
+    })
+}
+
+/// Parse a YCharts key stat ("24.51 USD for Q1 2024", "1.23% for Jan 2024") into its
+/// period ("2024Q1", "2024-01") and value, with percentages already made decimal. A stat is a
+/// percentage if it has a '%' or comes from a percentage endpoint, so a page that drops the
+/// sign isn't read as a raw value 100 times too large.
+pub fn parse_ycharts_stat(stat: &str, is_percentage_endpoint: bool) -> Result<(String, f64)> {
+    let stat = normalize_currency_symbols(stat);
+    let stat = stat.as_str();
+
+    // IMPROVED REGEX - handles the current YCharts format better
+    let re = Regex::new(r"([-+]?\d*\.?\d+)%?\s*(?:USD)?\s*(?:for)?\s+(?:(Q\d)\s+(\d{4})|(Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec)\s+(\d{4}))")?;
+    
+    if let Some(caps) = re.captures(stat) {
         let value_str = caps.get(1).ok_or(anyhow::anyhow!("No value match"))?.as_str();
         let value = value_str.parse::<f64>()?;
         
@@ -357,26 +1187,20 @@ async fn fetch_ycharts_value(url: &str) -> Result<(String, f64)> {
             format!("{}-{}", year, month_num)
         };
         
-        // Convert percentage to decimal if needed
-        let final_value = if stat.contains('%') {
-            value / 100.0
-        } else {
-            value
-        };
+        let final_value = percent_to_decimal(value, stat, is_percentage_endpoint);
         
         return Ok((period_text, final_value));
     }
     
-    // If regex didn't match, try a simpler approach to at least extract the value
+    // If regex didn't match, try a simpler approach to at least extract the value. Quarter
+    // and year numbers are period text, never the value.
+    let period_token_re = Regex::new(r"\b(?:Q[1-4]|(?:19|20)\d{2})\b").unwrap();
+    let value_text = period_token_re.replace_all(stat, " ");
     let fallback_re = Regex::new(r"([-+]?\d*\.?\d+)%?")?;
-    if let Some(caps) = fallback_re.captures(stat) {
+    if let Some(caps) = fallback_re.captures(&value_text) {
         let value_str = caps.get(1).ok_or(anyhow::anyhow!("No value match with fallback"))?.as_str();
         let value = value_str.parse::<f64>()?;
-        let final_value = if stat.contains('%') {
-            value / 100.0
-        } else {
-            value
-        };
+        let final_value = percent_to_decimal(value, stat, is_percentage_endpoint);
         
         // Try to extract period from text
         let year_re = Regex::new(r"\b(20\d{2})\b").unwrap();
@@ -410,62 +1234,207 @@ async fn fetch_ycharts_value(url: &str) -> Result<(String, f64)> {
         return Ok((period, final_value));
     }
     
-    Err(anyhow::anyhow!("Failed to parse value and period"))
+    Err(anyhow::anyhow!("Failed to parse value and period from stat text: {}", stat))
+}
+
+/// The single place YCharts percentages become decimals ("2.5%" -> 0.025).
+/// Other values are returned unchanged; callers must not divide again.
+fn percent_to_decimal(value: f64, stat: &str, is_percentage_endpoint: bool) -> f64 {
+    if is_percentage_endpoint || stat.contains('%') {
+        value / 100.0
+    } else {
+        value
+    }
+}
+
+/// Strip currency symbols and codes (`$1.76`, `1.76 USD`, `USD 1.76`) so the value
+/// regexes only ever see a bare number followed by the period text
+fn normalize_currency_symbols(stat: &str) -> String {
+    let currency_re = Regex::new(r"[$€£]|\b(?:USD|EUR|GBP)\b").unwrap();
+    let stripped = currency_re.replace_all(stat, " ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    // "-$1.76" leaves "- 1.76" behind, so re-attach the sign to its number
+    let sign_re = Regex::new(r"([-+])\s+(\d)").unwrap();
+    sign_re.replace_all(&stripped, "$1$2").into_owned()
+}
+
+/// Default relative difference between same-period EPS readings that triggers a warning
+const DEFAULT_EPS_DISAGREEMENT_THRESHOLD: f64 = 0.05;
+
+/// Pages the actual EPS is scraped from, from a comma-separated `EPS_SOURCES` list of URLs
+/// (default: just the YCharts S&P 500 EPS page). Earlier sources win ties.
+fn eps_sources() -> Vec<String> {
+    let sources: Vec<String> = std::env::var("EPS_SOURCES")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(String::from)
+        .collect();
+    if sources.is_empty() {
+        vec![YCHARTS_EPS_URL.to_string()]
+    } else {
+        sources
+    }
+}
+
+/// Relative difference above which two readings for the same period are reported,
+/// from `EPS_DISAGREEMENT_THRESHOLD` (e.g. `0.05` for 5%)
+fn eps_disagreement_threshold() -> f64 {
+    std::env::var("EPS_DISAGREEMENT_THRESHOLD")
+        .ok()
+        .and_then(|v| v.trim().parse::<f64>().ok())
+        .filter(|v| v.is_finite() && *v >= 0.0)
+        .unwrap_or(DEFAULT_EPS_DISAGREEMENT_THRESHOLD)
+}
+
+/// One source's scraped EPS
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpsReading {
+    pub source: String,
+    pub period: String,
+    pub value: f64,
+}
+
+/// The reading chosen from several EPS sources, and the same-period readings that disagreed with it
+#[derive(Debug, PartialEq)]
+pub struct EpsReconciliation {
+    pub chosen: EpsReading,
+    pub disagreements: Vec<EpsReading>,
+}
+
+/// Where a parsed YCharts period ends, as `(year, month)`: "2024Q1" ends in month 3, "2024-03"
+/// is month 3 and a year-only "2024-00" sorts before every month of 2024. `None` for "Unknown"
+/// or anything else unparseable, so those rank behind every dated period.
+fn period_rank(period: &str) -> Option<(i32, u32)> {
+    let year: i32 = period.get(..4)?.parse().ok()?;
+    let rest = period.get(4..)?;
+    let month = if let Some(q) = rest.strip_prefix('Q') {
+        let q: u32 = q.parse().ok()?;
+        if !(1..=4).contains(&q) {
+            return None;
+        }
+        q * 3
+    } else {
+        let m: u32 = rest.strip_prefix('-')?.parse().ok()?;
+        if m > 12 {
+            return None;
+        }
+        m
+    };
+    Some((year, month))
+}
+
+/// Pick the reading for the most recent period (the first source listed wins ties) and collect
+/// any other reading for that period whose value differs from it by more than `threshold`,
+/// relative to the chosen value. Readings for older periods are ignored.
+pub fn reconcile_eps(readings: &[EpsReading], threshold: f64) -> Option<EpsReconciliation> {
+    let mut chosen = readings.first()?;
+    for reading in &readings[1..] {
+        if period_rank(&reading.period) > period_rank(&chosen.period) {
+            chosen = reading;
+        }
+    }
+    let chosen = chosen.clone();
+
+    let disagreements = readings.iter()
+        .filter(|r| r.period == chosen.period && r.source != chosen.source)
+        .filter(|r| {
+            let scale = chosen.value.abs().max(f64::EPSILON);
+            (r.value - chosen.value).abs() / scale > threshold
+        })
+        .cloned()
+        .collect();
+
+    Some(EpsReconciliation { chosen, disagreements })
+}
+
+/// Scrape every configured EPS source and reconcile them into one `(quarter, value)`
+async fn fetch_reconciled_eps(fetcher: &impl HttpFetcher) -> Option<(String, f64)> {
+    let mut readings = Vec::new();
+    for source in eps_sources() {
+        match fetch_ycharts_value(fetcher, &source).await {
+            Ok((period, value)) => readings.push(EpsReading { source, period, value }),
+            Err(e) => warn!("Failed to fetch EPS from {}: {}", source, e),
+        }
+    }
+
+    let reconciled = reconcile_eps(&readings, eps_disagreement_threshold())?;
+    for other in &reconciled.disagreements {
+        warn!(
+            "EPS sources disagree for {}: {} from {} vs {} from {}, using the former",
+            reconciled.chosen.period, reconciled.chosen.value, reconciled.chosen.source, other.value, other.source
+        );
+    }
+    if readings.len() > 1 {
+        info!("Using EPS {} for {} from {}", reconciled.chosen.value, reconciled.chosen.period, reconciled.chosen.source);
+    }
+    Some((reconciled.chosen.period, reconciled.chosen.value))
 }
 
-async fn fetch_ycharts_data() -> Result<YChartsData> {
-    let mut quarterly_dividends = HashMap::new();
-    let mut eps_actual = HashMap::new();
-    let mut eps_estimated = HashMap::new();
+/// Fetch every YCharts indicator through `fetcher`; an indicator that fails leaves its field empty
+async fn fetch_ycharts_data_with(fetcher: &impl HttpFetcher) -> YChartsData {
+    let mut quarterly_dividends = BTreeMap::new();
+    let mut eps_actual = BTreeMap::new();
+    let mut eps_estimated = BTreeMap::new();
     let mut cape = (0.0, String::new());
     let mut monthly_return = None;
 
-    // Fetch quarterly dividend
-    if let Ok((quarter, value)) = fetch_ycharts_value(
-        "https://ycharts.com/indicators/sp_500_dividends_per_share"
-    ).await {
+    // The indicators are independent pages, so fetch them concurrently; a failed one
+    // just leaves its field empty
+    let (dividend, eps, forward_eps, cape_value, monthly) = tokio::join!(
+        fetch_ycharts_value(fetcher, YCHARTS_DIVIDEND_URL),
+        fetch_reconciled_eps(fetcher),
+        fetch_ycharts_value(fetcher, YCHARTS_FORWARD_EPS_URL),
+        fetch_ycharts_value(fetcher, YCHARTS_CAPE_URL),
+        fetch_ycharts_value(fetcher, YCHARTS_MONTHLY_RETURN_URL),
+    );
+
+    // Quarterly dividend
+    if let Ok((quarter, value)) = dividend {
         quarterly_dividends.insert(quarter, value);
     }
 
-    // Fetch Current EPS
-    if let Ok((quarter, value)) = fetch_ycharts_value(
-        "https://ycharts.com/indicators/sp_500_eps"
-    ).await {
+    // Current EPS, reconciled across the configured sources
+    if let Some((quarter, value)) = eps {
         eps_actual.insert(quarter, value);
     }
 
-    // Fetch Forward EPS
-    if let Ok((quarter, value)) = fetch_ycharts_value(
-        "https://ycharts.com/indicators/sp_500_earnings_per_share_forward_estimate"
-    ).await {
+    // Forward EPS
+    if let Ok((quarter, value)) = forward_eps {
         eps_estimated.insert(quarter, value);
     }
 
-    // Fetch CAPE with period
-    if let Ok((period, value)) = fetch_ycharts_value(
-        "https://ycharts.com/indicators/cyclically_adjusted_pe_ratio"
-    ).await {
-        cape = (value, period);
+    if let Ok((period, value)) = cape_value {
+        cape = (round_cape(value), period);
     }
 
-    // Fetch monthly return
-    if let Ok((period, value)) = fetch_ycharts_value(
-        "https://ycharts.com/indicators/sp_500_monthly_total_return"
-    ).await {
-        // Value is already converted to decimal by fetch_ycharts_value
+    // Monthly return, already a decimal (see percent_to_decimal)
+    if let Ok((period, value)) = monthly {
         monthly_return = Some((period, value));
     }
 
-    Ok(YChartsData {
+    YChartsData {
         quarterly_dividends,
         eps_actual,
         eps_estimated,
         cape,
         monthly_return,
-    })
+    }
 }
 
-fn update_cache_from_ycharts(cache: &mut crate::models::MarketCache, ycharts_data: YChartsData) {
+/// Round a fetched CAPE to `CAPE_DECIMALS` places (default 2) before it is cached
+fn round_cape(value: f64) -> f64 {
+    round_to(value, ingestion_decimals("CAPE_DECIMALS"))
+}
+
+/// Merge scraped values into the cache, returning the names of the fields that changed
+fn update_cache_from_ycharts(cache: &mut MarketCache, ycharts_data: YChartsData) -> Vec<&'static str> {
+    let before = cache.clone();
+
     // Update quarterly dividends
     for (quarter, value) in ycharts_data.quarterly_dividends {
         cache.quarterly_dividends.insert(quarter, value);
@@ -485,15 +1454,40 @@ fn update_cache_from_ycharts(cache: &mut crate::models::MarketCache, ycharts_dat
         cache.latest_monthly_return = return_value;
     }
     
-    cache.current_cape = ycharts_data.cape.0;
-    cache.cape_period = ycharts_data.cape.1;
+    // A failed CAPE fetch has no period; keep the cached value rather than zeroing it
+    if !ycharts_data.cape.1.is_empty() {
+        cache.current_cape = ycharts_data.cape.0;
+        cache.cape_period = ycharts_data.cape.1;
+    }
+
+    let mut changed = Vec::new();
+    if cache.quarterly_dividends != before.quarterly_dividends {
+        changed.push("quarterly_dividends");
+    }
+    if cache.eps_actual != before.eps_actual {
+        changed.push("eps_actual");
+    }
+    if cache.eps_estimated != before.eps_estimated {
+        changed.push("eps_estimated");
+    }
+    if cache.latest_month != before.latest_month || cache.latest_monthly_return != before.latest_monthly_return {
+        changed.push("latest_monthly_return");
+    }
+    if cache.current_cape != before.current_cape {
+        changed.push("current_cape");
+    }
+    if cache.cape_period != before.cape_period {
+        changed.push("cape_period");
+    }
+    changed
 }
 
-pub async fn update_monthly_data(db: &Arc<DbStore>, month: &str, return_value: f64) ->  Result<()> {
+/// Append `month` to the monthly sheet if it isn't there yet. Returns true if the sheet was written.
+pub async fn update_monthly_data(db: &Arc<DbStore>, month: &str, return_value: f64) ->  Result<bool> {
     info!("Updating monthly data for {}: {}", month, return_value);
     
     // Get existing monthly data
-    let mut monthly_data = db.sheets_store.get_monthly_data().await?;
+    let mut monthly_data = db.store.get_monthly_data().await?;
     
     // Check if this month already exists
     let month_exists = monthly_data.iter().any(|data| data.month == month);
@@ -510,27 +1504,97 @@ pub async fn update_monthly_data(db: &Arc<DbStore>, month: &str, return_value: f
         monthly_data.sort_by(|a, b| a.month.cmp(&b.month));
         
         // Update the sheet
-        db.sheets_store.update_monthly_data(&monthly_data).await?;
+        db.update_monthly_data(&monthly_data).await?;
         info!("Successfully updated monthly data sheet with new month: {}", month);
+        Ok(true)
     } else {
         info!("Month {} already exists in monthly data, skipping update", month);
+        Ok(false)
     }
-    
-    Ok(())
 }
 
-pub async fn update_quarterly_data(db: &Arc<DbStore>, quarterly_data: &HashMap<String, f64>, data_type: &str) ->  Result<()> {
-    if quarterly_data.is_empty() {
-        info!("No quarterly {} data to update", data_type);
-        return Ok(());
+/// Scraped quarterly values to merge into the QuarterlyData sheet, one map per column
+pub struct QuarterlyUpdates<'a> {
+    pub dividends: &'a BTreeMap<String, f64>,
+    pub eps_actual: &'a BTreeMap<String, f64>,
+    pub eps_estimated: &'a BTreeMap<String, f64>,
+}
+
+impl QuarterlyUpdates<'_> {
+    fn is_empty(&self) -> bool {
+        self.dividends.is_empty() && self.eps_actual.is_empty() && self.eps_estimated.is_empty()
+    }
+}
+
+/// Merge all quarterly columns with a single read and a single write of the QuarterlyData sheet.
+/// Returns true if the sheet was written.
+pub async fn update_quarterly_data(db: &Arc<DbStore>, updates: &QuarterlyUpdates<'_>) ->  Result<bool> {
+    if updates.is_empty() {
+        info!("No quarterly data to update");
+        return Ok(false);
     }
 
-    info!("Updating quarterly {} data with {} entries", data_type, quarterly_data.len());
-    
     // Get existing quarterly data
-    let mut existing_data = db.sheets_store.get_quarterly_data().await?;
+    let mut existing_data = db.store.get_quarterly_data().await?;
     info!("Retrieved {} existing quarterly records", existing_data.len());
-    
+
+    for attempt in 1..=MAX_WRITE_ATTEMPTS {
+        let fingerprint = rows_fingerprint(&existing_data);
+
+        let mut updates_made = false;
+        for (quarterly_data, data_type) in [
+            (updates.dividends, "dividend"),
+            (updates.eps_actual, "eps_actual"),
+            (updates.eps_estimated, "eps_estimated"),
+        ] {
+            if !quarterly_data.is_empty() {
+                info!("Updating quarterly {} data with {} entries", data_type, quarterly_data.len());
+                updates_made |= merge_quarterly_values(&mut existing_data, quarterly_data, data_type);
+            }
+        }
+
+        if !updates_made {
+            info!("No updates needed for quarterly data");
+            return Ok(false);
+        }
+
+        // Re-read before writing so manual edits made since our read aren't clobbered
+        let current_data = db.store.get_quarterly_data().await?;
+        if rows_fingerprint(&current_data) != fingerprint {
+            warn!("QuarterlyData changed while updating (attempt {}), re-applying updates to fresh data", attempt);
+            existing_data = current_data;
+            continue;
+        }
+
+        info!("Saving updated quarterly data to sheet");
+        
+        // Sort the data by quarter for consistency
+        existing_data.sort_by(|a, b| {
+            // Parse quarters like "2024Q1" for proper sorting
+            let parse_quarter = |q: &str| -> (i32, i32) {
+                let year = q.get(0..4).unwrap_or("0000").parse::<i32>().unwrap_or(0);
+                let quarter = q.get(4..6).unwrap_or("0").parse::<i32>().unwrap_or(0);
+                (year, quarter)
+            };
+            
+            let a_parts = parse_quarter(&a.quarter);
+            let b_parts = parse_quarter(&b.quarter);
+            a_parts.cmp(&b_parts)
+        });
+        
+        db.update_quarterly_data(&existing_data).await?;
+        info!("Quarterly data successfully updated");
+        return Ok(true);
+    }
+
+    Err(anyhow::anyhow!(
+        "QuarterlyData kept changing during update, giving up after {} attempts",
+        MAX_WRITE_ATTEMPTS
+    ))
+}
+
+/// Apply one column's worth of quarterly values onto the existing rows, returning whether anything changed
+fn merge_quarterly_values(existing_data: &mut Vec<QuarterlyData>, quarterly_data: &BTreeMap<String, f64>, data_type: &str) -> bool {
     let mut updates_made = false;
     
     // Update existing or add new quarterly data
@@ -598,57 +1662,199 @@ pub async fn update_quarterly_data(db: &Arc<DbStore>, quarterly_data: &HashMap<S
         }
     }
     
-    // If we made any updates, save the data back to the sheet
-    if updates_made {
-        info!("Saving updated quarterly data to sheet");
-        
-        // Sort the data by quarter for consistency
-        existing_data.sort_by(|a, b| {
-            // Parse quarters like "2024Q1" for proper sorting
-            let parse_quarter = |q: &str| -> (i32, i32) {
-                let year = q.get(0..4).unwrap_or("0000").parse::<i32>().unwrap_or(0);
-                let quarter = q.get(4..6).unwrap_or("0").parse::<i32>().unwrap_or(0);
-                (year, quarter)
-            };
-            
-            let a_parts = parse_quarter(&a.quarter);
-            let b_parts = parse_quarter(&b.quarter);
-            a_parts.cmp(&b_parts)
-        });
-        
-        db.sheets_store.update_quarterly_data(&existing_data).await?;
-        info!("Quarterly data successfully updated");
+    updates_made
+}
+
+/// Fields of a historical record to overwrite, collected before touching the sheet so the
+/// same patch can be re-applied if the row changes underneath us
+#[derive(Debug, Default)]
+struct HistoricalUpdate {
+    sp500_price: Option<f64>,
+    dividend: Option<f64>,
+    eps: Option<f64>,
+    cape: Option<f64>,
+    total_return: Option<f64>,
+    /// Prior year's closing price, used to approximate `total_return` when no precise
+    /// value is available
+    approximate_return_from: Option<f64>,
+}
+
+impl HistoricalUpdate {
+    fn is_empty(&self) -> bool {
+        self.sp500_price.is_none()
+            && self.dividend.is_none()
+            && self.eps.is_none()
+            && self.cape.is_none()
+            && self.total_return.is_none()
+            && self.approximate_return_from.is_none()
+    }
+
+    fn apply(&self, record: &mut HistoricalRecord) {
+        if let Some(price) = self.sp500_price {
+            record.sp500_price = price;
+        }
+        if let Some(dividend) = self.dividend {
+            record.dividend = dividend;
+        }
+        if let Some(eps) = self.eps {
+            record.eps = eps;
+        }
+        if let Some(cape) = self.cape {
+            record.cape = cape;
+        }
+        if let Some(total_return) = self.total_return {
+            record.total_return = total_return;
+            record.total_return_approximated = false;
+        }
+        if record.sp500_price > 0.0 && record.dividend > 0.0 {
+            record.dividend_yield = record.dividend / record.sp500_price;
+        }
+        // Never replace a precise monthly-derived return with an approximation
+        let has_precise_return = record.total_return != 0.0 && !record.total_return_approximated;
+        if let Some(start_price) = self.approximate_return_from.filter(|_| !has_precise_return) {
+            if let Some(approx) = approximate_total_return(start_price, record.sp500_price, record.dividend_yield) {
+                record.total_return = approx;
+                record.total_return_approximated = true;
+            }
+        }
+    }
+}
+
+/// Whether the prior year's final close still needs to be written, given the last captured year
+fn needs_year_close_capture(last_captured: i32, current_year: i32) -> bool {
+    last_captured < current_year - 1
+}
+
+/// Starting value for a cache written before the `last_year_close_captured` marker existed.
+/// Earlier year-ends are assumed handled, except when the cached price is still from last
+/// year: then this is the first update of the new year and its close hasn't been captured.
+fn initial_year_close_marker(last_price_at: DateTime<Utc>, current_year: i32) -> i32 {
+    let prev_year = current_year - 1;
+    if last_price_at.year() == prev_year {
+        prev_year - 1
     } else {
-        info!("No updates needed for quarterly data");
+        prev_year
     }
-    
-    Ok(())
 }
 
-async fn check_historical_updates(db: &Arc<DbStore>, cache: &crate::models::MarketCache) -> Result<()> {
-    let current_year = Utc::now().year() as i32;
+/// Write the previous year's final close to the historical sheet exactly once, at the first
+/// update of the new year. Returns true if the cache marker changed and needs saving.
+async fn capture_prior_year_close(db: &Arc<DbStore>, cache: &mut MarketCache, report: &mut RefreshReport) -> Result<bool> {
+    let current_year = Utc::now().year();
     let prev_year = current_year - 1;
-    
-    // Get existing record or create new one
-    let mut historical_record = match db.get_historical_year(prev_year).await? {
-        Some(record) => record,
-        None => HistoricalRecord {
-            year: prev_year,
-            sp500_price: 0.0,
-            dividend: 0.0,
-            dividend_yield: 0.0,
-            eps: 0.0,
-            cape: 0.0,
-            inflation: 0.0,
-            total_return: 0.0,
-            cumulative_return: 0.0
+    let mut marker_changed = false;
+
+    if cache.last_year_close_captured == 0 {
+        cache.last_year_close_captured = initial_year_close_marker(cache.timestamps.yahoo_price, current_year);
+        info!("Seeding last_year_close_captured marker with {}", cache.last_year_close_captured);
+        marker_changed = true;
+    }
+
+    if !needs_year_close_capture(cache.last_year_close_captured, current_year) {
+        return Ok(marker_changed);
+    }
+
+    if cache.daily_close_sp500_price <= 0.0 {
+        warn!("No daily close available to record as the {} closing price", prev_year);
+        return Ok(marker_changed);
+    }
+
+    let update = HistoricalUpdate {
+        sp500_price: Some(cache.daily_close_sp500_price),
+        ..Default::default()
+    };
+    db.modify_historical_year(prev_year, |record| update.apply(record)).await?;
+    info!("Recorded historical closing price for {}: {}", prev_year, cache.daily_close_sp500_price);
+
+    cache.last_year_close_captured = prev_year;
+    report.prior_year_close_captured = Some(prev_year);
+    Ok(true)
+}
+
+/// Year whose historical row should be finalized: the current year once all twelve monthly
+/// returns and its Q4 fundamentals are in (so a finished December isn't held back until
+/// January), otherwise the previous year
+fn historical_target_year(cache: &MarketCache, monthly_data: &[MonthlyData], current_year: i32) -> i32 {
+    let q4_key = format!("{}Q4", current_year);
+    let has_q4 = cache.eps_actual.contains_key(&q4_key) || cache.quarterly_dividends.contains_key(&q4_key);
+    if has_q4 && compute_yearly_return(monthly_data, current_year).is_some() {
+        current_year
+    } else {
+        current_year - 1
+    }
+}
+
+/// Fold completed quarterly, monthly and CAPE data into the target year's historical row
+/// (see `historical_target_year`). Once the current year is the target the previous year is
+/// refreshed too, so data that landed after it was last written isn't lost, and older years
+/// without a total return are backfilled (see `return_backfill`); all years go out in one store
+/// write. Returns the latest year written, if any.
+async fn check_historical_updates(db: &Arc<DbStore>, cache: &MarketCache) -> Result<Option<i32>> {
+    let monthly_data = db.store.get_monthly_data().await?;
+    let current_year = Utc::now().year();
+    let target_year = historical_target_year(cache, &monthly_data, current_year);
+
+    let mut updates = Vec::new();
+    for year in (current_year - 1)..=target_year {
+        let update = historical_update(db, cache, &monthly_data, year).await?;
+        if !update.is_empty() {
+            updates.push((year, update));
+        }
+    }
+
+    let backfill = return_backfill(&db.get_historical_data().await?, &monthly_data);
+    if !backfill.is_empty() {
+        info!("Approximating total return for {} historical year(s) without monthly data", backfill.len());
+    }
+    for (year, update) in backfill {
+        if !updates.iter().any(|(y, _)| *y == year) {
+            updates.push((year, update));
         }
+    }
+
+    let years: Vec<i32> = updates.iter().map(|(year, _)| *year).collect();
+    let Some(&latest) = years.iter().max() else {
+        return Ok(None);
     };
-    
-    let mut updates_needed = false;
 
-    // Check if we have new Q4 data to update previous year
-    let q4_key = format!("{}Q4", prev_year);
+    db.modify_historical_years(&years, |year, record| {
+        if let Some((_, update)) = updates.iter().find(|(y, _)| *y == year) {
+            update.apply(record);
+        }
+    }).await?;
+    info!("Successfully updated historical records for {:?}", years);
+    Ok(Some(latest))
+}
+
+/// Years with no total return yet and no complete monthly data, each with an update
+/// approximating it from the prior year's close (see `approximate_total_return`). Years without a
+/// consecutive prior year, or without prices, are left alone.
+fn return_backfill(records: &[HistoricalRecord], monthly_data: &[MonthlyData]) -> Vec<(i32, HistoricalUpdate)> {
+    let mut sorted: Vec<&HistoricalRecord> = records.iter().collect();
+    sorted.sort_by_key(|r| r.year);
+    sorted.windows(2)
+        .filter(|pair| pair[1].year == pair[0].year + 1)
+        .filter(|pair| pair[0].sp500_price > 0.0 && pair[1].sp500_price > 0.0)
+        .filter(|pair| pair[1].total_return == 0.0)
+        .filter(|pair| compute_yearly_return(monthly_data, pair[1].year).is_none())
+        .map(|pair| {
+            let update = HistoricalUpdate { approximate_return_from: Some(pair[0].sp500_price), ..Default::default() };
+            (pair[1].year, update)
+        })
+        .collect()
+}
+
+/// What the cached quarterly, monthly and CAPE data can fill in for `target_year`'s historical row
+async fn historical_update(
+    db: &Arc<DbStore>,
+    cache: &MarketCache,
+    monthly_data: &[MonthlyData],
+    target_year: i32,
+) -> Result<HistoricalUpdate> {
+    let mut update = HistoricalUpdate::default();
+
+    // Check if we have new Q4 data to update the target year
+    let q4_key = format!("{}Q4", target_year);
     
     if cache.eps_actual.contains_key(&q4_key) || cache.quarterly_dividends.contains_key(&q4_key) {
         let mut eps_sum = 0.0;
@@ -658,7 +1864,7 @@ async fn check_historical_updates(db: &Arc<DbStore>, cache: &crate::models::Mark
 
         // Sum up quarterly values
         for quarter in 1..=4 {
-            let q = format!("{}Q{}", prev_year, quarter);
+            let q = format!("{}Q{}", target_year, quarter);
             
             if let Some(eps) = cache.eps_actual.get(&q) {
                 eps_sum += eps;
@@ -674,65 +1880,115 @@ async fn check_historical_updates(db: &Arc<DbStore>, cache: &crate::models::Mark
         }
 
         if have_complete_eps {
-            historical_record.eps = eps_sum;
-            updates_needed = true;
-            info!("Updated historical EPS for {}: {}", prev_year, eps_sum);
+            update.eps = Some(eps_sum);
+            info!("Updated historical EPS for {}: {}", target_year, eps_sum);
         }
         
         if have_complete_div {
-            historical_record.dividend = div_sum;
-            updates_needed = true;
-            info!("Updated historical dividend for {}: {}", prev_year, div_sum);
+            update.dividend = Some(div_sum);
+            info!("Updated historical dividend for {}: {}", target_year, div_sum);
         }
     }
 
-    // Check for year change since last Yahoo price update
-    let last_update = cache.timestamps.yahoo_price.naive_utc().date();
-    let current_date = Utc::now().naive_utc().date();
-    
-    if last_update.year() < current_date.year() && cache.daily_close_sp500_price > 0.0 {
-        historical_record.sp500_price = cache.daily_close_sp500_price;
-        updates_needed = true;
-        info!("Updated historical closing price for {} based on year change: {}", 
-              prev_year, cache.daily_close_sp500_price);
-    }
-
-    // Check if we have complete monthly data for the previous year
-    let monthly_data = db.sheets_store.get_monthly_data().await?;
-    if let Some(yearly_return) = compute_yearly_return(&monthly_data, prev_year) {
-        historical_record.total_return = yearly_return;
-        updates_needed = true;
-        info!("Updated historical total return for {}: {}", prev_year, yearly_return);
+    // Check if we have complete monthly data for the target year
+    if let Some(yearly_return) = compute_yearly_return(monthly_data, target_year) {
+        update.total_return = Some(yearly_return);
+        info!("Updated historical total return for {}: {}", target_year, yearly_return);
+    } else if let Some(start) = db.get_historical_year(target_year - 1).await?.filter(|r| r.sp500_price > 0.0) {
+        // No complete monthly series: fall back to price change plus dividend yield
+        update.approximate_return_from = Some(start.sp500_price);
+        info!("Monthly returns incomplete for {}, approximating total return from prices", target_year);
     }
 
     // Check if we have a December CAPE value
-    if cache.cape_period == format!("Dec {}", prev_year) {
-        historical_record.cape = cache.current_cape;
-        updates_needed = true;
-        info!("Updated historical CAPE for {}: {}", prev_year, cache.current_cape);
+    if cache.cape_period == format!("Dec {}", target_year) {
+        update.cape = Some(cache.current_cape);
+        info!("Updated historical CAPE for {}: {}", target_year, cache.current_cape);
     }
 
-    if updates_needed {
-        if historical_record.sp500_price > 0.0 && historical_record.dividend > 0.0 {
-            historical_record.dividend_yield = historical_record.dividend / historical_record.sp500_price;
-        }
-        
-        db.update_historical_record(historical_record).await?;
-        info!("Successfully updated historical record for {}", prev_year);
-    }
+    Ok(update)
+}
+
+/// Market metrics over all historical years, with "current" CAGRs over the last `window_years`
+pub async fn get_market_metrics(db: &Arc<DbStore>, window_years: i32) -> Result<MarketMetrics> {
+    let historical_data = db.get_historical_data().await?;
+    calculate_market_metrics(&historical_data, window_years)
+}
 
-    Ok(())
+/// Market metrics over the historical years `start_year` through `end_year` inclusive
+pub async fn get_market_metrics_range(
+    db: &Arc<DbStore>,
+    start_year: i32,
+    end_year: i32,
+    window_years: i32,
+) -> Result<MarketMetrics> {
+    let historical_data = get_historical_data_range(db, start_year, end_year).await?;
+    calculate_market_metrics(&historical_data, window_years)
 }
 
-pub async fn get_market_metrics(db: &Arc<DbStore>) -> Result<MarketMetrics> {
+pub async fn get_average_dividend_yield(db: &Arc<DbStore>) -> Result<AverageDividendYield> {
     let historical_data = db.get_historical_data().await?;
-    calculate_market_metrics(&historical_data)
+    Ok(calculate_average_dividend_yield(&historical_data))
+}
+
+pub async fn get_real_returns(db: &Arc<DbStore>) -> Result<Vec<RealReturnPoint>> {
+    let historical_data = db.get_historical_data().await?;
+    Ok(calculate_real_return_series(&historical_data))
+}
+
+/// Sharpe-like ratio using the cached real T-bill yield (Treasury yield in percent minus BLS
+/// inflation as a decimal) as the risk-free rate
+pub async fn get_sharpe_like_ratio(db: &Arc<DbStore>) -> Result<SharpeLikeRatio> {
+    let historical_data = db.get_historical_data().await?;
+    let cache = db.get_market_cache().await?;
+    let risk_free_rate = (cache.tbill_yield != 0.0 && cache.inflation_rate != 0.0)
+        .then(|| cache.tbill_yield / 100.0 - cache.inflation_rate);
+    Ok(calculate_sharpe_like_ratio(&historical_data, risk_free_rate))
+}
+
+pub async fn get_drawdown_series(db: &Arc<DbStore>) -> Result<Vec<DrawdownPoint>> {
+    let historical_data = db.get_historical_data().await?;
+    Ok(calculate_drawdown_series(&historical_data))
+}
+
+/// Monthly total returns sorted by month, optionally limited to one year
+pub async fn get_monthly_returns(db: &Arc<DbStore>, year: Option<i32>) -> Result<Vec<MonthlyData>> {
+    let months = db.get_monthly_data().await?;
+    let Some(year) = year else {
+        return Ok(months);
+    };
+    let prefix = format!("{}-", year);
+    Ok(months.into_iter().filter(|m| m.month.starts_with(&prefix)).collect())
+}
+
+/// Monthly total returns from `start` through `end` inclusive, both `YYYY-MM`
+pub async fn get_monthly_returns_range(db: &Arc<DbStore>, start: &str, end: &str) -> Result<Vec<MonthlyData>> {
+    let months = db.get_monthly_data().await?;
+    Ok(months.into_iter()
+        .filter(|m| m.month.as_str() >= start && m.month.as_str() <= end)
+        .collect())
+}
+
+/// Stored quarterly dividends and EPS, most recent first, optionally from a `(year, quarter)` on
+pub async fn get_quarterly_data(db: &Arc<DbStore>, from: Option<(i32, i32)>) -> Result<Vec<QuarterlyData>> {
+    let mut quarters = db.store.get_quarterly_data().await?;
+    if let Some(from) = from {
+        quarters.retain(|q| quarter_sort_key(&q.quarter) >= from);
+    }
+    quarters.sort_by_key(|q| std::cmp::Reverse(quarter_sort_key(&q.quarter)));
+    Ok(quarters)
 }
 
 pub async fn get_historical_data(db: &Arc<DbStore>) -> Result<Vec<HistoricalRecord>> {
     db.get_historical_data().await
 }
 
+/// Every historical year with its price also restated in the latest year's dollars
+pub async fn get_historical_data_with_real_price(db: &Arc<DbStore>) -> Result<Vec<HistoricalRecordWithRealPrice>> {
+    let historical_data = db.get_historical_data().await?;
+    Ok(calculate_real_price_series(&historical_data))
+}
+
 pub async fn get_historical_data_range(
     db: &Arc<DbStore>, 
     start_year: i32, 
@@ -758,4 +2014,712 @@ fn compute_yearly_return(monthly_data: &[MonthlyData], year: i32) -> Option<f64>
     } else {
         None
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use crate::services::http::MockFetcher;
+    use crate::services::local_store::LocalSnapshot;
+    use crate::services::test_support::{local_db, market_cache, ycharts_page, ENV_LOCK};
+
+    #[test]
+    fn parse_ycharts_stat_strips_currency_symbols() {
+        assert_eq!(parse_ycharts_stat("$1.76 for Q1 2024", false).unwrap(), ("2024Q1".to_string(), 1.76));
+        assert_eq!(parse_ycharts_stat("1.76 USD for Q1 2024", false).unwrap(), ("2024Q1".to_string(), 1.76));
+
+        let (_, value) = parse_ycharts_stat("USD 1.76", false).unwrap();
+        assert_eq!(value, 1.76);
+    }
+
+    fn eps_reading(source: &str, period: &str, value: f64) -> EpsReading {
+        EpsReading { source: source.to_string(), period: period.to_string(), value }
+    }
+
+    #[test]
+    fn disagreeing_sources_for_the_latest_period_are_reported() {
+        let readings = vec![
+            eps_reading("sp_500_eps", "2023Q4", 50.0),
+            eps_reading("sp_500_eps", "2024Q1", 52.0),
+            eps_reading("sp_500_operating_eps", "2024Q1", 58.0),
+        ];
+
+        let reconciled = reconcile_eps(&readings, 0.05).unwrap();
+        assert_eq!(reconciled.chosen, readings[1]);
+        assert_eq!(reconciled.disagreements, vec![readings[2].clone()]);
+
+        // Within the threshold they agree
+        let reconciled = reconcile_eps(&readings, 0.2).unwrap();
+        assert!(reconciled.disagreements.is_empty());
+    }
+
+    #[test]
+    fn latest_period_is_ranked_by_date_not_by_string() {
+        // "Unknown" and "2024Q1" both sort above "2024-06" as strings
+        let readings = vec![
+            eps_reading("a", "Unknown", 1.0),
+            eps_reading("b", "2024Q1", 2.0),
+            eps_reading("c", "2024-06", 3.0),
+        ];
+        assert_eq!(reconcile_eps(&readings, 0.05).unwrap().chosen, readings[2]);
+
+        // A quarter outranks the months before its end, and ties go to the first source
+        let readings = vec![
+            eps_reading("a", "2024-02", 1.0),
+            eps_reading("b", "2024Q1", 2.0),
+            eps_reading("c", "2024Q1", 2.0),
+        ];
+        assert_eq!(reconcile_eps(&readings, 0.05).unwrap().chosen, readings[1]);
+
+        // Unknown periods are only chosen when nothing is dated
+        let readings = vec![eps_reading("a", "Unknown", 1.0)];
+        assert_eq!(reconcile_eps(&readings, 0.05).unwrap().chosen, readings[0]);
+        assert_eq!(reconcile_eps(&[], 0.05), None);
+    }
+
+    #[tokio::test]
+    async fn monthly_return_percent_is_stored_as_a_decimal_once() {
+        let fetcher = MockFetcher::new()
+            .with_response(YCHARTS_MONTHLY_RETURN_URL, &ycharts_page("2.5% for Jan 2024"));
+        let raw = fetch_ycharts_value_raw_with(&fetcher, YCHARTS_MONTHLY_RETURN_URL).await.unwrap();
+
+        let mut cache = market_cache();
+        update_cache_from_ycharts(&mut cache, YChartsData {
+            quarterly_dividends: BTreeMap::new(),
+            eps_actual: BTreeMap::new(),
+            eps_estimated: BTreeMap::new(),
+            cape: (0.0, String::new()),
+            monthly_return: Some((raw.period, raw.value)),
+        });
+
+        assert_eq!(cache.latest_month, "2024-01");
+        assert_eq!(cache.latest_monthly_return, 0.025);
+    }
+
+    fn quarterly_value(quarter: &str, value: f64) -> QuarterlyValue {
+        QuarterlyValue { final_quarter: quarter.to_string(), value, estimated: false }
+    }
+
+    #[test]
+    fn ttm_dividend_yield_is_none_without_a_price_or_ttm_dividend() {
+        let ttm = quarterly_value("2024Q4", 75.0);
+
+        assert_eq!(calculate_ttm_dividend_yield(Some(&ttm), 6000.0), Some(0.0125));
+        assert_eq!(calculate_ttm_dividend_yield(Some(&ttm), 0.0), None);
+        assert_eq!(calculate_ttm_dividend_yield(None, 6000.0), None);
+    }
+
+    #[test]
+    fn year_close_is_needed_once_per_new_year() {
+        // First update of 2025 with 2023's close already captured
+        assert!(needs_year_close_capture(2023, 2025));
+        // Already captured 2024's close
+        assert!(!needs_year_close_capture(2024, 2025));
+        // Still 2024: its close isn't final yet
+        assert!(!needs_year_close_capture(2023, 2024));
+    }
+
+    #[test]
+    fn initial_marker_leaves_last_years_close_pending_when_the_price_is_from_last_year() {
+        let dec_31 = Utc.with_ymd_and_hms(2024, 12, 31, 21, 0, 0).unwrap();
+        let jan_2 = Utc.with_ymd_and_hms(2025, 1, 2, 15, 0, 0).unwrap();
+
+        // The cache still holds 2024's last price on the first update of 2025
+        assert_eq!(initial_year_close_marker(dec_31, 2025), 2023);
+        assert!(needs_year_close_capture(initial_year_close_marker(dec_31, 2025), 2025));
+
+        // Already priced in 2025, or never priced: assume 2024 was handled
+        assert_eq!(initial_year_close_marker(jan_2, 2025), 2024);
+        assert_eq!(initial_year_close_marker(DateTime::UNIX_EPOCH, 2025), 2024);
+    }
+
+    #[tokio::test]
+    async fn first_update_of_the_year_captures_last_years_close_without_a_marker() {
+        let current_year = Utc::now().year();
+        let prev_year = current_year - 1;
+        let snapshot = LocalSnapshot {
+            historical_data: vec![HistoricalRecord { year: prev_year, ..Default::default() }],
+            ..Default::default()
+        };
+        let store = local_db(&snapshot);
+
+        let mut cache = market_cache();
+        cache.timestamps.yahoo_price = Utc.with_ymd_and_hms(prev_year, 12, 31, 21, 0, 0).unwrap();
+        cache.daily_close_sp500_price = 5881.63;
+        let mut report = RefreshReport::default();
+
+        assert!(capture_prior_year_close(&store.db, &mut cache, &mut report).await.unwrap());
+
+        assert_eq!(cache.last_year_close_captured, prev_year);
+        assert_eq!(report.prior_year_close_captured, Some(prev_year));
+        let record = store.db.get_historical_year(prev_year).await.unwrap().unwrap();
+        assert_eq!(record.sp500_price, 5881.63);
+
+        // A second update the same year leaves the sheet alone
+        let mut report = RefreshReport::default();
+        assert!(!capture_prior_year_close(&store.db, &mut cache, &mut report).await.unwrap());
+        assert_eq!(report.prior_year_close_captured, None);
+    }
+
+    fn quarter(label: &str, eps_actual: Option<f64>, eps_estimated: Option<f64>) -> QuarterlyData {
+        QuarterlyData { quarter: label.to_string(), dividend: None, eps_actual, eps_estimated }
+    }
+
+    #[test]
+    fn estimate_for_a_quarter_with_an_actual_is_skipped() {
+        let data = vec![
+            quarter("2024Q3", Some(54.0), Some(53.0)),
+            quarter("2024Q4", Some(56.0), Some(55.0)),
+            quarter("2025Q1", None, Some(57.0)),
+            quarter("2025Q2", None, Some(58.0)),
+            quarter("2025Q3", None, Some(59.0)),
+            quarter("2025Q4", None, Some(60.0)),
+        ];
+
+        let sum = sum_next_estimated_quarters(&data).unwrap();
+
+        assert_eq!(sum.final_quarter, "2025Q4");
+        assert_eq!(sum.value, 57.0 + 58.0 + 59.0 + 60.0);
+    }
+
+    #[tokio::test]
+    async fn interstitial_page_is_reported_as_an_interstitial() {
+        let page = include_str!("../../tests/fixtures/ycharts_interstitial.html");
+        let fetcher = MockFetcher::new().with_response(YCHARTS_CAPE_URL, page);
+
+        let err = fetch_ycharts_value_raw_with(&fetcher, YCHARTS_CAPE_URL).await.unwrap_err();
+
+        match err.downcast_ref::<ScrapeError>() {
+            Some(ScrapeError::Interstitial { marker }) => assert_eq!(*marker, "Just a moment..."),
+            other => panic!("expected an interstitial error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn an_interstitial_served_with_an_error_status_is_still_an_interstitial() {
+        let page = include_str!("../../tests/fixtures/ycharts_interstitial.html");
+        for status in [reqwest::StatusCode::FORBIDDEN, reqwest::StatusCode::SERVICE_UNAVAILABLE] {
+            let fetcher = MockFetcher::new().with_status(YCHARTS_CAPE_URL, status, page);
+
+            let err = fetch_ycharts_value_raw_with(&fetcher, YCHARTS_CAPE_URL).await.unwrap_err();
+
+            assert!(
+                matches!(err.downcast_ref::<ScrapeError>(), Some(ScrapeError::Interstitial { .. })),
+                "{}: {}", status, err
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn an_ordinary_error_page_is_an_http_error() {
+        let fetcher = MockFetcher::new()
+            .with_status(YCHARTS_CAPE_URL, reqwest::StatusCode::SERVICE_UNAVAILABLE, "<html>Service Unavailable</html>");
+
+        let err = fetch_ycharts_value_raw_with(&fetcher, YCHARTS_CAPE_URL).await.unwrap_err();
+
+        assert!(matches!(err.downcast_ref::<ServiceError>(), Some(ServiceError::Http(_))), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn a_saved_indicator_page_parses() {
+        let fetcher = MockFetcher::new()
+            .with_response(YCHARTS_CAPE_URL, &ycharts_page("37.05 for Dec 2024"));
+
+        let raw = fetch_ycharts_value_raw_with(&fetcher, YCHARTS_CAPE_URL).await.unwrap();
+
+        assert_eq!(raw.raw_text, "37.05 for Dec 2024");
+        assert_eq!((raw.period.as_str(), raw.value), ("2024-12", 37.05));
+    }
+
+    #[test]
+    fn approximated_return_tracks_the_monthly_compounded_one() {
+        // 1% a month with no dividend: 12.68% compounded, which the price change matches exactly
+        let monthly: Vec<MonthlyData> = (1..=12)
+            .map(|m| MonthlyData { month: format!("2023-{:02}", m), total_return: 0.01 })
+            .collect();
+        let precise = compute_yearly_return(&monthly, 2023).unwrap();
+        let approx = approximate_total_return(4000.0, 4000.0 * 1.01f64.powi(12), 0.0).unwrap();
+        assert!((precise - approx).abs() < 1e-12);
+
+        // An incomplete year has no precise return, so only the approximation is available
+        assert_eq!(compute_yearly_return(&monthly[..11], 2023), None);
+    }
+
+    #[test]
+    fn approximation_fills_a_gap_but_never_replaces_a_precise_return() {
+        let update = HistoricalUpdate { approximate_return_from: Some(4000.0), ..Default::default() };
+
+        let mut gap = HistoricalRecord { year: 1990, sp500_price: 4400.0, dividend: 88.0, ..Default::default() };
+        update.apply(&mut gap);
+        assert!((gap.total_return - (0.1 + 0.02)).abs() < 1e-12);
+        assert!(gap.total_return_approximated);
+
+        let mut precise = HistoricalRecord { year: 2023, sp500_price: 4400.0, total_return: 0.15, ..Default::default() };
+        update.apply(&mut precise);
+        assert_eq!(precise.total_return, 0.15);
+        assert!(!precise.total_return_approximated);
+    }
+
+    #[tokio::test]
+    async fn a_ycharts_outage_does_not_count_as_a_fundamentals_refresh() {
+        let store = local_db(&LocalSnapshot::default());
+        let mut cache = market_cache();
+        cache.current_cape = 37.05;
+        cache.cape_period = "2024-12".to_string();
+        let before = cache.timestamps.ycharts_data;
+        let mut report = RefreshReport::default();
+
+        // No canned responses, so every indicator fetch fails
+        let updated = refresh_fundamentals_with(&store.db, &MockFetcher::new(), &mut cache, &mut report).await;
+
+        assert!(!updated);
+        assert!(report.updated.is_empty());
+        assert_eq!(cache.timestamps.ycharts_data, before);
+        assert_eq!((cache.current_cape, cache.cape_period.as_str()), (37.05, "2024-12"));
+    }
+
+    #[tokio::test]
+    async fn one_indicator_is_enough_for_a_fundamentals_refresh() {
+        let store = local_db(&LocalSnapshot::default());
+        let mut cache = market_cache();
+        cache.current_cape = 37.05;
+        cache.cape_period = "2024-12".to_string();
+        let mut report = RefreshReport::default();
+        let fetcher = MockFetcher::new().with_response(YCHARTS_DIVIDEND_URL, &ycharts_page("1.76 for Q1 2025"));
+
+        let updated = refresh_fundamentals_with(&store.db, &fetcher, &mut cache, &mut report).await;
+
+        assert!(updated);
+        assert_eq!(report.updated, vec![RefreshPart::Fundamentals]);
+        assert!(cache.timestamps.ycharts_data > DateTime::UNIX_EPOCH);
+        assert_eq!(cache.quarterly_dividends.get("2025Q1"), Some(&1.76));
+        // CAPE failed, so the cached value is kept
+        assert_eq!((cache.current_cape, cache.cape_period.as_str()), (37.05, "2024-12"));
+    }
+
+    #[test]
+    fn a_refresh_covers_the_forced_parts_it_ran() {
+        let close_and_fundamentals = RefreshScope::of_get_market_data(true);
+        let fundamentals = RefreshScope::Forced(vec![RefreshPart::Fundamentals]);
+        let price = RefreshScope::Forced(vec![RefreshPart::Price]);
+
+        assert!(close_and_fundamentals.covers(&fundamentals));
+        assert!(close_and_fundamentals.covers(&RefreshScope::Due));
+        assert!(!close_and_fundamentals.covers(&price));
+        assert!(!fundamentals.covers(&close_and_fundamentals));
+        assert!(!RefreshScope::Due.covers(&fundamentals));
+    }
+
+    #[tokio::test]
+    async fn forced_refreshes_run_one_at_a_time_and_join_a_covering_one() {
+        let runs = std::sync::atomic::AtomicUsize::new(0);
+        let refresh = |delay_ms: u64| {
+            let runs = &runs;
+            move || async move {
+                runs.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(delay_ms)).await;
+                Err(anyhow::anyhow!("refresh {}", runs.load(Ordering::SeqCst)))
+            }
+        };
+        let after = |delay_ms: u64| tokio::time::sleep(std::time::Duration::from_millis(delay_ms));
+
+        // The scheduled close-and-fundamentals run is in flight when an admin forces fundamentals
+        let (scheduled, admin) = tokio::join!(
+            single_flight(RefreshScope::of_get_market_data(true), refresh(50)),
+            async {
+                after(10).await;
+                single_flight(RefreshScope::Forced(vec![RefreshPart::Fundamentals]), refresh(0)).await
+            },
+        );
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert_eq!(scheduled.unwrap_err().to_string(), admin.unwrap_err().to_string());
+
+        // A price refresh isn't covered, so it waits its turn and then runs
+        let (_, price) = tokio::join!(
+            single_flight(RefreshScope::Forced(vec![RefreshPart::Fundamentals]), refresh(50)),
+            async {
+                after(10).await;
+                single_flight(RefreshScope::Forced(vec![RefreshPart::Price]), refresh(0)).await
+            },
+        );
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        assert_eq!(price.unwrap_err().to_string(), "refresh 3");
+    }
+
+    #[tokio::test]
+    async fn years_before_the_monthly_series_are_backfilled_from_prices() {
+        let snapshot = LocalSnapshot {
+            historical_data: vec![
+                HistoricalRecord { year: 1990, sp500_price: 330.22, ..Default::default() },
+                HistoricalRecord { year: 1991, sp500_price: 417.09, dividend: 12.20, ..Default::default() },
+                HistoricalRecord { year: 1992, sp500_price: 435.71, dividend: 12.38, ..Default::default() },
+                // Already has a precise return, and 1994 has no price
+                HistoricalRecord { year: 1993, sp500_price: 466.45, total_return: 0.0997, ..Default::default() },
+                HistoricalRecord { year: 1994, ..Default::default() },
+            ],
+            ..Default::default()
+        };
+        let store = local_db(&snapshot);
+
+        check_historical_updates(&store.db, &market_cache()).await.unwrap();
+
+        let records = store.db.get_historical_data().await.unwrap();
+        let year = |y: i32| records.iter().find(|r| r.year == y).unwrap();
+        assert_eq!((year(1990).total_return, year(1990).total_return_approximated), (0.0, false));
+        let expected_1991 = 417.09 / 330.22 - 1.0 + 12.20 / 417.09;
+        assert!((year(1991).total_return - expected_1991).abs() < 1e-12, "{}", year(1991).total_return);
+        assert!(year(1991).total_return_approximated);
+        let expected_1992 = 435.71 / 417.09 - 1.0 + 12.38 / 435.71;
+        assert!((year(1992).total_return - expected_1992).abs() < 1e-12, "{}", year(1992).total_return);
+        assert!(year(1992).total_return_approximated);
+        assert_eq!((year(1993).total_return, year(1993).total_return_approximated), (0.0997, false));
+        assert_eq!((year(1994).total_return, year(1994).total_return_approximated), (0.0, false));
+    }
+
+    #[test]
+    fn a_year_with_complete_monthly_data_is_not_backfilled() {
+        let records = vec![
+            HistoricalRecord { year: 2022, sp500_price: 3839.5, ..Default::default() },
+            HistoricalRecord { year: 2023, sp500_price: 4769.83, ..Default::default() },
+        ];
+
+        let backfill = return_backfill(&records, &months_of(2023, 12));
+        assert!(backfill.is_empty());
+
+        let backfill = return_backfill(&records, &months_of(2023, 11));
+        assert_eq!(backfill.len(), 1);
+        assert_eq!((backfill[0].0, backfill[0].1.approximate_return_from), (2023, Some(3839.5)));
+    }
+
+    #[test]
+    fn weekend_fetches_do_not_count_as_market_price_updates() {
+        // Saturday 2024-06-15, 11:00 Central
+        let saturday = Utc.with_ymd_and_hms(2024, 6, 15, 16, 0, 0).unwrap();
+        // Monday 2024-06-17, 10:00 Central
+        let monday = Utc.with_ymd_and_hms(2024, 6, 17, 15, 0, 0).unwrap();
+        assert!(!is_market_hours(saturday));
+        assert!(is_market_hours(monday));
+
+        let mut cache = market_cache();
+        record_current_price(&mut cache, 5400.0, "yahoo", monday);
+        record_current_price(&mut cache, 5410.0, "stooq", saturday);
+
+        assert_eq!(cache.current_sp500_price, 5410.0);
+        assert_eq!(cache.price_source, "stooq");
+        assert_eq!(cache.timestamps.yahoo_price, saturday);
+        assert_eq!(cache.timestamps.last_market_price_update, monday);
+    }
+
+    #[test]
+    fn cape_noise_below_the_rounding_precision_is_not_a_change() {
+        let _env = ENV_LOCK.blocking_lock();
+        let mut cache = market_cache();
+        cache.current_cape = 34.12;
+        cache.cape_period = "Jun 2024".to_string();
+
+        let changed = update_cache_from_ycharts(&mut cache, YChartsData {
+            quarterly_dividends: BTreeMap::new(),
+            eps_actual: BTreeMap::new(),
+            eps_estimated: BTreeMap::new(),
+            cape: (round_cape(34.1199), "Jun 2024".to_string()),
+            monthly_return: None,
+        });
+        assert!(changed.is_empty(), "{:?}", changed);
+
+        let changed = update_cache_from_ycharts(&mut cache, YChartsData {
+            quarterly_dividends: BTreeMap::new(),
+            eps_actual: BTreeMap::new(),
+            eps_estimated: BTreeMap::new(),
+            cape: (round_cape(34.16), "Jun 2024".to_string()),
+            monthly_return: None,
+        });
+        assert_eq!(changed, vec!["current_cape"]);
+    }
+
+    fn dividend_quarter(label: &str, dividend: f64) -> QuarterlyData {
+        QuarterlyData { dividend: Some(dividend), ..quarter(label, None, None) }
+    }
+
+    #[test]
+    fn three_quarters_are_annualized_only_when_allowed() {
+        let data = vec![
+            dividend_quarter("2024Q2", 18.0),
+            dividend_quarter("2024Q3", 19.0),
+            dividend_quarter("2024Q4", 20.0),
+        ];
+
+        let ttm = sum_latest_quarters(&data, |q| q.dividend, 3).unwrap();
+        assert_eq!(ttm.final_quarter, "2024Q4");
+        assert!((ttm.value - 57.0 * 4.0 / 3.0).abs() < 1e-12);
+        assert!(ttm.estimated);
+
+        assert!(sum_latest_quarters(&data, |q| q.dividend, 4).is_none());
+
+        let mut full = vec![dividend_quarter("2024Q1", 17.0)];
+        full.extend(data);
+        let ttm = sum_latest_quarters(&full, |q| q.dividend, 3).unwrap();
+        assert_eq!(ttm.value, 74.0);
+        assert!(!ttm.estimated);
+    }
+
+    #[test]
+    fn ttm_min_quarters_only_accepts_three_or_four() {
+        let _env = ENV_LOCK.blocking_lock();
+        for (raw, expected) in [("3", 3), ("4", 4), ("1", 4), ("2", 4), ("5", 4), ("x", 4)] {
+            std::env::set_var("TTM_MIN_QUARTERS", raw);
+            assert_eq!(ttm_min_quarters(), expected, "TTM_MIN_QUARTERS={}", raw);
+        }
+        std::env::remove_var("TTM_MIN_QUARTERS");
+        assert_eq!(ttm_min_quarters(), 4);
+    }
+
+    #[test]
+    fn price_bounds_and_stooq_symbols_follow_the_tracked_index() {
+        for (symbol, plausible, implausible, stooq) in [
+            ("^GSPC", 5800.0, 42000.0, Some("^spx")),
+            ("^DJI", 42000.0, 5800.0, Some("^dji")),
+            ("^IXIC", 18000.0, 4000.0, Some("^ndq")),
+        ] {
+            assert!(is_plausible_price(symbol, plausible), "{} at {}", symbol, plausible);
+            assert!(!is_plausible_price(symbol, implausible), "{} at {}", symbol, implausible);
+            assert_eq!(stooq_symbol(symbol), stooq);
+        }
+
+        // Unknown symbols take any positive price and have no Stooq fallback
+        assert!(is_plausible_price("^RUT", 2100.0));
+        assert!(!is_plausible_price("^RUT", 0.0));
+        assert_eq!(stooq_symbol("^RUT"), None);
+    }
+
+    #[test]
+    fn chart_price_for_an_alternate_symbol_uses_its_own_bounds() {
+        let body = r#"{"chart":{"result":[{"meta":{"symbol":"^DJI","regularMarketPrice":42863.86}}]}}"#;
+
+        assert_eq!(parse_yahoo_chart_price(body, "^DJI"), Some(42863.86));
+        assert_eq!(parse_yahoo_chart_price(body, "^GSPC"), None);
+    }
+
+    #[test]
+    fn new_quarters_are_added_in_quarter_order_whatever_the_insertion_order() {
+        let mut scraped = BTreeMap::new();
+        for (quarter, value) in [("2024Q3", 1.9), ("2023Q4", 1.8), ("2024Q1", 1.85), ("2024Q2", 1.88)] {
+            scraped.insert(quarter.to_string(), value);
+        }
+
+        let mut rows = Vec::new();
+        assert!(merge_quarterly_values(&mut rows, &scraped, "dividend"));
+
+        let quarters: Vec<&str> = rows.iter().map(|q| q.quarter.as_str()).collect();
+        assert_eq!(quarters, ["2023Q4", "2024Q1", "2024Q2", "2024Q3"]);
+    }
+
+    fn months_of(year: i32, count: u32) -> Vec<MonthlyData> {
+        (1..=count)
+            .map(|m| MonthlyData { month: format!("{}-{:02}", year, m), total_return: 0.01 })
+            .collect()
+    }
+
+    #[test]
+    fn a_complete_december_finalizes_the_current_year() {
+        let mut cache = market_cache();
+        cache.eps_actual.insert("2024Q4".to_string(), 60.0);
+
+        assert_eq!(historical_target_year(&cache, &months_of(2024, 12), 2024), 2024);
+    }
+
+    #[test]
+    fn an_incomplete_current_year_targets_the_previous_year() {
+        // Q4 fundamentals are in but December's return isn't
+        let mut cache = market_cache();
+        cache.quarterly_dividends.insert("2024Q4".to_string(), 19.5);
+        assert_eq!(historical_target_year(&cache, &months_of(2024, 11), 2024), 2023);
+
+        // Twelve months of returns but no Q4 fundamentals yet
+        let cache = market_cache();
+        assert_eq!(historical_target_year(&cache, &months_of(2024, 12), 2024), 2023);
+    }
+
+    #[test]
+    fn stooq_close_is_read_from_a_saved_quote() {
+        let quote = include_str!("../../tests/fixtures/stooq_spx_quote.csv");
+        assert_eq!(parse_stooq_price(quote, SP500_SYMBOL).unwrap(), 5881.63);
+
+        // The same close is implausible for the Dow
+        assert!(parse_stooq_price(quote, "^DJI").is_err());
+    }
+
+    #[test]
+    fn stooq_n_d_quote_is_an_error() {
+        let quote = include_str!("../../tests/fixtures/stooq_nd_quote.csv");
+        let err = parse_stooq_price(quote, SP500_SYMBOL).unwrap_err();
+        assert!(err.to_string().contains("'N/D'"), "{}", err);
+    }
+
+    #[test]
+    fn yahoo_chart_price_is_read_from_a_saved_response() {
+        let body = include_str!("../../tests/fixtures/yahoo_chart_gspc.json");
+        assert_eq!(parse_yahoo_chart_price(body, SP500_SYMBOL), Some(5881.63));
+        assert_eq!(parse_yahoo_chart_price(body, "^DJI"), None);
+        assert_eq!(parse_yahoo_chart_price(r#"{"chart":{"result":null}}"#, SP500_SYMBOL), None);
+    }
+
+    #[test]
+    fn yahoo_quote_page_price_is_read_from_a_saved_page() {
+        let html = include_str!("../../tests/fixtures/yahoo_quote_gspc.html");
+        assert_eq!(parse_yahoo_quote_page(html, SP500_SYMBOL), Some(5881.63));
+
+        // Without a known pattern, the plausible-number sweep is only used for bounded symbols
+        let bare = "<html><body><p>Last 5881.63</p></body></html>";
+        assert_eq!(parse_yahoo_quote_page(bare, SP500_SYMBOL), Some(5881.63));
+        assert_eq!(parse_yahoo_quote_page(bare, "^RUT"), None);
+    }
+
+    #[tokio::test]
+    async fn one_failing_indicator_leaves_the_others_populated() {
+        let _env = ENV_LOCK.lock().await;
+        std::env::remove_var("EPS_SOURCES");
+
+        // No response for the forward EPS page, so that fetch errors
+        let fetcher = MockFetcher::new()
+            .with_response(YCHARTS_DIVIDEND_URL, &ycharts_page("19.51 USD for Q3 2024"))
+            .with_response(YCHARTS_EPS_URL, &ycharts_page("58.43 USD for Q3 2024"))
+            .with_response(YCHARTS_CAPE_URL, &ycharts_page("37.05 for Dec 2024"))
+            .with_response(YCHARTS_MONTHLY_RETURN_URL, &ycharts_page("-2.38% for Dec 2024"));
+
+        let data = fetch_ycharts_data_with(&fetcher).await;
+
+        assert_eq!(data.quarterly_dividends.get("2024Q3"), Some(&19.51));
+        assert_eq!(data.eps_actual.get("2024Q3"), Some(&58.43));
+        assert!(data.eps_estimated.is_empty());
+        assert_eq!(data.cape, (37.05, "2024-12".to_string()));
+        let (month, value) = data.monthly_return.unwrap();
+        assert_eq!(month, "2024-12");
+        assert!((value + 0.0238).abs() < 1e-12);
+    }
+
+    #[test]
+    fn exactly_four_forward_estimates_are_summed() {
+        let data = vec![
+            quarter("2024Q2", Some(54.0), None),
+            quarter("2024Q3", None, Some(60.0)),
+            quarter("2024Q4", None, Some(61.0)),
+            quarter("2025Q1", None, Some(62.0)),
+            quarter("2025Q2", None, Some(63.0)),
+        ];
+
+        let sum = sum_next_estimated_quarters(&data).unwrap();
+
+        assert_eq!(sum.value, 246.0);
+        assert_eq!(sum.final_quarter, "2025Q2");
+    }
+
+    #[test]
+    fn three_forward_estimates_are_not_summed() {
+        let data = vec![
+            quarter("2024Q2", Some(54.0), None),
+            quarter("2024Q3", None, Some(60.0)),
+            quarter("2024Q4", None, Some(61.0)),
+            quarter("2025Q1", None, Some(62.0)),
+        ];
+
+        assert!(sum_next_estimated_quarters(&data).is_none());
+    }
+
+    #[test]
+    fn a_gap_in_the_forward_estimates_stops_the_sum() {
+        let data = vec![
+            quarter("2024Q2", Some(54.0), None),
+            quarter("2024Q3", None, Some(60.0)),
+            quarter("2024Q4", None, None),
+            quarter("2025Q1", None, Some(62.0)),
+            quarter("2025Q2", None, Some(63.0)),
+            quarter("2025Q3", None, Some(64.0)),
+        ];
+
+        assert!(sum_next_estimated_quarters(&data).is_none());
+    }
+
+    fn central(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+        Central.with_ymd_and_hms(y, m, d, h, min, 0).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn market_hours_are_the_nyse_session_in_central_time() {
+        // Wednesday 2024-11-13
+        assert!(!is_market_hours(central(2024, 11, 13, 8, 29)));
+        assert!(is_market_hours(central(2024, 11, 13, 8, 30)));
+        assert!(is_market_hours(central(2024, 11, 13, 14, 59)));
+        assert!(!is_market_hours(central(2024, 11, 13, 15, 0)));
+        // Saturday
+        assert!(!is_market_hours(central(2024, 11, 16, 10, 0)));
+    }
+
+    #[test]
+    fn a_stale_price_is_only_refreshed_while_the_market_is_open() {
+        let interval = Duration::minutes(15);
+
+        let open = central(2024, 11, 13, 10, 0);
+        assert!(price_refresh_due(open - Duration::hours(1), open, interval));
+        assert!(!price_refresh_due(open - Duration::minutes(5), open, interval));
+
+        // Stale, but after the close and on a weekend
+        let after_close = central(2024, 11, 13, 18, 0);
+        assert!(!price_refresh_due(after_close - Duration::hours(3), after_close, interval));
+        let saturday = central(2024, 11, 16, 10, 0);
+        assert!(!price_refresh_due(saturday - Duration::days(1), saturday, interval));
+    }
+
+    #[tokio::test]
+    async fn the_key_stat_is_found_under_the_alternate_class() {
+        let page = include_str!("../../tests/fixtures/ycharts_stat_value_span.html");
+
+        let document = Html::parse_document(page);
+        assert_eq!(
+            find_key_stat(&document),
+            Some(("span.stat-value", "58.43 USD for Q3 2024".to_string()))
+        );
+
+        let fetcher = MockFetcher::new().with_response(YCHARTS_EPS_URL, page);
+        let raw = fetch_ycharts_value_raw_with(&fetcher, YCHARTS_EPS_URL).await.unwrap();
+        assert_eq!((raw.period.as_str(), raw.value), ("2024Q3", 58.43));
+    }
+
+    #[test]
+    fn ycharts_stats_parse_into_period_and_value() {
+        let cases = [
+            ("24.51 USD for Q1 2024", false, "2024Q1", 24.51),
+            ("1.23% for Jan 2024", true, "2024-01", 0.0123),
+            ("-0.45% for Dec 2023", true, "2023-12", -0.0045),
+            // A percentage endpoint that lost its '%' is still a percentage
+            ("-0.45 for Dec 2023", true, "2023-12", -0.0045),
+            // The '%' alone is enough on any endpoint
+            ("1.23% for Jan 2024", false, "2024-01", 0.0123),
+        ];
+        for (stat, is_percentage_endpoint, period, value) in cases {
+            let (parsed_period, parsed_value) = parse_ycharts_stat(stat, is_percentage_endpoint).unwrap();
+            assert_eq!(parsed_period, period, "{}", stat);
+            assert!((parsed_value - value).abs() < 1e-12, "{}: {}", stat, parsed_value);
+        }
+    }
+
+    #[test]
+    fn malformed_ycharts_stats_are_errors_or_unknown_periods() {
+        for stat in ["", "n/a", "USD for Q1 2024"] {
+            assert!(parse_ycharts_stat(stat, false).is_err(), "{:?}", stat);
+        }
+        assert_eq!(parse_ycharts_stat("37.05 as of today", false).unwrap(), ("Unknown".to_string(), 37.05));
+    }
+
+    #[test]
+    fn daily_update_runs_once_after_the_target_time() {
+        let target = NaiveTime::from_hms_opt(15, 30, 0).unwrap();
+        let now = central(2024, 11, 13, 16, 0);
+
+        // Already updated today
+        assert!(!should_update_daily(now, target, central(2024, 11, 13, 15, 31)));
+        // Past the target and last updated yesterday
+        assert!(should_update_daily(now, target, central(2024, 11, 12, 15, 31)));
+        // Before the target, even though today's update hasn't run
+        assert!(!should_update_daily(central(2024, 11, 13, 15, 29), target, central(2024, 11, 12, 15, 31)));
+    }
+}
@@ -0,0 +1,110 @@
+// src/services/error.rs
+use std::error::Error as StdError;
+use std::fmt;
+use crate::services::equity::ScrapeError;
+use crate::services::google_oauth::TokenError;
+use crate::services::treasury_common::TreasuryCsvError;
+
+/// What went wrong in a service call, so handlers can pick a status code from the kind of
+/// failure instead of from its message
+#[derive(Debug)]
+pub enum ServiceError {
+    /// The request never got a usable response: connection failure, timeout or error status
+    Http(String),
+    /// A response arrived but its contents couldn't be parsed
+    Parse(String),
+    /// The Sheets API reported a failure, or a sheet is missing rows the store can't do without
+    Sheets(String),
+    /// No Google access token could be obtained
+    Auth(TokenError),
+    /// The requested row or record doesn't exist
+    NotFound(String),
+    /// A scraped page didn't have what we were looking for
+    Scrape(ScrapeError),
+}
+
+impl ServiceError {
+    /// Keep the `TokenError` a token fetch failed with; anything else is treated as transient
+    pub fn auth(e: anyhow::Error) -> Self {
+        match e.downcast::<TokenError>() {
+            Ok(token_error) => ServiceError::Auth(token_error),
+            Err(e) => ServiceError::Auth(TokenError::Transient(e.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ServiceError::Http(msg) => write!(f, "HTTP request failed: {}", msg),
+            ServiceError::Parse(msg) => write!(f, "Failed to parse response: {}", msg),
+            ServiceError::Sheets(msg) => write!(f, "Sheets API error: {}", msg),
+            ServiceError::Auth(e) => write!(f, "{}", e),
+            ServiceError::NotFound(msg) => write!(f, "{}", msg),
+            ServiceError::Scrape(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl StdError for ServiceError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            ServiceError::Auth(e) => Some(e),
+            ServiceError::Scrape(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ServiceError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_decode() {
+            ServiceError::Parse(e.to_string())
+        } else {
+            ServiceError::Http(e.to_string())
+        }
+    }
+}
+
+impl From<serde_json::Error> for ServiceError {
+    fn from(e: serde_json::Error) -> Self {
+        ServiceError::Parse(e.to_string())
+    }
+}
+
+impl From<std::num::ParseFloatError> for ServiceError {
+    fn from(e: std::num::ParseFloatError) -> Self {
+        ServiceError::Parse(e.to_string())
+    }
+}
+
+impl From<std::num::ParseIntError> for ServiceError {
+    fn from(e: std::num::ParseIntError) -> Self {
+        ServiceError::Parse(e.to_string())
+    }
+}
+
+impl From<TokenError> for ServiceError {
+    fn from(e: TokenError) -> Self {
+        ServiceError::Auth(e)
+    }
+}
+
+impl From<ScrapeError> for ServiceError {
+    fn from(e: ScrapeError) -> Self {
+        ServiceError::Scrape(e)
+    }
+}
+
+impl From<TreasuryCsvError> for ServiceError {
+    fn from(e: TreasuryCsvError) -> Self {
+        ServiceError::Parse(e.to_string())
+    }
+}
+
+/// The FRED and BLS clients box their errors; all of them are upstream failures
+impl From<Box<dyn StdError + Send + Sync>> for ServiceError {
+    fn from(e: Box<dyn StdError + Send + Sync>) -> Self {
+        ServiceError::Http(e.to_string())
+    }
+}
@@ -0,0 +1,108 @@
+// src/services/export.rs
+use csv::WriterBuilder;
+use anyhow::Result;
+use crate::models::HistoricalRecord;
+use crate::services::sheets::HISTORICAL_COLUMNS;
+
+/// Number formatting for CSV exports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvFormat {
+    /// `.` decimal separator, `,` field delimiter
+    Us,
+    /// `,` decimal separator, `;` field delimiter (Excel in most European locales)
+    Eu,
+}
+
+impl CsvFormat {
+    /// Parse the `decimal` query value (`point` or `comma`)
+    pub fn from_decimal_param(decimal: Option<&str>) -> Option<Self> {
+        match decimal.map(|d| d.trim().to_lowercase()).as_deref() {
+            None | Some("") | Some("point") | Some("dot") => Some(CsvFormat::Us),
+            Some("comma") => Some(CsvFormat::Eu),
+            _ => None,
+        }
+    }
+
+    fn delimiter(self) -> u8 {
+        match self {
+            CsvFormat::Us => b',',
+            CsvFormat::Eu => b';',
+        }
+    }
+
+    /// Zero means "no data" in the store, so it's written as an empty cell like the sheet does
+    fn format_number(self, value: f64) -> String {
+        if value == 0.0 {
+            return String::new();
+        }
+        match self {
+            CsvFormat::Us => value.to_string(),
+            CsvFormat::Eu => value.to_string().replace('.', ","),
+        }
+    }
+}
+
+/// Serialize historical records to CSV with a header row matching the HistoricalData sheet
+pub fn historical_records_to_csv(records: &[HistoricalRecord], format: CsvFormat) -> Result<String> {
+    let mut writer = WriterBuilder::new()
+        .delimiter(format.delimiter())
+        .from_writer(Vec::new());
+
+    writer.write_record(HISTORICAL_COLUMNS)?;
+    for record in records {
+        writer.write_record([
+            record.year.to_string(),
+            format.format_number(record.sp500_price),
+            format.format_number(record.dividend),
+            format.format_number(record.dividend_yield),
+            format.format_number(record.eps),
+            format.format_number(record.cape),
+            format.format_number(record.inflation),
+            format.format_number(record.total_return),
+            format.format_number(record.cumulative_return),
+            if record.total_return_approximated { "TRUE".to_string() } else { String::new() },
+        ])?;
+    }
+
+    let bytes = writer.into_inner()?;
+    Ok(String::from_utf8(bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<HistoricalRecord> {
+        vec![HistoricalRecord {
+            year: 2024,
+            sp500_price: 5881.63,
+            dividend_yield: 0.0125,
+            ..Default::default()
+        }]
+    }
+
+    #[test]
+    fn us_format_uses_point_decimals_and_comma_delimiters() {
+        let csv = historical_records_to_csv(&sample(), CsvFormat::Us).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines[0], HISTORICAL_COLUMNS.join(","));
+        assert_eq!(lines[1], "2024,5881.63,,0.0125,,,,,,");
+    }
+
+    #[test]
+    fn eu_format_uses_comma_decimals_and_semicolon_delimiters() {
+        let csv = historical_records_to_csv(&sample(), CsvFormat::Eu).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines[0], HISTORICAL_COLUMNS.join(";"));
+        assert_eq!(lines[1], "2024;5881,63;;0,0125;;;;;;");
+    }
+
+    #[test]
+    fn decimal_param_selects_the_format() {
+        assert_eq!(CsvFormat::from_decimal_param(None), Some(CsvFormat::Us));
+        assert_eq!(CsvFormat::from_decimal_param(Some("comma")), Some(CsvFormat::Eu));
+        assert_eq!(CsvFormat::from_decimal_param(Some("semicolon")), None);
+    }
+}
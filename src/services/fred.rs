@@ -0,0 +1,101 @@
+// src/services/fred.rs
+use log::{info, warn, error};
+use reqwest::Client;
+use serde::Deserialize;
+use std::env;
+use std::error::Error as StdError;
+use std::time::Duration;
+
+pub type Result<T> = std::result::Result<T, Box<dyn StdError + Send + Sync>>;
+
+/// FRED series used as fallbacks for the Treasury CSV yields
+pub const FRED_TBILL_4WK: &str = "DTB4WK";
+pub const FRED_BOND_20Y: &str = "DGS20";
+pub const FRED_TIPS_20Y: &str = "DFII20";
+
+#[derive(Deserialize, Debug)]
+struct FredResponse {
+    observations: Vec<FredObservation>,
+}
+
+#[derive(Deserialize, Debug)]
+struct FredObservation {
+    date: String,
+    value: String,
+}
+
+/// Pick the most recent published value out of a FRED observations response.
+/// FRED marks missing observations (e.g. market holidays) with ".".
+pub fn parse_fred_latest(json: &str) -> Result<(String, f64)> {
+    let resp: FredResponse = serde_json::from_str(json)?;
+
+    let latest = resp.observations.iter()
+        .filter(|obs| obs.value.trim() != ".")
+        .max_by(|a, b| a.date.cmp(&b.date))
+        .ok_or("No published observations in FRED response")?;
+
+    let value = latest.value.trim().parse::<f64>()
+        .map_err(|e| format!("Failed to parse FRED value '{}' for {}: {}", latest.value, latest.date, e))?;
+
+    Ok((latest.date.clone(), value))
+}
+
+/// Fetch the latest value of a FRED series. Requires `FRED_API_KEY`.
+pub async fn fetch_fred_latest(series_id: &str) -> Result<f64> {
+    fetch_fred_latest_observation(series_id).await.map(|(_, value)| value)
+}
+
+/// Fetch the latest observation of a FRED series as `(date, value)`. Requires `FRED_API_KEY`.
+pub async fn fetch_fred_latest_observation(series_id: &str) -> Result<(String, f64)> {
+    let api_key = env::var("FRED_API_KEY")
+        .map_err(|_| "FRED_API_KEY is not set, FRED data unavailable")?;
+
+    let client = Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()?;
+
+    info!("Fetching FRED series {}", series_id);
+
+    let response = client
+        .get("https://api.stlouisfed.org/fred/series/observations")
+        .query(&[
+            ("series_id", series_id),
+            ("api_key", api_key.as_str()),
+            ("file_type", "json"),
+            ("sort_order", "desc"),
+            ("limit", "10"),
+        ])
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        let err_msg = format!("FRED request for {} failed with status: {}", series_id, response.status());
+        error!("{}", err_msg);
+        return Err(err_msg.into());
+    }
+
+    let body = response.text().await?;
+    let (date, value) = parse_fred_latest(&body)?;
+    info!("Found FRED {} for {}: {}", series_id, date, value);
+    Ok((date, value))
+}
+
+/// Return the primary (Treasury) result, or fall back to the given FRED series if it failed
+pub async fn with_fred_fallback<E: std::fmt::Display>(
+    primary: std::result::Result<f64, E>,
+    series_id: &str,
+    service_context: &str,
+) -> Result<f64> {
+    match primary {
+        Ok(rate) => Ok(rate),
+        Err(primary_err) => {
+            warn!("Treasury fetch for {} failed ({}), falling back to FRED {}", service_context, primary_err, series_id);
+            fetch_fred_latest(series_id).await.map_err(|fred_err| {
+                format!(
+                    "{} unavailable. Treasury: {}; FRED fallback: {}",
+                    service_context, primary_err, fred_err
+                ).into()
+            })
+        }
+    }
+}
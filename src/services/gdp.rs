@@ -0,0 +1,114 @@
+// src/services/gdp.rs
+use std::sync::Arc;
+use chrono::{DateTime, Duration, Utc};
+use log::{error, info};
+use serde::Serialize;
+use anyhow::Result;
+use crate::services::db::DbStore;
+use crate::services::fred::fetch_fred_latest_observation;
+
+/// FRED series for US nominal GDP, billions of dollars, seasonally adjusted annual rate
+pub const FRED_GDP: &str = "GDP";
+
+/// Default market cap per index point, in billions of dollars (roughly the S&P 500 divisor)
+const DEFAULT_MARKET_CAP_PER_INDEX_POINT: f64 = 8.6;
+
+/// GDP is published quarterly, so a daily check is plenty
+pub fn gdp_max_age() -> Duration {
+    Duration::days(1)
+}
+
+/// Billions of dollars of market cap per index point, from `MARKET_CAP_PER_INDEX_POINT`.
+/// Multiplying the index level by this gives the market-cap proxy used for the ratio.
+pub fn market_cap_per_index_point() -> f64 {
+    std::env::var("MARKET_CAP_PER_INDEX_POINT")
+        .ok()
+        .and_then(|v| v.trim().parse::<f64>().ok())
+        .filter(|v| *v > 0.0)
+        .unwrap_or(DEFAULT_MARKET_CAP_PER_INDEX_POINT)
+}
+
+/// Market cap proxy over GDP ("Buffett indicator") and the inputs it was built from
+#[derive(Debug, Serialize)]
+pub struct MarketCapToGdp {
+    /// Index level times `MARKET_CAP_PER_INDEX_POINT`, in billions of dollars
+    pub market_cap_proxy: Option<f64>,
+    /// Latest GDP, in billions of dollars (SAAR)
+    pub gdp: Option<f64>,
+    pub gdp_period: Option<String>,
+    /// `market_cap_proxy / gdp`, or `None` if either input is missing
+    pub ratio: Option<f64>,
+    pub gdp_as_of: DateTime<Utc>,
+}
+
+/// Market cap proxy over GDP; `None` when the price or GDP hasn't been fetched
+pub fn calculate_market_cap_to_gdp(index_price: f64, cap_per_point: f64, gdp: f64) -> Option<f64> {
+    if index_price > 0.0 && gdp > 0.0 {
+        Some(index_price * cap_per_point / gdp)
+    } else {
+        None
+    }
+}
+
+/// Latest GDP observation from FRED as `(date, billions of dollars)`
+pub async fn fetch_gdp() -> Result<(String, f64)> {
+    fetch_fred_latest_observation(FRED_GDP).await.map_err(|e| anyhow::anyhow!(e))
+}
+
+/// Combine the cached index price with GDP, refreshing GDP into the cache when it's
+/// more than a day old and handlers are allowed to write
+pub async fn get_market_cap_to_gdp(db: &Arc<DbStore>) -> Result<MarketCapToGdp> {
+    let mut cache = db.get_market_cache().await?;
+
+    if db.handlers_may_write() && cache.timestamps.gdp_data < Utc::now() - gdp_max_age() {
+        info!("GDP cache expired, fetching from FRED");
+        match fetch_gdp().await {
+            Ok((period, gdp)) => {
+                cache.gdp = gdp;
+                cache.gdp_period = period;
+                cache.timestamps.gdp_data = Utc::now();
+                if let Err(e) = db.update_market_cache(&cache).await {
+                    error!("Failed to update cache with new GDP data: {}", e);
+                }
+            }
+            // Keep serving the previous value; the ratio is null if there never was one
+            Err(e) => error!("Failed to fetch GDP: {}", e),
+        }
+    }
+
+    let cap_per_point = market_cap_per_index_point();
+    let price = cache.current_sp500_price;
+    Ok(MarketCapToGdp {
+        market_cap_proxy: (price > 0.0).then_some(price * cap_per_point),
+        gdp: (cache.gdp > 0.0).then_some(cache.gdp),
+        gdp_period: (!cache.gdp_period.is_empty()).then(|| cache.gdp_period.clone()),
+        ratio: calculate_market_cap_to_gdp(price, cap_per_point, cache.gdp),
+        gdp_as_of: cache.timestamps.gdp_data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::fred::parse_fred_latest;
+
+    const FRED_GDP_RESPONSE: &str = include_str!("../../tests/fixtures/fred_gdp_response.json");
+
+    #[test]
+    fn latest_published_gdp_is_read_from_a_saved_fred_response() {
+        // The newest quarter is still "." (not yet published), so the one before it wins
+        let (period, gdp) = parse_fred_latest(FRED_GDP_RESPONSE).unwrap();
+
+        assert_eq!(period, "2024-07-01");
+        assert_eq!(gdp, 29374.914);
+    }
+
+    #[test]
+    fn ratio_needs_both_a_price_and_gdp() {
+        let ratio = calculate_market_cap_to_gdp(5800.0, 8.6, 29374.914).unwrap();
+        assert!((ratio - 5800.0 * 8.6 / 29374.914).abs() < 1e-12);
+
+        assert_eq!(calculate_market_cap_to_gdp(0.0, 8.6, 29374.914), None);
+        assert_eq!(calculate_market_cap_to_gdp(5800.0, 8.6, 0.0), None);
+    }
+}
@@ -1,9 +1,41 @@
 // src/services/google_oauth.rs
-use chrono::{Utc, Duration};
+use chrono::{DateTime, Utc, Duration};
 use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
 use serde::{Deserialize, Serialize};
 use reqwest::Client;
 use anyhow::Result;
+use std::fmt;
+use crate::services::http::RetryPolicy;
+
+/// Why a token couldn't be obtained, so callers can tell a broken setup from a blip
+#[derive(Debug)]
+pub enum TokenError {
+    /// Unreadable service account file or key, or the token endpoint rejected us (4xx);
+    /// retrying won't help
+    Misconfigured(String),
+    /// Network failure or a 5xx from the token endpoint, still failing after the retries
+    Transient(String),
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenError::Misconfigured(msg) => write!(f, "Google auth misconfigured: {}", msg),
+            TokenError::Transient(msg) => write!(f, "Google token endpoint unavailable: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+/// Retries of the token exchange after a transient failure, from `OAUTH_TOKEN_RETRIES` (default 3)
+fn token_retry_policy() -> RetryPolicy {
+    let retries = std::env::var("OAUTH_TOKEN_RETRIES")
+        .ok()
+        .and_then(|v| v.trim().parse::<u32>().ok())
+        .unwrap_or(RetryPolicy::default().retries);
+    RetryPolicy { retries, ..RetryPolicy::default() }
+}
 
 /// This matches the format of your JSON service account file
 #[derive(Debug, Serialize, Deserialize)]
@@ -31,13 +63,23 @@ struct Claims {
     iat: i64,
 }
 
+/// A Bearer token and when Google says it stops being valid
+#[derive(Debug, Clone)]
+pub struct AccessToken {
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
 /// Load the service account JSON from a file and request a Bearer token
 pub async fn fetch_access_token_from_file(
     service_account_json_path: &str,
-) -> Result<String> {
+) -> Result<AccessToken> {
+    let misconfigured = |e: &dyn fmt::Display| TokenError::Misconfigured(e.to_string());
+
     // 1. Read the JSON file
-    let json_bytes = std::fs::read(service_account_json_path)?;
-    let key: ServiceAccountKey = serde_json::from_slice(&json_bytes)?;
+    let json_bytes = std::fs::read(service_account_json_path)
+        .map_err(|e| misconfigured(&format!("{}: {}", service_account_json_path, e)))?;
+    let key: ServiceAccountKey = serde_json::from_slice(&json_bytes).map_err(|e| misconfigured(&e))?;
 
     // 2. Build JWT claims
     let iat = Utc::now();
@@ -50,9 +92,9 @@ pub async fn fetch_access_token_from_file(
         iat: iat.timestamp(),
     };
 
-    let encoding_key = EncodingKey::from_rsa_pem(key.private_key.as_bytes())?;
+    let encoding_key = EncodingKey::from_rsa_pem(key.private_key.as_bytes()).map_err(|e| misconfigured(&e))?;
 
-    let jwt = encode(&Header::new(Algorithm::RS256), &claims, &encoding_key)?;
+    let jwt = encode(&Header::new(Algorithm::RS256), &claims, &encoding_key).map_err(|e| misconfigured(&e))?;
 
     // 4. Exchange the signed JWT for an access token
     #[derive(Debug, Serialize)]
@@ -73,16 +115,73 @@ pub async fn fetch_access_token_from_file(
         expires_in: i64,
     }
 
+    // 4xx means the credentials or request are wrong; anything else may clear up on a retry
     let client = Client::new();
-    let resp = client
-        .post(&key.token_uri)
-        .json(&req_body)
-        .send()
-        .await?
-        .error_for_status()?
-        .json::<TokenResponse>()
+    let exchange = || async {
+        let resp = client
+            .post(&key.token_uri)
+            .json(&req_body)
+            .send()
+            .await
+            .map_err(|e| TokenError::Transient(e.to_string()))?;
+        let status = resp.status();
+        if status.is_client_error() {
+            let body = resp.text().await.unwrap_or_default();
+            return Err(TokenError::Misconfigured(format!("token endpoint returned {}: {}", status, body)).into());
+        }
+        if !status.is_success() {
+            return Err(TokenError::Transient(format!("token endpoint returned {}", status)).into());
+        }
+        let token = resp.json::<TokenResponse>()
+            .await
+            .map_err(|e| TokenError::Transient(e.to_string()))?;
+        Ok(token)
+    };
+    let resp = token_retry_policy()
+        .run_while("Google token exchange", exchange, |e| {
+            matches!(e.downcast_ref::<TokenError>(), Some(TokenError::Transient(_)))
+        })
         .await?;
 
-    // 5. Return the actual "access_token"
-    Ok(resp.access_token)
+    // 5. Return the actual "access_token", timed from before the exchange so we never overestimate
+    Ok(AccessToken {
+        token: resp.access_token,
+        expires_at: iat + Duration::seconds(resp.expires_in),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering;
+    use crate::services::test_support::{mock_server, service_account_file, token_response, ENV_LOCK};
+
+    #[tokio::test]
+    async fn a_5xx_from_the_token_endpoint_is_retried() {
+        let _env = ENV_LOCK.lock().await;
+        std::env::remove_var("OAUTH_TOKEN_RETRIES");
+        let (url, hits) = mock_server(|hit, _| {
+            if hit == 0 { (500, "backend error".to_string()) } else { (200, token_response("ya29.test")) }
+        });
+        let key = service_account_file(&format!("{}/token", url));
+
+        let token = fetch_access_token_from_file(key.path.to_str().unwrap()).await.unwrap();
+
+        assert_eq!(token.token, "ya29.test");
+        assert!(token.expires_at > Utc::now() + Duration::minutes(59));
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn a_4xx_from_the_token_endpoint_is_not_retried() {
+        let _env = ENV_LOCK.lock().await;
+        std::env::remove_var("OAUTH_TOKEN_RETRIES");
+        let (url, hits) = mock_server(|_, _| (400, r#"{"error":"invalid_grant"}"#.to_string()));
+        let key = service_account_file(&format!("{}/token", url));
+
+        let err = fetch_access_token_from_file(key.path.to_str().unwrap()).await.unwrap_err();
+
+        assert!(matches!(err.downcast_ref::<TokenError>(), Some(TokenError::Misconfigured(_))), "{}", err);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
 }
@@ -0,0 +1,194 @@
+// src/services/http.rs
+// The seam between the scrapers and the network, so page parsing can run against saved fixtures,
+// and the retry policy shared by upstream fetches
+use anyhow::Result;
+use log::warn;
+use reqwest::{Client, StatusCode};
+use std::collections::HashMap;
+use std::future::Future;
+
+/// Fetches a page body as text
+pub trait HttpFetcher: Send + Sync {
+    /// GET `url` and return the status and body, whatever the status. For callers that need to
+    /// look at an error page, e.g. a challenge page served with a 403.
+    fn get(&self, url: &str) -> impl Future<Output = Result<(StatusCode, String)>> + Send;
+
+    /// GET `url` and return the body; a non-2xx status is an error
+    fn get_text(&self, url: &str) -> impl Future<Output = Result<String>> + Send {
+        async move {
+            let (status, body) = self.get(url).await?;
+            if !status.is_success() {
+                return Err(anyhow::anyhow!("HTTP status {} for url ({})", status, url));
+            }
+            Ok(body)
+        }
+    }
+}
+
+/// The live fetcher, backed by a reqwest client (carrying whatever default headers it was built with)
+#[derive(Clone, Default)]
+pub struct ReqwestFetcher {
+    client: Client,
+}
+
+impl ReqwestFetcher {
+    pub fn new(client: Client) -> Self {
+        ReqwestFetcher { client }
+    }
+}
+
+impl HttpFetcher for ReqwestFetcher {
+    async fn get(&self, url: &str) -> Result<(StatusCode, String)> {
+        let response = self.client.get(url).send().await?;
+        let status = response.status();
+        Ok((status, response.text().await?))
+    }
+}
+
+/// Serves canned bodies by URL instead of going to the network; unknown URLs are an error
+#[derive(Clone, Default)]
+pub struct MockFetcher {
+    responses: HashMap<String, (StatusCode, String)>,
+}
+
+impl MockFetcher {
+    pub fn new() -> Self {
+        MockFetcher::default()
+    }
+
+    /// Answer requests for `url` with `body` and a 200
+    pub fn with_response(self, url: &str, body: &str) -> Self {
+        self.with_status(url, StatusCode::OK, body)
+    }
+
+    /// Answer requests for `url` with `body` and `status`
+    pub fn with_status(mut self, url: &str, status: StatusCode, body: &str) -> Self {
+        self.responses.insert(url.to_string(), (status, body.to_string()));
+        self
+    }
+}
+
+impl HttpFetcher for MockFetcher {
+    async fn get(&self, url: &str) -> Result<(StatusCode, String)> {
+        self.responses
+            .get(url)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("MockFetcher has no response for {}", url))
+    }
+}
+
+/// How often, and how patiently, a flaky upstream fetch is retried
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt
+    pub retries: u32,
+    /// Delay before the first retry, doubled before each one after it
+    pub base_delay: std::time::Duration,
+}
+
+impl Default for RetryPolicy {
+    /// Three retries after 250 ms, 500 ms and 1 s
+    fn default() -> Self {
+        RetryPolicy { retries: 3, base_delay: std::time::Duration::from_millis(250) }
+    }
+}
+
+impl RetryPolicy {
+    fn delay_before_retry(&self, retry: u32) -> std::time::Duration {
+        self.base_delay.saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+    }
+
+    /// Run `fetch` until it succeeds or the retries run out, returning the last error
+    pub async fn run<T, F, Fut>(&self, what: &str, fetch: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<T>>,
+    {
+        self.run_while(what, fetch, |_| true).await
+    }
+
+    /// Like `run`, but an error for which `retryable` is false is returned immediately
+    pub async fn run_while<T, F, Fut>(&self, what: &str, mut fetch: F, retryable: impl Fn(&anyhow::Error) -> bool) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<T>>,
+    {
+        let mut retry = 0;
+        loop {
+            match fetch().await {
+                Ok(value) => return Ok(value),
+                Err(e) if retry < self.retries && retryable(&e) => {
+                    let attempt = retry + 1;
+                    retry += 1;
+                    let delay = self.delay_before_retry(retry);
+                    warn!(
+                        "{} failed on attempt {} of {} ({}), retry {} in {} ms",
+                        what, attempt, self.retries + 1, e, retry, delay.as_millis()
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::test_support::mock_server;
+
+    #[tokio::test]
+    async fn get_text_rejects_an_error_status_that_get_returns() {
+        let fetcher = MockFetcher::new()
+            .with_response("https://example.com/ok", "fine")
+            .with_status("https://example.com/busy", StatusCode::SERVICE_UNAVAILABLE, "busy");
+
+        assert_eq!(fetcher.get_text("https://example.com/ok").await.unwrap(), "fine");
+        assert_eq!(
+            fetcher.get("https://example.com/busy").await.unwrap(),
+            (StatusCode::SERVICE_UNAVAILABLE, "busy".to_string())
+        );
+        let err = fetcher.get_text("https://example.com/busy").await.unwrap_err();
+        assert!(err.to_string().contains("503"), "{}", err);
+        assert!(fetcher.get_text("https://example.com/missing").await.is_err());
+    }
+
+    async fn get_text(client: &Client, url: &str) -> Result<String> {
+        Ok(client.get(url).send().await?.error_for_status()?.text().await?)
+    }
+
+    #[tokio::test]
+    async fn a_fetch_that_fails_twice_then_succeeds_is_retried() {
+        let (url, hits) = mock_server(|hit, _| {
+            if hit < 2 { (500, "busy".to_string()) } else { (200, "5881.63".to_string()) }
+        });
+        let client = Client::new();
+        let policy = RetryPolicy { retries: 3, base_delay: std::time::Duration::from_millis(1) };
+
+        let body = policy.run("mock fetch", || get_text(&client, &url)).await.unwrap();
+
+        assert_eq!(body, "5881.63");
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn zero_delay_retries_run_back_to_back_and_return_the_last_error() {
+        let (url, hits) = mock_server(|hit, _| (503, format!("down {}", hit)));
+        let client = Client::new();
+        let policy = RetryPolicy { retries: 2, base_delay: std::time::Duration::ZERO };
+        assert!((1..=2).all(|retry| policy.delay_before_retry(retry).is_zero()));
+
+        let err = policy.run("mock fetch", || get_text(&client, &url)).await.unwrap_err();
+
+        assert!(err.to_string().contains("503"), "{}", err);
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn retry_delays_double_from_the_base_delay() {
+        let policy = RetryPolicy::default();
+        let delays: Vec<u128> = (1..=3).map(|retry| policy.delay_before_retry(retry).as_millis()).collect();
+        assert_eq!(delays, vec![250, 500, 1000]);
+    }
+}
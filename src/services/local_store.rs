@@ -0,0 +1,140 @@
+// src/services/local_store.rs
+
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use anyhow::Result;
+use crate::models::{HistoricalRecord, MonthlyData, QuarterlyData};
+use crate::services::sheets::RawMarketCache;
+
+/// Everything the Sheets backend stores, kept in a single JSON file for local development
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LocalSnapshot {
+    #[serde(default)]
+    pub market_cache: Option<RawMarketCache>,
+    #[serde(default)]
+    pub quarterly_data: Vec<QuarterlyData>,
+    #[serde(default)]
+    pub monthly_data: Vec<MonthlyData>,
+    #[serde(default)]
+    pub historical_data: Vec<HistoricalRecord>,
+}
+
+/// JSON-file-backed stand-in for `SheetsStore`, so the API can run without Google credentials.
+/// Method names and row semantics mirror `SheetsStore`.
+pub struct LocalStore {
+    path: PathBuf,
+    // Serializes load-modify-save cycles within this process
+    lock: Mutex<()>,
+}
+
+impl LocalStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        LocalStore {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub async fn load(&self) -> Result<LocalSnapshot> {
+        let text = tokio::fs::read_to_string(&self.path).await
+            .map_err(|e| anyhow::anyhow!("Failed to read local store {}: {}", self.path.display(), e))?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    /// Write the snapshot to a temp file and rename it over the store so readers never see a partial file
+    pub async fn save(&self, snapshot: &LocalSnapshot) -> Result<()> {
+        let tmp_path = self.path.with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, serde_json::to_vec_pretty(snapshot)?).await?;
+        tokio::fs::rename(&tmp_path, &self.path).await?;
+        Ok(())
+    }
+
+    async fn modify<F>(&self, apply: F) -> Result<()>
+    where
+        F: FnOnce(&mut LocalSnapshot) -> Result<()>,
+    {
+        let _guard = self.lock.lock().await;
+        let mut snapshot = self.load().await?;
+        apply(&mut snapshot)?;
+        self.save(&snapshot).await
+    }
+
+    pub async fn get_market_cache(&self) -> Result<RawMarketCache> {
+        self.load().await?
+            .market_cache
+            .ok_or_else(|| anyhow::anyhow!("No market cache data found"))
+    }
+
+    pub async fn update_market_cache(&self, cache: &RawMarketCache) -> Result<()> {
+        self.modify(|snapshot| {
+            snapshot.market_cache = Some(cache.clone());
+            Ok(())
+        }).await
+    }
+
+    pub async fn get_quarterly_data(&self) -> Result<Vec<QuarterlyData>> {
+        Ok(self.load().await?.quarterly_data)
+    }
+
+    pub async fn update_quarterly_data(&self, data: &[QuarterlyData]) -> Result<()> {
+        self.modify(|snapshot| {
+            snapshot.quarterly_data = data.to_vec();
+            Ok(())
+        }).await
+    }
+
+    pub async fn get_monthly_data(&self) -> Result<Vec<MonthlyData>> {
+        Ok(self.load().await?.monthly_data)
+    }
+
+    pub async fn update_monthly_data(&self, data: &[MonthlyData]) -> Result<()> {
+        self.modify(|snapshot| {
+            snapshot.monthly_data = data.to_vec();
+            Ok(())
+        }).await
+    }
+
+    pub async fn get_historical_data(&self) -> Result<Vec<HistoricalRecord>> {
+        Ok(self.load().await?.historical_data)
+    }
+
+    pub async fn update_historical_record(&self, record: &HistoricalRecord) -> Result<()> {
+        self.update_historical_records(std::slice::from_ref(record)).await
+    }
+
+    /// Overwrite the existing records for several years in one save; nothing is saved if
+    /// any year has no record
+    pub async fn update_historical_records(&self, records: &[HistoricalRecord]) -> Result<()> {
+        self.modify(|snapshot| {
+            for record in records {
+                let existing = snapshot.historical_data.iter_mut()
+                    .rfind(|r| r.year == record.year)
+                    .ok_or(anyhow::anyhow!("Record not found"))?;
+                *existing = record.clone();
+            }
+            Ok(())
+        }).await
+    }
+
+    /// Overwrite historical records by their 0-based position in `get_historical_data`, in one
+    /// save. Records are applied in order of position, and the position one past the end appends.
+    pub async fn write_historical_rows(&self, rows: &[(usize, HistoricalRecord)]) -> Result<()> {
+        let mut rows = rows.to_vec();
+        rows.sort_by_key(|(index, _)| *index);
+
+        self.modify(|snapshot| {
+            let data = &mut snapshot.historical_data;
+            for (index, record) in rows {
+                if index < data.len() {
+                    data[index] = record;
+                } else if index == data.len() {
+                    data.push(record);
+                } else {
+                    return Err(anyhow::anyhow!("Historical record {} is past the end of the local store", index));
+                }
+            }
+            Ok(())
+        }).await
+    }
+}
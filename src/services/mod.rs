@@ -1,8 +1,20 @@
 pub mod bls;
 pub mod treasury;
 pub mod treasury_long;
+pub mod treasury_common;
+pub mod fred;
 pub mod equity;
+pub mod error;
 pub mod sheets;
 pub mod db;
 pub mod google_oauth;
-pub mod calculations;
\ No newline at end of file
+pub mod http;
+pub mod calculations;
+pub mod export;
+pub mod rates;
+pub mod local_store;
+pub mod signals;
+pub mod price_stream;
+pub mod gdp;
+#[cfg(test)]
+pub(crate) mod test_support;
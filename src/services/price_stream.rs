@@ -0,0 +1,124 @@
+// src/services/price_stream.rs
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use futures_util::stream::{self, Stream};
+use log::{info, warn};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{interval_at, Instant, Interval};
+use anyhow::Result;
+
+use crate::services::db::DbStore;
+use crate::services::equity::{subscribe_price_updates, PriceUpdate};
+
+/// Default cap on concurrent `/equity/stream` connections
+const DEFAULT_MAX_SUBSCRIBERS: usize = 100;
+
+static ACTIVE_SUBSCRIBERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Max concurrent stream subscribers, from `SSE_MAX_SUBSCRIBERS` (at least 1)
+fn max_subscribers() -> usize {
+    std::env::var("SSE_MAX_SUBSCRIBERS")
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_MAX_SUBSCRIBERS)
+}
+
+/// Optional fallback interval from `PRICE_STREAM_INTERVAL_SECS`; when set, subscribers
+/// also get the cached price on that cadence even if no refresh happened in between
+fn poll_interval() -> Option<Duration> {
+    std::env::var("PRICE_STREAM_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs)
+}
+
+/// Holds one subscriber slot and releases it when the connection's stream is dropped
+struct SubscriberSlot;
+
+impl SubscriberSlot {
+    fn acquire() -> Option<Self> {
+        let limit = max_subscribers();
+        ACTIVE_SUBSCRIBERS
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| (n < limit).then_some(n + 1))
+            .ok()
+            .map(|_| SubscriberSlot)
+    }
+}
+
+impl Drop for SubscriberSlot {
+    fn drop(&mut self) {
+        let remaining = ACTIVE_SUBSCRIBERS.fetch_sub(1, Ordering::SeqCst) - 1;
+        info!("Price stream subscriber disconnected ({} active)", remaining);
+    }
+}
+
+struct StreamState {
+    db: Arc<DbStore>,
+    rx: broadcast::Receiver<PriceUpdate>,
+    ticker: Option<Interval>,
+    // Sent before waiting on the channel so new subscribers see a price immediately
+    initial: Option<PriceUpdate>,
+    _slot: SubscriberSlot,
+}
+
+async fn cached_update(db: &DbStore) -> Option<PriceUpdate> {
+    match db.get_market_cache().await {
+        Ok(cache) => Some(PriceUpdate::from_cache(&cache)),
+        Err(e) => {
+            warn!("Price stream couldn't read the market cache: {}", e);
+            None
+        }
+    }
+}
+
+async fn next_update(state: &mut StreamState) -> Option<PriceUpdate> {
+    if let Some(update) = state.initial.take() {
+        return Some(update);
+    }
+
+    loop {
+        let ticked = match state.ticker.as_mut() {
+            Some(ticker) => tokio::select! {
+                received = state.rx.recv() => match received {
+                    Ok(update) => return Some(update),
+                    // A slow subscriber skips to the newest price rather than disconnecting
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                },
+                _ = ticker.tick() => true,
+            },
+            None => match state.rx.recv().await {
+                Ok(update) => return Some(update),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            },
+        };
+
+        if ticked {
+            if let Some(update) = cached_update(&state.db).await {
+                return Some(update);
+            }
+        }
+    }
+}
+
+/// Stream of price updates for one subscriber: the cached price first, then every price the
+/// refresh path publishes. Fails when `SSE_MAX_SUBSCRIBERS` connections are already open.
+pub async fn price_update_stream(db: Arc<DbStore>) -> Result<impl Stream<Item = PriceUpdate> + Send + 'static> {
+    let slot = SubscriberSlot::acquire()
+        .ok_or_else(|| anyhow::anyhow!("Too many price stream subscribers (limit {})", max_subscribers()))?;
+    info!("Price stream subscriber connected ({} active)", ACTIVE_SUBSCRIBERS.load(Ordering::SeqCst));
+
+    // Subscribe before reading the cache so no refresh in between is missed
+    let rx = subscribe_price_updates();
+    let initial = cached_update(&db).await;
+    let ticker = poll_interval().map(|period| interval_at(Instant::now() + period, period));
+
+    let state = StreamState { db, rx, ticker, initial, _slot: slot };
+    Ok(stream::unfold(state, |mut state| async move {
+        next_update(&mut state).await.map(|update| (update, state))
+    }))
+}
@@ -0,0 +1,228 @@
+// src/services/rates.rs
+use std::future::Future;
+use std::sync::Arc;
+use chrono::{Duration, Utc};
+use log::{info, error, warn};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use anyhow::Result;
+use crate::models::MarketCache;
+use crate::services::bls::fetch_inflation_data;
+use crate::services::db::DbStore;
+use crate::services::error::ServiceError;
+use crate::services::treasury::fetch_tbill_data;
+use crate::services::treasury_long::{fetch_20y_bond_yield, fetch_20y_tips_yield};
+
+/// Default cap on simultaneous upstream fetches when several rates are refreshed together
+const DEFAULT_FETCH_CONCURRENCY: usize = 3;
+
+/// Max simultaneous upstream fetches, from `DASHBOARD_FETCH_CONCURRENCY` (at least 1)
+pub fn fetch_concurrency() -> usize {
+    std::env::var("DASHBOARD_FETCH_CONCURRENCY")
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_FETCH_CONCURRENCY)
+}
+
+/// Treasury and BLS values in the market cache that can be refreshed independently
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateSource {
+    TBill,
+    Bond20y,
+    Tips20y,
+    Inflation,
+}
+
+impl RateSource {
+    pub const ALL: [RateSource; 4] = [RateSource::TBill, RateSource::Bond20y, RateSource::Tips20y, RateSource::Inflation];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RateSource::TBill => "T-bill",
+            RateSource::Bond20y => "20y bond yield",
+            RateSource::Tips20y => "20y TIPS yield",
+            RateSource::Inflation => "inflation",
+        }
+    }
+
+    async fn fetch(self) -> std::result::Result<f64, String> {
+        let result = match self {
+            RateSource::TBill => fetch_tbill_data().await,
+            RateSource::Bond20y => fetch_20y_bond_yield().await,
+            RateSource::Tips20y => fetch_20y_tips_yield().await,
+            RateSource::Inflation => fetch_inflation_data().await.map_err(ServiceError::from),
+        };
+        result.map_err(|e| e.to_string())
+    }
+
+    fn apply(self, cache: &mut MarketCache, value: f64) {
+        match self {
+            RateSource::TBill => cache.tbill_yield = value,
+            RateSource::Bond20y => cache.bond_yield_20y = Some(value),
+            RateSource::Tips20y => cache.tips_yield_20y = Some(value),
+            RateSource::Inflation => cache.inflation_rate = value,
+        }
+        match self {
+            RateSource::Inflation => cache.timestamps.bls_data = Utc::now(),
+            _ => cache.timestamps.treasury_data = Utc::now(),
+        }
+    }
+
+    /// Sources older than the hourly refresh interval the rate handlers use
+    pub fn stale(cache: &MarketCache) -> Vec<RateSource> {
+        let cutoff = Utc::now() - Duration::hours(1);
+        Self::ALL.into_iter()
+            .filter(|source| match source {
+                RateSource::Inflation => cache.timestamps.bls_data < cutoff,
+                _ => cache.timestamps.treasury_data < cutoff,
+            })
+            .collect()
+    }
+}
+
+/// Run `tasks` with at most `limit` in flight at once, returning results in input order.
+/// A task that panics is logged and left out of the results rather than taking the caller down.
+pub async fn run_bounded<F, T>(tasks: Vec<F>, limit: usize) -> Vec<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let semaphore = Arc::new(Semaphore::new(limit.max(1)));
+    let mut set = JoinSet::new();
+
+    for (index, task) in tasks.into_iter().enumerate() {
+        let semaphore = semaphore.clone();
+        set.spawn(async move {
+            // The semaphore is never closed, so acquiring can't fail
+            let _permit = semaphore.acquire_owned().await.expect("fetch semaphore closed");
+            (index, task.await)
+        });
+    }
+
+    let mut results = Vec::with_capacity(set.len());
+    while let Some(joined) = set.join_next().await {
+        match joined {
+            Ok(result) => results.push(result),
+            Err(e) => warn!("Skipping a bounded fetch task that failed: {}", e),
+        }
+    }
+
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Fetch `sources` with at most `limit` requests in flight and apply the ones that succeed.
+/// Failed sources keep their previous cached value. Returns true if anything was updated.
+pub async fn refresh_sources(cache: &mut MarketCache, sources: &[RateSource], limit: usize) -> bool {
+    let fetches = sources.iter().map(|&source| async move { (source, source.fetch().await) }).collect();
+
+    let mut updated = false;
+    for (source, result) in run_bounded(fetches, limit).await {
+        match result {
+            Ok(value) => {
+                source.apply(cache, value);
+                updated = true;
+            }
+            Err(e) => error!("Failed to refresh {}: {}", source.name(), e),
+        }
+    }
+    updated
+}
+
+/// Refresh the Treasury and BLS values in the market cache in one pass.
+/// Used by the scheduler when handlers aren't allowed to write the cache themselves.
+/// Values that fail to fetch keep their previous cached value.
+pub async fn refresh_rates(db: &Arc<DbStore>) -> Result<()> {
+    let mut cache = db.get_market_cache().await?;
+    let mut treasury_updated = false;
+
+    match fetch_tbill_data().await {
+        Ok(rate) => {
+            cache.tbill_yield = rate;
+            treasury_updated = true;
+        }
+        Err(e) => error!("Scheduled T-bill refresh failed: {}", e),
+    }
+
+    match fetch_20y_bond_yield().await {
+        Ok(rate) => {
+            cache.bond_yield_20y = Some(rate);
+            treasury_updated = true;
+        }
+        Err(e) => error!("Scheduled 20y bond yield refresh failed: {}", e),
+    }
+
+    match fetch_20y_tips_yield().await {
+        Ok(rate) => {
+            cache.tips_yield_20y = Some(rate);
+            treasury_updated = true;
+        }
+        Err(e) => error!("Scheduled 20y TIPS yield refresh failed: {}", e),
+    }
+
+    if treasury_updated {
+        cache.timestamps.treasury_data = Utc::now();
+    }
+
+    let bls_updated = match fetch_inflation_data().await {
+        Ok(rate) => {
+            cache.inflation_rate = rate;
+            cache.timestamps.bls_data = Utc::now();
+            true
+        }
+        Err(e) => {
+            error!("Scheduled inflation refresh failed: {}", e);
+            false
+        }
+    };
+
+    if treasury_updated || bls_updated {
+        db.update_market_cache(&cache).await?;
+        info!("Scheduled rates refresh complete (treasury: {}, bls: {})", treasury_updated, bls_updated);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn run_bounded_never_exceeds_the_limit() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..10)
+            .map(|i| {
+                let in_flight = in_flight.clone();
+                let max_in_flight = max_in_flight.clone();
+                async move {
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    i
+                }
+            })
+            .collect();
+
+        let results = run_bounded(tasks, 3).await;
+
+        assert_eq!(results, (0..10).collect::<Vec<_>>());
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn run_bounded_skips_a_panicked_task() {
+        let tasks: Vec<std::pin::Pin<Box<dyn Future<Output = i32> + Send>>> = vec![
+            Box::pin(async { 1 }),
+            Box::pin(async { panic!("fetch blew up") }),
+            Box::pin(async { 3 }),
+        ];
+
+        assert_eq!(run_bounded(tasks, 2).await, vec![1, 3]);
+    }
+}
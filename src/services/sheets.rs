@@ -1,12 +1,25 @@
 // src/services/sheets.rs
 
 use serde::{Deserialize, Serialize};
-use crate::{models::{MonthlyData, QuarterlyData}, services::google_oauth::fetch_access_token_from_file};
-use log::info;
+use crate::{models::{MonthlyData, QuarterlyData}, services::google_oauth::{fetch_access_token_from_file, AccessToken}};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use serde_json::json;
 use reqwest::Client;
+use chrono::{Duration, Utc};
+use tokio::sync::Mutex;
 use crate::models::HistoricalRecord;
-use anyhow::Result;
+use crate::services::error::ServiceError;
+
+pub type Result<T> = std::result::Result<T, ServiceError>;
+
+/// A cached token is replaced this long before Google's expiry so it can't lapse mid-request
+const TOKEN_REFRESH_MARGIN_SECS: i64 = 60;
+
+/// Where spreadsheets live in the Sheets API unless `SheetsStore::with_api_base` says otherwise
+pub const SHEETS_API_BASE: &str = "https://sheets.googleapis.com/v4/spreadsheets";
 
 #[derive(Clone)]
 pub struct SheetsConfig {
@@ -32,7 +45,7 @@ impl Default for SheetNames {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RawMarketCache {
     pub timestamp_yahoo: String,
     pub timestamp_ycharts: String,
@@ -42,18 +55,37 @@ pub struct RawMarketCache {
     pub current_sp500_price: f64,
     pub current_cape: f64,
     pub cape_period: String,
-    pub tips_yield_20y: f64,
-    pub bond_yield_20y: f64,
+    /// Empty cells (never fetched) read as `None`
+    pub tips_yield_20y: Option<f64>,
+    pub bond_yield_20y: Option<f64>,
     pub tbill_yield: f64,
     pub inflation_rate: f64,
     pub latest_monthly_return: f64,    
     pub latest_month: String,          
+    // Columns added after the original layout default when missing (e.g. older local stores)
+    #[serde(default)]
+    pub last_year_close_captured: i32,
+    #[serde(default)]
+    pub timestamp_market_price: String,
+    #[serde(default)]
+    pub gdp: f64,
+    #[serde(default)]
+    pub gdp_period: String,
+    #[serde(default)]
+    pub timestamp_gdp: String,
+    #[serde(default)]
+    pub price_source: String,
 }
 
 pub struct SheetsStore {
     pub config: SheetsConfig,
     client: Client,
+    /// Sheets API base URL, without a trailing slash
+    api_base: String,
     sheet_names: SheetNames,
+    /// Reused until shortly before it expires; the lock also keeps concurrent callers from
+    /// minting a token each
+    token: Mutex<Option<AccessToken>>,
 }
 
 impl SheetsStore {
@@ -61,37 +93,46 @@ impl SheetsStore {
         SheetsStore {
             config,
             client: reqwest::Client::new(),
+            api_base: SHEETS_API_BASE.to_string(),
             sheet_names: SheetNames::default(),
+            token: Mutex::new(None),
         }
     }
 
+    /// Send Sheets API requests to `api_base` instead, e.g. a local stand-in
+    pub fn with_api_base(mut self, api_base: &str) -> Self {
+        self.api_base = api_base.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Bearer token for the Sheets API, fetched from Google only when the cached one is
+    /// missing or about to expire
     pub async fn get_auth_token(&self) -> Result<String> {
-        crate::services::google_oauth::fetch_access_token_from_file(&self.config.service_account_json_path).await
+        let mut cached = self.token.lock().await;
+        if let Some(token) = cached.as_ref() {
+            if token.expires_at - Duration::seconds(TOKEN_REFRESH_MARGIN_SECS) > Utc::now() {
+                return Ok(token.token.clone());
+            }
+        }
+
+        let token = fetch_access_token_from_file(&self.config.service_account_json_path)
+            .await
+            .map_err(ServiceError::auth)?;
+        info!("Fetched Google access token, valid until {}", token.expires_at);
+        *cached = Some(token.clone());
+        Ok(token.token)
     }
 
     pub async fn bulk_upload_historical_records(&self, records: &[HistoricalRecord]) -> Result<()> {
         let token = self.get_auth_token().await?;
         let client = reqwest::Client::new();
         
-        // Convert records to values, using empty string for zero values
-        let values: Vec<Vec<String>> = records.iter()
-            .map(|record| vec![
-                record.year.to_string(),
-                if record.sp500_price == 0.0 { "".to_string() } else { record.sp500_price.to_string() },
-                if record.dividend == 0.0 { "".to_string() } else { record.dividend.to_string() },
-                if record.dividend_yield == 0.0 { "".to_string() } else { record.dividend_yield.to_string() },
-                if record.eps == 0.0 { "".to_string() } else { record.eps.to_string() },
-                if record.cape == 0.0 { "".to_string() } else { record.cape.to_string() },
-                if record.inflation == 0.0 { "".to_string() } else { record.inflation.to_string() },
-                if record.total_return == 0.0 { "".to_string() } else { record.total_return.to_string() },
-                if record.cumulative_return == 0.0 { "".to_string() } else { record.cumulative_return.to_string() },
-            ])
-            .collect();
+        let values: Vec<Vec<String>> = records.iter().map(historical_row_values).collect();
     
-        let range = format!("{}!A2:I{}", self.sheet_names.historical_data, values.len() + 1);
+        let range = format!("{}!A2:J{}", self.sheet_names.historical_data, values.len() + 1);
         let url = format!(
-            "https://sheets.googleapis.com/v4/spreadsheets/{}/values/{}",
-            self.config.spreadsheet_id,
+            "{}/{}/values/{}",
+            self.api_base, self.config.spreadsheet_id,
             range
         );
     
@@ -111,20 +152,20 @@ impl SheetsStore {
     
             if !response.status().is_success() {
                 let error_text = response.text().await?;
-                return Err(anyhow::anyhow!("Failed to upload historical records: {}", error_text));
+                return Err(ServiceError::Sheets(format!("Failed to upload historical records: {}", error_text)));
             }
     
         Ok(())
     }    
 
     pub async fn get_market_cache(&self) -> Result<RawMarketCache> {
-        let token = fetch_access_token_from_file(&self.config.service_account_json_path).await?;
+        let token = self.get_auth_token().await?;
     
         // Update range to include new columns
-        let range = format!("{}!A2:N2", self.sheet_names.market_cache);
+        let range = format!("{}!A2:T2", self.sheet_names.market_cache);
         let url = format!(
-            "https://sheets.googleapis.com/v4/spreadsheets/{}/values/{}",
-            self.config.spreadsheet_id, range
+            "{}/{}/values/{}",
+            self.api_base, self.config.spreadsheet_id, range
         );
     
         let response: serde_json::Value = self.client
@@ -138,35 +179,20 @@ impl SheetsStore {
     
         if let Some(values) = response["values"].as_array() {
             if let Some(row) = values.first() {
-                return Ok(RawMarketCache {
-                    timestamp_yahoo: row.get(0).and_then(|v| v.as_str()).unwrap_or("").to_string(),
-                    timestamp_ycharts: row.get(1).and_then(|v| v.as_str()).unwrap_or("").to_string(),
-                    timestamp_treasury: row.get(2).and_then(|v| v.as_str()).unwrap_or("").to_string(),
-                    timestamp_bls: row.get(3).and_then(|v| v.as_str()).unwrap_or("").to_string(),
-                    daily_close_sp500_price: row.get(4).and_then(|v| v.as_str()).unwrap_or("0").parse()?,
-                    current_sp500_price: row.get(5).and_then(|v| v.as_str()).unwrap_or("0").parse()?,
-                    current_cape: row.get(6).and_then(|v| v.as_str()).unwrap_or("0").parse()?,
-                    cape_period: row.get(7).and_then(|v| v.as_str()).unwrap_or("").to_string(),
-                    tips_yield_20y: row.get(8).and_then(|v| v.as_str()).unwrap_or("0").parse()?,
-                    bond_yield_20y: row.get(9).and_then(|v| v.as_str()).unwrap_or("0").parse()?,
-                    tbill_yield: row.get(10).and_then(|v| v.as_str()).unwrap_or("0").parse()?,
-                    inflation_rate: row.get(11).and_then(|v| v.as_str()).unwrap_or("0").parse()?,
-                    latest_monthly_return: row.get(12).and_then(|v| v.as_str()).unwrap_or("0").parse()?,
-                    latest_month: row.get(13).and_then(|v| v.as_str()).unwrap_or("").to_string(),
-                });
+                return parse_market_cache_row(row);
             }
         }
     
-        Err(anyhow::anyhow!("No market cache data found"))
+        Err(ServiceError::Sheets("No market cache data found".to_string()))
     }    
 
     pub async fn update_market_cache(&self, cache: &RawMarketCache) -> Result<()> {
-        let token = fetch_access_token_from_file(&self.config.service_account_json_path).await?;
+        let token = self.get_auth_token().await?;
     
-        let range = format!("{}!A2:N2", self.sheet_names.market_cache);
+        let range = format!("{}!A2:T2", self.sheet_names.market_cache);
         let url = format!(
-            "https://sheets.googleapis.com/v4/spreadsheets/{}/values/{}?valueInputOption=RAW",
-            self.config.spreadsheet_id, range
+            "{}/{}/values/{}?valueInputOption=RAW",
+            self.api_base, self.config.spreadsheet_id, range
         );
     
         let values = vec![vec![
@@ -178,12 +204,18 @@ impl SheetsStore {
             cache.current_sp500_price.to_string(),
             cache.current_cape.to_string(),
             cache.cape_period.clone(),
-            cache.tips_yield_20y.to_string(),
-            cache.bond_yield_20y.to_string(),
+            cache.tips_yield_20y.map(|v| v.to_string()).unwrap_or_default(),
+            cache.bond_yield_20y.map(|v| v.to_string()).unwrap_or_default(),
             cache.tbill_yield.to_string(),
             cache.inflation_rate.to_string(),
             cache.latest_monthly_return.to_string(),
             cache.latest_month.clone(),
+            cache.last_year_close_captured.to_string(),
+            cache.timestamp_market_price.clone(),
+            cache.gdp.to_string(),
+            cache.gdp_period.clone(),
+            cache.timestamp_gdp.clone(),
+            cache.price_source.clone(),
         ]];
     
         let body = json!({
@@ -203,12 +235,12 @@ impl SheetsStore {
 
     /// Example of reading from "QuarterlyData!A2:D" range
     pub async fn get_quarterly_data(&self) -> Result<Vec<QuarterlyData>> {
-        let token = fetch_access_token_from_file(&self.config.service_account_json_path).await?;
+        let token = self.get_auth_token().await?;
 
         let range = format!("{}!A2:D", self.sheet_names.quarterly_data);
         let url = format!(
-            "https://sheets.googleapis.com/v4/spreadsheets/{}/values/{}",
-            self.config.spreadsheet_id, range
+            "{}/{}/values/{}",
+            self.api_base, self.config.spreadsheet_id, range
         );
 
         let response: serde_json::Value = self.client
@@ -240,12 +272,12 @@ impl SheetsStore {
     }
 
     pub async fn update_quarterly_data(&self, data: &[QuarterlyData]) -> Result<()> {
-        let token = fetch_access_token_from_file(&self.config.service_account_json_path).await?;
+        let token = self.get_auth_token().await?;
 
         let range = format!("{}!A2:D{}", self.sheet_names.quarterly_data, data.len() + 1);
         let url = format!(
-            "https://sheets.googleapis.com/v4/spreadsheets/{}/values/{}?valueInputOption=RAW",
-            self.config.spreadsheet_id, range
+            "{}/{}/values/{}?valueInputOption=RAW",
+            self.api_base, self.config.spreadsheet_id, range
         );
 
         let values: Vec<Vec<String>> = data.iter().map(|row| {
@@ -277,8 +309,8 @@ impl SheetsStore {
         let token = self.get_auth_token().await?;
         let range = format!("{}!A2:B", "MonthlyData");
         let url = format!(
-            "https://sheets.googleapis.com/v4/spreadsheets/{}/values/{}",
-            self.config.spreadsheet_id, range
+            "{}/{}/values/{}",
+            self.api_base, self.config.spreadsheet_id, range
         );
 
         let response: serde_json::Value = self.client
@@ -312,8 +344,8 @@ impl SheetsStore {
         let token = self.get_auth_token().await?;
         let range = format!("{}!A2:B{}", "MonthlyData", data.len() + 1);
         let url = format!(
-            "https://sheets.googleapis.com/v4/spreadsheets/{}/values/{}?valueInputOption=RAW",
-            self.config.spreadsheet_id, range
+            "{}/{}/values/{}?valueInputOption=RAW",
+            self.api_base, self.config.spreadsheet_id, range
         );
 
         let values: Vec<Vec<String>> = data.iter().map(|row| {
@@ -338,15 +370,15 @@ impl SheetsStore {
         Ok(())
     }
 
-    pub async fn get_historical_data(&self) -> Result<Vec<HistoricalRecord>> {
-        let token = fetch_access_token_from_file(&self.config.service_account_json_path).await?;
-    
-        let range = format!("{}!A2:I", self.sheet_names.historical_data);
+    /// Read the first row of a sheet
+    pub async fn get_header_row(&self, sheet: &str) -> Result<Vec<String>> {
+        let token = self.get_auth_token().await?;
+
         let url = format!(
-            "https://sheets.googleapis.com/v4/spreadsheets/{}/values/{}",
-            self.config.spreadsheet_id, range
+            "{}/{}/values/{}!1:1",
+            self.api_base, self.config.spreadsheet_id, sheet
         );
-    
+
         let response: serde_json::Value = self.client
             .get(&url)
             .bearer_auth(token)
@@ -355,75 +387,735 @@ impl SheetsStore {
             .error_for_status()?
             .json()
             .await?;
-    
-        let mut historical_data = Vec::new();
-        if let Some(values) = response["values"].as_array() {
-            for row in values {
-                // Helper function to parse optional float value
-                let parse_opt_float = |value: Option<&serde_json::Value>| -> f64 {
-                    value
-                        .and_then(|v| v.as_str())
-                        .filter(|s| !s.is_empty())
-                        .and_then(|s| s.parse::<f64>().ok())
-                        .unwrap_or(0.0)
-                };
-    
-                historical_data.push(HistoricalRecord {
-                    year: row.get(0).and_then(|v| v.as_str()).unwrap_or("0").parse()?,
-                    sp500_price: parse_opt_float(row.get(1)),
-                    dividend: parse_opt_float(row.get(2)),
-                    dividend_yield: parse_opt_float(row.get(3)),
-                    eps: parse_opt_float(row.get(4)),
-                    cape: parse_opt_float(row.get(5)),
-                    inflation: parse_opt_float(row.get(6)),
-                    total_return: parse_opt_float(row.get(7)),
-                    cumulative_return: parse_opt_float(row.get(8)),
-                });
+
+        Ok(response["values"][0].as_array()
+            .map(|cells| cells.iter().map(|c| c.as_str().unwrap_or("").to_string()).collect())
+            .unwrap_or_default())
+    }
+
+    /// Compare every sheet's header row against `SHEET_SCHEMAS`
+    pub async fn check_schema(&self) -> Result<Vec<HeaderDrift>> {
+        let mut drift = Vec::new();
+        for (sheet, expected) in SHEET_SCHEMAS {
+            let headers = self.get_header_row(sheet).await?;
+            if let Some(diff) = diff_headers(sheet, expected, &headers) {
+                drift.push(diff);
             }
         }
-    
-        Ok(historical_data)
+        Ok(drift)
     }
 
-    pub async fn update_historical_record(&self, record: &HistoricalRecord) -> Result<()> {
-        let all_records = self.get_historical_data().await?;
-        let row_index = all_records.iter().position(|r| r.year == record.year)
-            .ok_or(anyhow::anyhow!("Record not found"))?;
-    
-        let token = fetch_access_token_from_file(&self.config.service_account_json_path).await?;
+    pub async fn get_historical_data(&self) -> Result<Vec<HistoricalRecord>> {
+        // Read from row 1 so the header row can be used to map columns by name
+        let rows = self.get_historical_rows("A1:Z").await?;
+        parse_historical_rows(&rows)
+    }
+
+    /// Raw HistoricalData cells in `range` (e.g. `A1:Z`), one array per row
+    async fn get_historical_rows(&self, range: &str) -> Result<Vec<serde_json::Value>> {
+        let token = self.get_auth_token().await?;
     
-        let row_num = row_index + 2;
-        let range = format!("{}!A{}:I{}", self.sheet_names.historical_data, row_num, row_num);
+        let range = format!("{}!{}", self.sheet_names.historical_data, range);
         let url = format!(
-            "https://sheets.googleapis.com/v4/spreadsheets/{}/values/{}?valueInputOption=RAW",
-            self.config.spreadsheet_id, range
+            "{}/{}/values/{}",
+            self.api_base, self.config.spreadsheet_id, range
         );
     
-        let values = vec![vec![
-            record.year.to_string(),
-            if record.sp500_price == 0.0 { "".to_string() } else { record.sp500_price.to_string() },
-            if record.dividend == 0.0 { "".to_string() } else { record.dividend.to_string() },
-            if record.dividend_yield == 0.0 { "".to_string() } else { record.dividend_yield.to_string() },
-            if record.eps == 0.0 { "".to_string() } else { record.eps.to_string() },
-            if record.cape == 0.0 { "".to_string() } else { record.cape.to_string() },
-            if record.inflation == 0.0 { "".to_string() } else { record.inflation.to_string() },
-            if record.total_return == 0.0 { "".to_string() } else { record.total_return.to_string() },
-            if record.cumulative_return == 0.0 { "".to_string() } else { record.cumulative_return.to_string() },
-        ]];
+        let response: serde_json::Value = self.client
+            .get(&url)
+            .bearer_auth(token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
     
+        Ok(response["values"].as_array().cloned().unwrap_or_default())
+    }
+
+    pub async fn update_historical_record(&self, record: &HistoricalRecord) -> Result<()> {
+        self.update_historical_records(std::slice::from_ref(record)).await
+    }
+
+    /// Overwrite the existing rows for several years with one read and a single
+    /// `values:batchUpdate` call. Fails without writing if any year has no row.
+    pub async fn update_historical_records(&self, records: &[HistoricalRecord]) -> Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let sheet_rows = self.get_historical_rows("A1:Z").await?;
+        let layout = HistoricalLayout::of_rows(&sheet_rows);
+        let all_records = parse_historical_rows(&sheet_rows)?;
+        let mut rows = Vec::with_capacity(records.len());
+        for record in records {
+            let index = all_records.iter().rposition(|r| r.year == record.year)
+                .ok_or_else(|| ServiceError::NotFound(format!("No HistoricalData row for {}", record.year)))?;
+            rows.push((index, record.clone()));
+        }
+
+        self.write_historical_rows_with(&layout, &rows).await
+    }
+
+    /// Overwrite HistoricalData records by their 0-based position in `get_historical_data`, all in
+    /// one `values:batchUpdate`. The sheet's header row is read first so each value lands in the
+    /// column and row the reader took it from.
+    pub async fn write_historical_rows(&self, rows: &[(usize, HistoricalRecord)]) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let header = self.get_historical_rows("A1:Z1").await?;
+        self.write_historical_rows_with(&HistoricalLayout::of_rows(&header), rows).await
+    }
+
+    async fn write_historical_rows_with(&self, layout: &HistoricalLayout, rows: &[(usize, HistoricalRecord)]) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        let data: Vec<_> = rows.iter()
+            .map(|(index, record)| {
+                let row_num = layout.row_num(*index);
+                let values = layout.row_values(record);
+                json!({
+                    "range": format!(
+                        "{}!A{}:{}{}",
+                        self.sheet_names.historical_data, row_num, column_letter(values.len() - 1), row_num
+                    ),
+                    "values": [values],
+                })
+            })
+            .collect();
+
+        let token = self.get_auth_token().await?;
+        let url = format!(
+            "{}/{}/values:batchUpdate",
+            self.api_base, self.config.spreadsheet_id
+        );
         let body = json!({
-            "values": values,
+            "valueInputOption": "RAW",
+            "data": data,
         });
-    
-        let response = self.client
-            .put(&url)
+
+        self.client
+            .post(&url)
             .bearer_auth(token)
             .json(&body)
             .send()
             .await?
             .error_for_status()?;
-    
-        info!("update_historical_record response: {:?}", response);
+
+        info!("Updated {} HistoricalData rows in one batch", rows.len());
         Ok(())
     }
 }
+
+/// Where HistoricalData records sit in the sheet, as `parse_historical_rows` found them
+struct HistoricalLayout {
+    columns: HashMap<String, usize>,
+    has_header: bool,
+}
+
+impl HistoricalLayout {
+    /// Layout of a sheet whose first rows are `rows` (only the first one is looked at)
+    fn of_rows(rows: &[serde_json::Value]) -> Self {
+        let (columns, has_header) = build_column_map(rows.first(), &HISTORICAL_COLUMNS);
+        HistoricalLayout { columns, has_header }
+    }
+
+    /// 1-based sheet row of the record at 0-based `index`; data starts below the header if any
+    fn row_num(&self, index: usize) -> usize {
+        index + if self.has_header { 2 } else { 1 }
+    }
+
+    /// A record as a row with each value in its mapped column. Unmapped columns are null,
+    /// which the Sheets API leaves untouched.
+    fn row_values(&self, record: &HistoricalRecord) -> Vec<serde_json::Value> {
+        let width = self.columns.values().max().map_or(0, |last| last + 1);
+        let mut values = vec![serde_json::Value::Null; width];
+        for (name, value) in HISTORICAL_COLUMNS.iter().zip(historical_row_values(record)) {
+            if let Some(&idx) = self.columns.get(*name) {
+                values[idx] = json!(value);
+            }
+        }
+        values
+    }
+}
+
+/// Sheet column letter for a 0-based index within A-Z, the widest range the store reads
+fn column_letter(idx: usize) -> char {
+    (b'A' + idx.min(25) as u8) as char
+}
+
+/// The MarketCache data row. Empty or absent numeric cells read as 0, so a column added to the
+/// layout but not yet filled in doesn't fail the read.
+fn parse_market_cache_row(row: &serde_json::Value) -> Result<RawMarketCache> {
+    Ok(RawMarketCache {
+        timestamp_yahoo: row.get(0).and_then(|v| v.as_str()).unwrap_or("").to_string(),
+        timestamp_ycharts: row.get(1).and_then(|v| v.as_str()).unwrap_or("").to_string(),
+        timestamp_treasury: row.get(2).and_then(|v| v.as_str()).unwrap_or("").to_string(),
+        timestamp_bls: row.get(3).and_then(|v| v.as_str()).unwrap_or("").to_string(),
+        daily_close_sp500_price: parse_cell_or_default(row.get(4))?,
+        current_sp500_price: parse_cell_or_default(row.get(5))?,
+        current_cape: parse_cell_or_default(row.get(6))?,
+        cape_period: row.get(7).and_then(|v| v.as_str()).unwrap_or("").to_string(),
+        tips_yield_20y: parse_optional_cell(row.get(8))?,
+        bond_yield_20y: parse_optional_cell(row.get(9))?,
+        tbill_yield: parse_cell_or_default(row.get(10))?,
+        inflation_rate: parse_cell_or_default(row.get(11))?,
+        latest_monthly_return: parse_cell_or_default(row.get(12))?,
+        latest_month: row.get(13).and_then(|v| v.as_str()).unwrap_or("").to_string(),
+        last_year_close_captured: parse_cell_or_default(row.get(14))?,
+        timestamp_market_price: row.get(15).and_then(|v| v.as_str()).unwrap_or("").to_string(),
+        gdp: parse_cell_or_default(row.get(16))?,
+        gdp_period: row.get(17).and_then(|v| v.as_str()).unwrap_or("").to_string(),
+        timestamp_gdp: row.get(18).and_then(|v| v.as_str()).unwrap_or("").to_string(),
+        price_source: row.get(19).and_then(|v| v.as_str()).unwrap_or("").to_string(),
+    })
+}
+
+/// A record as a HistoricalData row, in `HISTORICAL_COLUMNS` order. Zeros are written as
+/// empty cells so missing values stay blank in the sheet.
+fn historical_row_values(record: &HistoricalRecord) -> Vec<String> {
+    let cell = |value: f64| if value == 0.0 { String::new() } else { value.to_string() };
+    vec![
+        record.year.to_string(),
+        cell(record.sp500_price),
+        cell(record.dividend),
+        cell(record.dividend_yield),
+        cell(record.eps),
+        cell(record.cape),
+        cell(record.inflation),
+        cell(record.total_return),
+        cell(record.cumulative_return),
+        if record.total_return_approximated { "TRUE".to_string() } else { String::new() },
+    ]
+}
+
+/// A numeric cell that may be left empty; empty or absent cells are `None`
+fn parse_optional_cell(cell: Option<&serde_json::Value>) -> Result<Option<f64>> {
+    match cell.and_then(|v| v.as_str()).map(str::trim).filter(|s| !s.is_empty()) {
+        Some(text) => Ok(Some(text.parse()?)),
+        None => Ok(None),
+    }
+}
+
+/// A numeric cell whose empty or absent value means the default (0)
+fn parse_cell_or_default<T>(cell: Option<&serde_json::Value>) -> Result<T>
+where
+    T: std::str::FromStr + Default,
+    ServiceError: From<T::Err>,
+{
+    match cell.and_then(|v| v.as_str()).map(str::trim).filter(|s| !s.is_empty()) {
+        Some(text) => Ok(text.parse()?),
+        None => Ok(T::default()),
+    }
+}
+
+/// Stable fingerprint of a set of rows, used to detect edits made between a read and a write
+pub fn rows_fingerprint<T: Serialize>(rows: &[T]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(rows).unwrap_or_default().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Column headers of the HistoricalData sheet, in their default positional order
+pub const HISTORICAL_COLUMNS: [&str; 10] = [
+    "year",
+    "sp500_price",
+    "dividend",
+    "dividend_yield",
+    "eps",
+    "cape",
+    "inflation",
+    "total_return",
+    "cumulative_return",
+    "total_return_approximated",
+];
+
+/// Column headers of the MarketCache sheet, in the order `update_market_cache` writes them
+pub const MARKET_CACHE_COLUMNS: [&str; 20] = [
+    "timestamp_yahoo",
+    "timestamp_ycharts",
+    "timestamp_treasury",
+    "timestamp_bls",
+    "daily_close_sp500_price",
+    "current_sp500_price",
+    "current_cape",
+    "cape_period",
+    "tips_yield_20y",
+    "bond_yield_20y",
+    "tbill_yield",
+    "inflation_rate",
+    "latest_monthly_return",
+    "latest_return_month",
+    "last_year_close_captured",
+    "timestamp_market_price",
+    "gdp",
+    "gdp_period",
+    "timestamp_gdp",
+    "price_source",
+];
+
+/// Column headers of the QuarterlyData sheet
+pub const QUARTERLY_COLUMNS: [&str; 4] = ["quarter", "dividend", "eps_actual", "eps_estimated"];
+
+/// Column headers of the MonthlyData sheet
+pub const MONTHLY_COLUMNS: [&str; 2] = ["month", "total_return"];
+
+/// Every sheet the store reads, with the header row the code expects it to have
+pub const SHEET_SCHEMAS: [(&str, &[&str]); 4] = [
+    ("MarketCache", &MARKET_CACHE_COLUMNS),
+    ("QuarterlyData", &QUARTERLY_COLUMNS),
+    ("MonthlyData", &MONTHLY_COLUMNS),
+    ("HistoricalData", &HISTORICAL_COLUMNS),
+];
+
+/// Differences between a sheet's header row and the expected headers
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HeaderDrift {
+    pub sheet: String,
+    /// Expected headers absent from the sheet
+    pub missing: Vec<String>,
+    /// Sheet headers the code doesn't know about
+    pub unexpected: Vec<String>,
+    /// Expected headers present in the sheet but at a different column, as (header, expected, actual)
+    pub moved: Vec<(String, usize, usize)>,
+}
+
+impl std::fmt::Display for HeaderDrift {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:", self.sheet)?;
+        if !self.missing.is_empty() {
+            write!(f, " missing {:?}", self.missing)?;
+        }
+        if !self.unexpected.is_empty() {
+            write!(f, " unexpected {:?}", self.unexpected)?;
+        }
+        for (name, expected, actual) in &self.moved {
+            write!(f, " '{}' in column {} (expected {})", name, actual + 1, expected + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare a header row to the expected headers (case-insensitive, whitespace trimmed).
+/// Returns `None` when they match exactly.
+pub fn diff_headers(sheet: &str, expected: &[&str], actual: &[String]) -> Option<HeaderDrift> {
+    let actual: Vec<String> = actual.iter().map(|h| h.trim().to_lowercase()).collect();
+    let position = |name: &str| actual.iter().position(|h| h == name);
+
+    let missing = expected.iter()
+        .filter(|name| position(name).is_none())
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+    let unexpected = actual.iter()
+        .filter(|h| !h.is_empty() && !expected.contains(&h.as_str()))
+        .cloned()
+        .collect::<Vec<_>>();
+    let moved = expected.iter()
+        .enumerate()
+        .filter_map(|(idx, name)| position(name).filter(|pos| *pos != idx).map(|pos| (name.to_string(), idx, pos)))
+        .collect::<Vec<_>>();
+
+    if missing.is_empty() && unexpected.is_empty() && moved.is_empty() {
+        None
+    } else {
+        Some(HeaderDrift { sheet: sheet.to_string(), missing, unexpected, moved })
+    }
+}
+
+/// Build a header name -> column index map from the sheet's header row, and report whether
+/// that row really was a header. Falls back to the positional layout in `expected` when the
+/// row is missing or doesn't contain any of the expected headers; the row is data then.
+pub fn build_column_map(header_row: Option<&serde_json::Value>, expected: &[&str]) -> (HashMap<String, usize>, bool) {
+    let mut mapped = HashMap::new();
+
+    if let Some(cells) = header_row.and_then(|row| row.as_array()) {
+        for (idx, cell) in cells.iter().enumerate() {
+            let name = cell.as_str().unwrap_or("").trim().to_lowercase();
+            if expected.contains(&name.as_str()) && !mapped.contains_key(&name) {
+                mapped.insert(name, idx);
+            }
+        }
+    }
+
+    if mapped.is_empty() {
+        warn!("No recognizable header row found, falling back to positional column mapping");
+        let positional = expected.iter()
+            .enumerate()
+            .map(|(idx, name)| (name.to_string(), idx))
+            .collect();
+        return (positional, false);
+    }
+
+    for name in expected {
+        if !mapped.contains_key(*name) {
+            warn!("Header '{}' not found in sheet, treating column as empty", name);
+        }
+    }
+
+    (mapped, true)
+}
+
+/// Parse HistoricalData rows into records, mapping columns by header name.
+/// The first row is skipped only when it is a header; a sheet without one is read positionally.
+pub fn parse_historical_rows(rows: &[serde_json::Value]) -> Result<Vec<HistoricalRecord>> {
+    let (columns, has_header) = build_column_map(rows.first(), &HISTORICAL_COLUMNS);
+    let data_rows = if has_header { rows.get(1..).unwrap_or(&[]) } else { rows };
+
+    let mut historical_data = Vec::new();
+    for row in data_rows {
+        // Look up a cell by header name and parse it as an optional float value
+        let cell = |name: &str| -> Option<&serde_json::Value> {
+            columns.get(name).and_then(|idx| row.get(*idx))
+        };
+        let parse_opt_float = |name: &str| -> f64 {
+            cell(name)
+                .and_then(|v| v.as_str())
+                .filter(|s| !s.is_empty())
+                .and_then(|s| s.parse::<f64>().ok())
+                .unwrap_or(0.0)
+        };
+
+        historical_data.push(HistoricalRecord {
+            year: cell("year").and_then(|v| v.as_str()).unwrap_or("0").parse()?,
+            sp500_price: parse_opt_float("sp500_price"),
+            dividend: parse_opt_float("dividend"),
+            dividend_yield: parse_opt_float("dividend_yield"),
+            eps: parse_opt_float("eps"),
+            cape: parse_opt_float("cape"),
+            inflation: parse_opt_float("inflation"),
+            total_return: parse_opt_float("total_return"),
+            cumulative_return: parse_opt_float("cumulative_return"),
+            total_return_approximated: cell("total_return_approximated")
+                .and_then(|v| v.as_str())
+                .is_some_and(|s| s.trim().eq_ignore_ascii_case("true")),
+        });
+    }
+
+    Ok(historical_data)
+}
+
+/// Parse the `stk_mkt.csv` export (year, price, dividend, dividend yield, EPS, CAPE, inflation,
+/// total return, cumulative return) into records. Header rows are skipped even when Excel has
+/// prefixed them with a UTF-8 BOM; empty cells read as 0.0.
+pub fn parse_stk_mkt_csv<R: std::io::Read>(reader: R) -> Result<Vec<HistoricalRecord>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(reader);
+
+    let parse_float = |s: &str, field: &str| -> Result<f64> {
+        let trimmed = s.trim();
+        if trimmed.is_empty() {
+            return Ok(0.0);
+        }
+        trimmed.parse::<f64>().map_err(|e| {
+            ServiceError::Parse(format!("Error parsing {} value '{}': {}", field, trimmed, e))
+        })
+    };
+
+    let mut records = Vec::new();
+    for (row_number, result) in rdr.records().enumerate() {
+        let record = result.map_err(|e| ServiceError::Parse(format!("Row {}: {}", row_number + 1, e)))?;
+        let field = |idx: usize| record.get(idx).unwrap_or("");
+
+        // csv strips a BOM at the very start of the file, but Excel exports that were
+        // concatenated can carry one on a later header too
+        if field(0).trim_start_matches('\u{feff}').trim() == "Year" {
+            continue;
+        }
+
+        records.push(HistoricalRecord {
+            year: field(0).trim().parse().map_err(|e| {
+                ServiceError::Parse(format!("Error parsing year '{}' on row {}: {}", field(0), row_number + 1, e))
+            })?,
+            sp500_price: parse_float(field(1), "SP500 price")?,
+            dividend: parse_float(field(2), "dividend")?,
+            dividend_yield: parse_float(field(3), "dividend yield")?,
+            eps: parse_float(field(4), "EPS")?,
+            cape: parse_float(field(5), "CAPE")?,
+            inflation: parse_float(field(6), "inflation")?,
+            total_return: parse_float(field(7), "total return")?,
+            cumulative_return: parse_float(field(8), "cumulative return")?,
+            total_return_approximated: false,
+        });
+    }
+
+    Ok(records)
+}
+
+/// Collapse rows that share a year into one record, keeping first-seen order.
+/// Later rows win for every non-zero field, so an accidental duplicate never blanks out data.
+pub fn dedupe_historical_years(records: Vec<HistoricalRecord>) -> Vec<HistoricalRecord> {
+    let mut deduped: Vec<HistoricalRecord> = Vec::with_capacity(records.len());
+
+    for record in records {
+        match deduped.iter_mut().find(|r| r.year == record.year) {
+            Some(existing) => {
+                warn!("Duplicate HistoricalData rows for {}, merging non-zero fields from the later row", record.year);
+                merge_historical_record(existing, &record);
+            }
+            None => deduped.push(record),
+        }
+    }
+
+    deduped
+}
+
+fn merge_historical_record(into: &mut HistoricalRecord, later: &HistoricalRecord) {
+    let fields = [
+        (&mut into.sp500_price, later.sp500_price),
+        (&mut into.dividend, later.dividend),
+        (&mut into.dividend_yield, later.dividend_yield),
+        (&mut into.eps, later.eps),
+        (&mut into.cape, later.cape),
+        (&mut into.inflation, later.inflation),
+        (&mut into.total_return, later.total_return),
+        (&mut into.cumulative_return, later.cumulative_return),
+    ];
+    for (field, value) in fields {
+        if value != 0.0 {
+            *field = value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::sync::atomic::Ordering;
+    use crate::services::test_support::{mock_server, mock_sheets_store, service_account_file, token_response, TempFile};
+
+    #[test]
+    fn parse_historical_rows_maps_reordered_columns_by_header() {
+        let rows = vec![
+            json!(["eps", "year", "inflation", "sp500_price"]),
+            json!(["150.5", "2020", "0.012", "3756.07"]),
+            json!(["197.9", "2021", "0.047", "4766.18"]),
+        ];
+
+        let records = parse_historical_rows(&rows).unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].year, 2020);
+        assert_eq!(records[0].eps, 150.5);
+        assert_eq!(records[0].inflation, 0.012);
+        assert_eq!(records[0].sp500_price, 3756.07);
+        assert_eq!(records[0].dividend, 0.0);
+        assert_eq!(records[1].year, 2021);
+        assert_eq!(records[1].sp500_price, 4766.18);
+    }
+
+    #[test]
+    fn parse_historical_rows_keeps_first_row_when_sheet_has_no_header() {
+        let rows = vec![
+            json!(["2020", "3756.07", "58.28"]),
+            json!(["2021", "4766.18", "60.40"]),
+        ];
+
+        let records = parse_historical_rows(&rows).unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].year, 2020);
+        assert_eq!(records[0].sp500_price, 3756.07);
+        assert_eq!(records[0].dividend, 58.28);
+        assert_eq!(records[1].year, 2021);
+    }
+
+    #[test]
+    fn writes_go_to_the_columns_and_rows_the_reader_used() {
+        let record = HistoricalRecord { year: 2021, sp500_price: 4766.18, eps: 197.9, ..Default::default() };
+
+        // Reordered columns with one the store doesn't know about, which is left untouched
+        let layout = HistoricalLayout::of_rows(&[json!(["eps", "notes", "year", "sp500_price"])]);
+        assert_eq!(layout.row_num(0), 2);
+        assert_eq!(layout.row_values(&record), vec![json!("197.9"), Value::Null, json!("2021"), json!("4766.18")]);
+
+        // Without a header the sheet is positional and data starts on row 1
+        let layout = HistoricalLayout::of_rows(&[json!(["2020", "3756.07"])]);
+        assert_eq!(layout.row_num(0), 1);
+        let values = layout.row_values(&record);
+        assert_eq!(values.len(), HISTORICAL_COLUMNS.len());
+        assert_eq!((&values[0], &values[1], &values[4]), (&json!("2021"), &json!("4766.18"), &json!("197.9")));
+    }
+
+    #[test]
+    fn build_column_map_reports_whether_a_header_was_found() {
+        let (columns, has_header) = build_column_map(Some(&json!(["Year", "EPS"])), &HISTORICAL_COLUMNS);
+        assert!(has_header);
+        assert_eq!(columns.get("year"), Some(&0));
+        assert_eq!(columns.get("eps"), Some(&1));
+        assert_eq!(columns.get("cape"), None);
+
+        let (columns, has_header) = build_column_map(Some(&json!(["2020", "3756.07"])), &HISTORICAL_COLUMNS);
+        assert!(!has_header);
+        assert_eq!(columns.get("sp500_price"), Some(&1));
+
+        let (_, has_header) = build_column_map(None, &HISTORICAL_COLUMNS);
+        assert!(!has_header);
+    }
+
+    #[test]
+    fn optional_cells_distinguish_empty_from_zero() {
+        assert_eq!(parse_optional_cell(Some(&json!("0"))).unwrap(), Some(0.0));
+        assert_eq!(parse_optional_cell(Some(&json!("4.61"))).unwrap(), Some(4.61));
+        assert_eq!(parse_optional_cell(Some(&json!(""))).unwrap(), None);
+        assert_eq!(parse_optional_cell(None).unwrap(), None);
+    }
+
+    #[test]
+    fn empty_cells_in_added_market_cache_columns_read_as_zero() {
+        let row = json!([
+            "2025-01-06T21:00:00Z", "2025-01-06T21:00:00Z", "2025-01-06T21:00:00Z", "2025-01-06T21:00:00Z",
+            "5942.47", "5975.38", "37.05", "2024-12", "", "4.86", "4.31", "0.029", "-0.0242", "2024-12",
+            "", "", "", "",
+        ]);
+
+        let cache = parse_market_cache_row(&row).unwrap();
+
+        assert_eq!(cache.current_sp500_price, 5975.38);
+        assert_eq!(cache.tips_yield_20y, None);
+        assert_eq!(cache.bond_yield_20y, Some(4.86));
+        assert_eq!(cache.last_year_close_captured, 0);
+        assert_eq!(cache.gdp, 0.0);
+        assert_eq!(cache.price_source, "");
+        assert!(parse_market_cache_row(&json!(["", "", "", "", "n/a"])).is_err());
+    }
+
+    #[test]
+    fn duplicate_years_merge_into_one_record() {
+        let records = vec![
+            HistoricalRecord { year: 2007, sp500_price: 1468.36, ..Default::default() },
+            HistoricalRecord { year: 2008, sp500_price: 903.25, eps: 14.88, ..Default::default() },
+            HistoricalRecord { year: 2008, cape: 15.17, eps: 49.51, ..Default::default() },
+        ];
+
+        let deduped = dedupe_historical_years(records);
+
+        assert_eq!(deduped.len(), 2);
+        assert_eq!(deduped[0].year, 2007);
+        let merged = &deduped[1];
+        assert_eq!(merged.year, 2008);
+        // Zeros in the later row don't blank out the earlier values; non-zero later values win
+        assert_eq!(merged.sp500_price, 903.25);
+        assert_eq!(merged.cape, 15.17);
+        assert_eq!(merged.eps, 49.51);
+    }
+
+    fn header_row(cells: &[&str]) -> Vec<String> {
+        cells.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn matching_headers_have_no_drift() {
+        let actual = header_row(&[" Quarter", "DIVIDEND", "eps_actual", "eps_estimated "]);
+
+        assert_eq!(diff_headers("QuarterlyData", &QUARTERLY_COLUMNS, &actual), None);
+    }
+
+    #[test]
+    fn drifted_headers_report_missing_unexpected_and_moved_columns() {
+        let actual = header_row(&["quarter", "eps_actual", "dividend", "eps_forward"]);
+
+        let drift = diff_headers("QuarterlyData", &QUARTERLY_COLUMNS, &actual).unwrap();
+
+        assert_eq!(drift.missing, vec!["eps_estimated".to_string()]);
+        assert_eq!(drift.unexpected, vec!["eps_forward".to_string()]);
+        assert_eq!(drift.moved, vec![("dividend".to_string(), 1, 2), ("eps_actual".to_string(), 2, 1)]);
+        assert_eq!(
+            drift.to_string(),
+            "QuarterlyData: missing [\"eps_estimated\"] unexpected [\"eps_forward\"] \
+             'dividend' in column 3 (expected 2) 'eps_actual' in column 2 (expected 3)"
+        );
+    }
+
+    #[test]
+    fn stk_mkt_csv_skips_bom_prefixed_header_rows() {
+        let csv = "\u{feff}Year,Price,Dividend,Yield,EPS,CAPE,CPI,Return,Cumulative\n\
+                   2020,3756.07,58.28,0.0155,94.13,33.3,260.5,0.184,10.5\n\
+                   \u{feff}Year,Price,Dividend,Yield,EPS,CAPE,CPI,Return,Cumulative\n\
+                   2021,4766.18,60.4,,197.9,38.3,278.8,0.287,13.5\n";
+
+        let records = parse_stk_mkt_csv(csv.as_bytes()).unwrap();
+
+        assert_eq!(records.iter().map(|r| r.year).collect::<Vec<_>>(), vec![2020, 2021]);
+        assert_eq!(records[0].sp500_price, 3756.07);
+        assert_eq!(records[0].cumulative_return, 10.5);
+        assert_eq!(records[1].dividend_yield, 0.0);
+        assert_eq!(records[1].eps, 197.9);
+    }
+
+    #[test]
+    fn stk_mkt_csv_reports_an_unparseable_year() {
+        let err = parse_stk_mkt_csv("Yr,Price\n2020,3756.07\n".as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("Error parsing year 'Yr'"), "{}", err);
+    }
+
+    fn sheets_store(key: &TempFile) -> SheetsStore {
+        SheetsStore::new(SheetsConfig {
+            spreadsheet_id: "test-sheet".to_string(),
+            service_account_json_path: key.path.to_str().unwrap().to_string(),
+        })
+    }
+
+    #[tokio::test]
+    async fn consecutive_auth_token_calls_mint_one_token() {
+        let (url, hits) = mock_server(|hit, _| (200, token_response(&format!("token-{}", hit))));
+        let key = service_account_file(&format!("{}/token", url));
+        let store = sheets_store(&key);
+
+        assert_eq!(store.get_auth_token().await.unwrap(), "token-0");
+        assert_eq!(store.get_auth_token().await.unwrap(), "token-0");
+        let (a, b) = tokio::join!(store.get_auth_token(), store.get_auth_token());
+        assert_eq!((a.unwrap(), b.unwrap()), ("token-0".to_string(), "token-0".to_string()));
+
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn a_token_inside_the_refresh_margin_is_replaced() {
+        let (url, hits) = mock_server(|hit, _| {
+            let body = json!({ "access_token": format!("token-{}", hit), "token_type": "Bearer", "expires_in": 30 });
+            (200, body.to_string())
+        });
+        let key = service_account_file(&format!("{}/token", url));
+        let store = sheets_store(&key);
+
+        assert_eq!(store.get_auth_token().await.unwrap(), "token-0");
+        assert_eq!(store.get_auth_token().await.unwrap(), "token-1");
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn updating_three_years_issues_one_write() {
+        let (store, _key, writes) = mock_sheets_store(json!([
+            ["year", "sp500_price", "eps"],
+            ["2021", "4766.18", "197.87"],
+            ["2022", "3839.5", "187.08"],
+            ["2023", "4769.83", "192.43"],
+        ]));
+        let records: Vec<HistoricalRecord> = (2021..=2023)
+            .map(|year| HistoricalRecord { year, eps: 200.0, ..Default::default() })
+            .collect();
+
+        store.update_historical_records(&records).await.unwrap();
+
+        assert_eq!(writes.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn updating_a_year_without_a_row_writes_nothing() {
+        let (store, _key, writes) = mock_sheets_store(json!([["year", "eps"], ["2023", "192.43"]]));
+        let records = vec![
+            HistoricalRecord { year: 2023, eps: 200.0, ..Default::default() },
+            HistoricalRecord { year: 2024, eps: 210.0, ..Default::default() },
+        ];
+
+        assert!(store.update_historical_records(&records).await.is_err());
+        assert_eq!(writes.load(Ordering::SeqCst), 0);
+    }
+}
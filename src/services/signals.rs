@@ -0,0 +1,63 @@
+// src/services/signals.rs
+use std::sync::Arc;
+use serde::Serialize;
+use anyhow::Result;
+use crate::services::calculations::{NoHistoricalData, DEFAULT_CAGR_WINDOW_YEARS};
+use crate::services::db::DbStore;
+use crate::services::equity::{build_market_data_from_cache, get_market_metrics};
+
+/// Gordon-growth implied long-term equity return and the inputs it was built from
+#[derive(Debug, Serialize)]
+pub struct ImpliedReturn {
+    /// Trailing-twelve-month dividend yield
+    pub dividend_yield: Option<f64>,
+    /// Current (10-year) dividend CAGR
+    pub dividend_growth: Option<f64>,
+    /// `dividend_yield + dividend_growth`, or `None` if either input is missing
+    pub implied_return: Option<f64>,
+}
+
+/// Gordon model: expected return = dividend yield + dividend growth
+pub fn gordon_implied_return(dividend_yield: Option<f64>, dividend_growth: Option<f64>) -> ImpliedReturn {
+    ImpliedReturn {
+        dividend_yield,
+        dividend_growth,
+        implied_return: dividend_yield.zip(dividend_growth).map(|(y, g)| y + g),
+    }
+}
+
+/// Combine the cached TTM dividend yield with the historical dividend CAGR.
+/// Reads the cache only; nothing is fetched.
+pub async fn get_implied_return(db: &Arc<DbStore>) -> Result<ImpliedReturn> {
+    let market_data = build_market_data_from_cache(db).await?;
+
+    let dividend_growth = match get_market_metrics(db, DEFAULT_CAGR_WINDOW_YEARS).await {
+        // The CAGR helpers report 0.0 when there isn't enough data to compute a rate
+        Ok(metrics) => Some(metrics.current_dividend_cagr).filter(|cagr| *cagr != 0.0),
+        Err(e) if e.downcast_ref::<NoHistoricalData>().is_some() => None,
+        Err(e) => return Err(e),
+    };
+
+    Ok(gordon_implied_return(market_data.ttm_dividend_yield, dividend_growth))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn implied_return_is_yield_plus_growth() {
+        let implied = gordon_implied_return(Some(0.015), Some(0.055));
+
+        assert_eq!(implied.dividend_yield, Some(0.015));
+        assert_eq!(implied.dividend_growth, Some(0.055));
+        assert!((implied.implied_return.unwrap() - 0.07).abs() < 1e-12);
+    }
+
+    #[test]
+    fn implied_return_is_none_when_an_input_is_missing() {
+        assert_eq!(gordon_implied_return(None, Some(0.055)).implied_return, None);
+        assert_eq!(gordon_implied_return(Some(0.015), None).implied_return, None);
+        assert_eq!(gordon_implied_return(None, None).implied_return, None);
+    }
+}
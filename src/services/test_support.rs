@@ -0,0 +1,202 @@
+// src/services/test_support.rs
+// Shared builders for the unit tests
+use chrono::DateTime;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use warp::Filter;
+use crate::models::{MarketCache, Timestamps};
+use crate::services::db::{CacheWriteMode, DbStore};
+use crate::services::local_store::LocalSnapshot;
+use crate::services::sheets::{RawMarketCache, SheetsConfig, SheetsStore};
+
+/// A market cache with every timestamp at the Unix epoch and every value empty
+pub fn market_cache() -> MarketCache {
+    MarketCache {
+        timestamps: Timestamps {
+            yahoo_price: DateTime::UNIX_EPOCH,
+            ycharts_data: DateTime::UNIX_EPOCH,
+            treasury_data: DateTime::UNIX_EPOCH,
+            bls_data: DateTime::UNIX_EPOCH,
+            last_market_price_update: DateTime::UNIX_EPOCH,
+            gdp_data: DateTime::UNIX_EPOCH,
+        },
+        daily_close_sp500_price: 0.0,
+        current_sp500_price: 0.0,
+        quarterly_dividends: BTreeMap::new(),
+        eps_actual: BTreeMap::new(),
+        eps_estimated: BTreeMap::new(),
+        current_cape: 0.0,
+        cape_period: String::new(),
+        tips_yield_20y: None,
+        bond_yield_20y: None,
+        tbill_yield: 0.0,
+        inflation_rate: 0.0,
+        latest_monthly_return: 0.0,
+        latest_month: String::new(),
+        last_year_close_captured: 0,
+        gdp: 0.0,
+        gdp_period: String::new(),
+        price_source: String::new(),
+    }
+}
+
+/// The stored form of `market_cache()`
+pub fn raw_market_cache() -> RawMarketCache {
+    let epoch = DateTime::UNIX_EPOCH.to_rfc3339();
+    RawMarketCache {
+        timestamp_yahoo: epoch.clone(),
+        timestamp_ycharts: epoch.clone(),
+        timestamp_treasury: epoch.clone(),
+        timestamp_bls: epoch.clone(),
+        daily_close_sp500_price: 0.0,
+        current_sp500_price: 0.0,
+        current_cape: 0.0,
+        cape_period: String::new(),
+        tips_yield_20y: None,
+        bond_yield_20y: None,
+        tbill_yield: 0.0,
+        inflation_rate: 0.0,
+        latest_monthly_return: 0.0,
+        latest_month: String::new(),
+        last_year_close_captured: 0,
+        timestamp_market_price: epoch.clone(),
+        gdp: 0.0,
+        gdp_period: String::new(),
+        timestamp_gdp: epoch,
+        price_source: String::new(),
+    }
+}
+
+/// A minimal YCharts indicator page with `stat` in the key stat element
+pub fn ycharts_page(stat: &str) -> String {
+    format!(
+        "<html><body><div class=\"key-stats\"><div class=\"key-stat-title\">{}</div></div></body></html>",
+        stat
+    )
+}
+
+static NEXT_STORE_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A `DbStore` over a local JSON store in the temp directory; the file is removed on drop
+pub struct LocalDb {
+    pub db: Arc<DbStore>,
+    pub path: PathBuf,
+}
+
+impl Drop for LocalDb {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Write `snapshot` to a fresh local store file and open a `DbStore` on it
+pub fn local_db(snapshot: &LocalSnapshot) -> LocalDb {
+    local_db_with_mode(snapshot, CacheWriteMode::WriteThrough)
+}
+
+/// `local_db` with an explicit cache write mode instead of the one from the environment
+pub fn local_db_with_mode(snapshot: &LocalSnapshot, write_mode: CacheWriteMode) -> LocalDb {
+    let path = std::env::temp_dir().join(format!(
+        "macro_dashboard_test_{}_{}.json",
+        std::process::id(),
+        NEXT_STORE_ID.fetch_add(1, Ordering::SeqCst)
+    ));
+    std::fs::write(&path, serde_json::to_vec(snapshot).unwrap()).unwrap();
+    let mut db = DbStore::new_local(path.to_str().unwrap());
+    db.write_mode = write_mode;
+    LocalDb { db: Arc::new(db), path }
+}
+
+/// Held by tests that set process environment variables, since the test harness runs tests
+/// on parallel threads
+pub static ENV_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// A local HTTP server answering every request with `respond(hit, path)`, where `hit` counts
+/// requests from 0. Returns its base URL and the hit counter.
+pub fn mock_server<R>(respond: R) -> (String, Arc<AtomicUsize>)
+where
+    R: Fn(usize, &str) -> (u16, String) + Clone + Send + Sync + 'static,
+{
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counter = hits.clone();
+    let route = warp::any()
+        .and(warp::path::full())
+        .map(move |path: warp::path::FullPath| {
+            let hit = counter.fetch_add(1, Ordering::SeqCst);
+            let (status, body) = respond(hit, path.as_str());
+            warp::reply::with_status(body, warp::http::StatusCode::from_u16(status).unwrap())
+        });
+    let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    (format!("http://{}", addr), hits)
+}
+
+/// A file in the temp directory, removed on drop
+pub struct TempFile {
+    pub path: PathBuf,
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// A service account key file signing with the test RSA key and exchanging tokens at `token_uri`
+pub fn service_account_file(token_uri: &str) -> TempFile {
+    let key = serde_json::json!({
+        "type": "service_account",
+        "project_id": "test-project",
+        "private_key_id": "test-key",
+        "private_key": include_str!("../../tests/fixtures/test_rsa_key.pem"),
+        "client_email": "dashboard@test-project.iam.gserviceaccount.com",
+        "client_id": "1",
+        "auth_uri": "https://accounts.google.com/o/oauth2/auth",
+        "token_uri": token_uri,
+        "auth_provider_x509_cert_url": "https://www.googleapis.com/oauth2/v1/certs",
+        "client_x509_cert_url": "https://www.googleapis.com/robot/v1/metadata/x509/dashboard",
+    });
+    let path = std::env::temp_dir().join(format!(
+        "macro_dashboard_test_key_{}_{}.json",
+        std::process::id(),
+        NEXT_STORE_ID.fetch_add(1, Ordering::SeqCst)
+    ));
+    std::fs::write(&path, serde_json::to_vec(&key).unwrap()).unwrap();
+    TempFile { path }
+}
+
+/// A token endpoint reply granting `token` for an hour
+pub fn token_response(token: &str) -> String {
+    serde_json::json!({ "access_token": token, "token_type": "Bearer", "expires_in": 3600 }).to_string()
+}
+
+/// A Sheets store backed by a local stand-in for the Sheets API whose HistoricalData sheet holds
+/// `rows` (header first). The counter is the number of write requests the stand-in received; the
+/// key file must outlive the store.
+pub fn mock_sheets_store(rows: serde_json::Value) -> (SheetsStore, TempFile, Arc<AtomicUsize>) {
+    let writes = Arc::new(AtomicUsize::new(0));
+    let counter = writes.clone();
+    let header = serde_json::json!({ "values": rows.as_array().and_then(|rows| rows.get(..1)) }).to_string();
+    let values = serde_json::json!({ "values": rows }).to_string();
+    let (url, _) = mock_server(move |_, path| {
+        if path == "/token" {
+            (200, token_response("sheets-token"))
+        } else if path.ends_with("!A1:Z1") {
+            (200, header.clone())
+        } else if path.ends_with("!A1:Z") {
+            (200, values.clone())
+        } else {
+            counter.fetch_add(1, Ordering::SeqCst);
+            (200, "{}".to_string())
+        }
+    });
+    let key = service_account_file(&format!("{}/token", url));
+    let store = SheetsStore::new(SheetsConfig {
+        spreadsheet_id: "test-sheet".to_string(),
+        service_account_json_path: key.path.to_str().unwrap().to_string(),
+    })
+    .with_api_base(&url);
+    (store, key, writes)
+}
@@ -0,0 +1,249 @@
+// src/services/treasury_common.rs
+// Pieces shared by the T-bill (`treasury`) and long-term (`treasury_long`) fetchers
+use chrono::NaiveDate;
+use csv::Reader;
+use log::{info, warn, error};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, ACCEPT_LANGUAGE, CONNECTION, USER_AGENT};
+use reqwest::Client;
+use std::fmt;
+use std::sync::OnceLock;
+use std::time::Duration;
+use crate::services::calculations::{ingestion_decimals, round_to};
+use crate::services::error::ServiceError;
+use crate::services::http::{HttpFetcher, ReqwestFetcher};
+
+pub type Result<T> = std::result::Result<T, ServiceError>;
+
+/// Round a fetched yield to `YIELD_DECIMALS` places (default 2) before it is cached
+pub(crate) fn round_yield(rate: f64) -> f64 {
+    round_to(rate, ingestion_decimals("YIELD_DECIMALS"))
+}
+
+/// Header of the Treasury CSV date column, overridable with `TREASURY_DATE_COLUMN`
+pub(crate) fn treasury_date_column() -> String {
+    std::env::var("TREASURY_DATE_COLUMN").unwrap_or_else(|_| "Date".to_string())
+}
+
+/// Why a Treasury rates CSV didn't yield a rate
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TreasuryCsvError {
+    /// The CSV couldn't be read at all
+    Malformed(String),
+    /// The target column isn't in the header, e.g. the wrong `type` layout was returned
+    MissingColumn { column: String, headers: Vec<String> },
+    /// The column exists but no row has a value yet (every cell is `N/A` or blank)
+    NoPublishedData { column: String, rows: usize },
+    /// The newest published cell isn't a number
+    InvalidValue { column: String, date: String, value: String },
+}
+
+impl fmt::Display for TreasuryCsvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TreasuryCsvError::Malformed(msg) => write!(f, "Malformed CSV: {}", msg),
+            TreasuryCsvError::MissingColumn { column, headers } => {
+                write!(f, "No '{}' column. Headers found: {:?}", column, headers)
+            }
+            TreasuryCsvError::NoPublishedData { column, rows } => {
+                write!(f, "No published data yet for '{}' ({} rows, all N/A or blank)", column, rows)
+            }
+            TreasuryCsvError::InvalidValue { column, date, value } => {
+                write!(f, "Failed to parse rate '{}' for '{}' on {}", value, column, date)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TreasuryCsvError {}
+
+fn is_published(cell: &str) -> bool {
+    !(cell.is_empty() || cell.eq_ignore_ascii_case("N/A"))
+}
+
+/// Find the newest row of a Treasury rates CSV (dates in `MM/DD/YYYY`) that has a value in
+/// `column_name` and parse it. Rows where that column is `N/A` or blank are skipped.
+/// Treasury currently lists newest first, but rows are compared by date so ordering doesn't matter.
+/// If the date column is missing, the first published row is used.
+pub(crate) fn parse_latest_treasury_rate(
+    csv_text: &str,
+    column_name: &str,
+    date_column: &str,
+) -> std::result::Result<(String, f64), TreasuryCsvError> {
+    let mut rdr = Reader::from_reader(csv_text.as_bytes());
+    let headers = rdr.headers().map_err(|e| TreasuryCsvError::Malformed(e.to_string()))?.clone();
+    let col_idx = headers
+        .iter()
+        .position(|h| h.trim() == column_name)
+        .ok_or_else(|| TreasuryCsvError::MissingColumn {
+            column: column_name.to_string(),
+            headers: headers.iter().map(|h| h.trim().to_string()).collect(),
+        })?;
+    let date_idx = headers.iter().position(|h| h.trim() == date_column);
+    if date_idx.is_none() {
+        warn!("No '{}' date column, assuming the first row is the latest. Headers found: {:?}", date_column, headers);
+    }
+
+    let mut rows = 0;
+    let mut latest: Option<(Option<NaiveDate>, String)> = None;
+    for record_result in rdr.records() {
+        let row = record_result.map_err(|e| TreasuryCsvError::Malformed(e.to_string()))?;
+        rows += 1;
+
+        let cell = row.get(col_idx).map(str::trim).unwrap_or("");
+        if !is_published(cell) {
+            continue;
+        }
+
+        let Some(date_idx) = date_idx else {
+            latest = Some((None, cell.to_string()));
+            break;
+        };
+
+        let date = row.get(date_idx)
+            .and_then(|d| NaiveDate::parse_from_str(d.trim(), "%m/%d/%Y").ok());
+        if date.is_none() {
+            warn!("Skipping row with unparseable '{}' value: {:?}", date_column, row);
+            continue;
+        }
+        if latest.as_ref().is_none_or(|(latest_date, _)| date > *latest_date) {
+            latest = Some((date, cell.to_string()));
+        }
+    }
+
+    let (date, cell) = latest.ok_or_else(|| TreasuryCsvError::NoPublishedData {
+        column: column_name.to_string(),
+        rows,
+    })?;
+    let date = date.map_or_else(|| "first row".to_string(), |d| d.to_string());
+
+    match cell.parse::<f64>() {
+        Ok(rate) => Ok((date, rate)),
+        Err(_) => Err(TreasuryCsvError::InvalidValue { column: column_name.to_string(), date, value: cell }),
+    }
+}
+
+/// One client for every Treasury CSV request, so refreshing all three yields in the same
+/// request reuses the TLS connection instead of opening one per fetch
+fn treasury_client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, HeaderValue::from_static("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36"));
+        headers.insert(ACCEPT, HeaderValue::from_static("text/csv,application/csv;q=0.9,*/*;q=0.8"));
+        headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_static("en-US,en;q=0.9"));
+        headers.insert(CONNECTION, HeaderValue::from_static("keep-alive"));
+        headers.insert("Sec-Fetch-Dest", HeaderValue::from_static("empty"));
+        headers.insert("Sec-Fetch-Mode", HeaderValue::from_static("cors"));
+        headers.insert("Sec-Fetch-Site", HeaderValue::from_static("cross-site"));
+
+        Client::builder()
+            .timeout(Duration::from_secs(30))
+            .default_headers(headers)
+            .build()
+            .expect("Failed to build Treasury HTTP client")
+    })
+}
+
+/// Fetch a Treasury rates CSV and parse the newest published value of `column_name`
+pub(crate) async fn fetch_treasury_csv_rate(
+    url: &str,
+    column_name: &str,
+    service_context: &str,
+) -> Result<f64> {
+    let fetcher = ReqwestFetcher::new(treasury_client().clone());
+    fetch_treasury_csv_rate_with(&fetcher, url, column_name, service_context).await
+}
+
+/// `fetch_treasury_csv_rate` against any `HttpFetcher`, e.g. a `MockFetcher` serving a saved CSV
+pub(crate) async fn fetch_treasury_csv_rate_with(
+    fetcher: &impl HttpFetcher,
+    url: &str,
+    column_name: &str,
+    service_context: &str,
+) -> Result<f64> {
+    info!("Fetching {} CSV from URL: {}", service_context, url);
+
+    let csv_text = fetcher.get_text(url).await.map_err(|e| {
+        let err_msg = format!("Request for {} failed: {} for URL: {}", service_context, e, url);
+        error!("{}", err_msg);
+        ServiceError::Http(err_msg)
+    })?;
+    if csv_text.trim().is_empty() {
+        let err_msg = format!("Received empty CSV data for {} from URL: {}", service_context, url);
+        warn!("{}", err_msg);
+        return Err(ServiceError::Parse(err_msg));
+    }
+
+    match parse_latest_treasury_rate(&csv_text, column_name, &treasury_date_column()) {
+        Ok((date, rate)) => {
+            info!("Found {} ({}) for {}: {}", service_context, column_name, date, rate);
+            Ok(rate)
+        }
+        Err(e) => {
+            let err_msg = format!("{} in {} CSV from URL: {}", e, service_context, url);
+            // Nothing published yet is expected early in the year, not a layout problem
+            if matches!(e, TreasuryCsvError::NoPublishedData { .. }) {
+                warn!("{}", err_msg);
+            } else {
+                error!("{}", err_msg);
+            }
+            Err(ServiceError::Parse(err_msg))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::test_support::ENV_LOCK;
+
+    #[test]
+    fn newest_row_is_selected_when_dates_ascend() {
+        let csv = "Date,20 YR,30 YR\n\
+                   01/02/2025,4.86,4.79\n\
+                   01/03/2025,4.88,4.82\n\
+                   01/06/2025,4.91,4.85\n";
+
+        let (date, rate) = parse_latest_treasury_rate(csv, "20 YR", "Date").unwrap();
+
+        assert_eq!(date, "2025-01-06");
+        assert_eq!(rate, 4.91);
+    }
+
+    #[test]
+    fn yields_are_rounded_to_two_places_by_default() {
+        let _env = ENV_LOCK.blocking_lock();
+
+        assert_eq!(round_yield(4.2501), 4.25);
+        assert_eq!(round_yield(4.2499), round_yield(4.25));
+        assert_eq!(round_yield(4.256), 4.26);
+    }
+
+    #[test]
+    fn missing_column_reports_the_headers_found() {
+        let csv = "Date,4 WEEKS BANK DISCOUNT,4 WEEKS COUPON EQUIVALENT\n01/06/2025,4.23,4.31\n";
+
+        let err = parse_latest_treasury_rate(csv, "20 YR", "Date").unwrap_err();
+
+        assert_eq!(err, TreasuryCsvError::MissingColumn {
+            column: "20 YR".to_string(),
+            headers: vec![
+                "Date".to_string(),
+                "4 WEEKS BANK DISCOUNT".to_string(),
+                "4 WEEKS COUPON EQUIVALENT".to_string(),
+            ],
+        });
+    }
+
+    #[test]
+    fn a_column_of_only_na_is_unpublished_rather_than_malformed() {
+        let csv = "Date,20 YR\n01/03/2025,N/A\n01/02/2025,\n";
+
+        let err = parse_latest_treasury_rate(csv, "20 YR", "Date").unwrap_err();
+
+        assert_eq!(err, TreasuryCsvError::NoPublishedData { column: "20 YR".to_string(), rows: 2 });
+
+        let err = parse_latest_treasury_rate("Date,20 YR\n01/03/2025,abc\n", "20 YR", "Date").unwrap_err();
+        assert!(matches!(err, TreasuryCsvError::InvalidValue { .. }), "{:?}", err);
+    }
+}